@@ -2,16 +2,162 @@
 
 extern crate test;
 
+use actix_web::{web, App, HttpResponse, HttpServer};
 use docker_wrapper::*;
+use mimir::rubber::{IndexSettings, PlacesSearch, Rubber};
+use rs_es::query::Query;
+use serde::Deserialize;
 use tools::{BragiHandler, ElasticSearchWrapper};
 
+const AUTOCOMPLETE_QUERY: &str = "/autocomplete?q=Parking vélo Saint-Martin&type[]=poi";
+
+// Number of pois indexed for the benches
+const NB_POIS: usize = 1000;
+
+// Number of queries sent at the same time to bragi
+const NB_CONCURRENT_QUERIES: usize = 64;
+
+// Number of workers of the servers receiving the concurrent queries
+const NB_WORKERS: usize = 2;
+
+/// Index the pois searched by the benches, the names of all of them match the query
+fn load_fixture(es_wrapper: &mut ElasticSearchWrapper<'_>) {
+    let pois = (0..NB_POIS).map(|i| {
+        let coord = mimir::Coord::new(2.35 + i as f64 * 1e-4, 48.85);
+        let name = format!("Parking vélo Saint-Martin {}", i);
+        mimir::Poi {
+            id: format!("poi:bench:{}", i),
+            label: format!("{} (Paris)", name),
+            name,
+            coord,
+            approx_coord: Some(coord.into()),
+            poi_type: mimir::PoiType {
+                id: "poi_type:amenity:bicycle_parking".to_string(),
+                name: "Parking vélo".to_string(),
+            },
+            ..Default::default()
+        }
+    });
+    let index_settings = IndexSettings {
+        nb_shards: 1,
+        nb_replicas: 0,
+    };
+    es_wrapper
+        .rubber
+        .public_index("bench", &index_settings, pois)
+        .unwrap();
+    es_wrapper.refresh();
+}
+
+/// An address with a free port, to run a server of the benches
+fn free_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Send `NB_CONCURRENT_QUERIES` autocomplete queries at the same time to the server
+/// listening on the address, and wait for all the responses
+fn bench_concurrent_queries(b: &mut test::Bencher, address: &str) {
+    let url = format!("http://{}{}", address, AUTOCOMPLETE_QUERY);
+    let client = reqwest::blocking::Client::new();
+    // wait for the server to be up
+    while client.get(&url).send().is_err() {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    b.iter(|| {
+        let queries: Vec<_> = (0..NB_CONCURRENT_QUERIES)
+            .map(|_| {
+                let client = client.clone();
+                let url = url.clone();
+                std::thread::spawn(move || {
+                    let response = client.get(&url).send().unwrap();
+                    assert!(response.status().is_success());
+                })
+            })
+            .collect();
+        for query in queries {
+            query.join().unwrap();
+        }
+    });
+}
+
 #[bench]
 fn bench_new(b: &mut test::Bencher) {
     let docker_wrapper = DockerWrapper::new().unwrap();
-    let es_wrapper = ElasticSearchWrapper::new(&docker_wrapper);
+    let mut es_wrapper = ElasticSearchWrapper::new(&docker_wrapper);
+    load_fixture(&mut es_wrapper);
     let mut bragi = BragiHandler::new(format!("{}/munin", es_wrapper.host()));
     b.iter(|| {
-        let _response =
-            bragi.get("/autocomplete?q=Parking vélo Saint-Martin&pt_dataset[]=dataset1&type[]=poi");
+        let _response = bragi.get(AUTOCOMPLETE_QUERY);
     });
 }
+
+/// Throughput of bragi when a lot of queries are received at the same time:
+/// the elasticsearch queries are asynchronous, so a few workers should be able to handle them.
+/// To be compared with `bench_concurrent_blocking_autocomplete`.
+#[bench]
+fn bench_concurrent_autocomplete(b: &mut test::Bencher) {
+    let docker_wrapper = DockerWrapper::new().unwrap();
+    let mut es_wrapper = ElasticSearchWrapper::new(&docker_wrapper);
+    load_fixture(&mut es_wrapper);
+    let address = free_address();
+    let args = bragi::Args {
+        bind: address.clone(),
+        connection_string: format!("{}/munin", es_wrapper.host()),
+        nb_threads: NB_WORKERS,
+        ..Default::default()
+    };
+    std::thread::spawn(move || bragi::server::run_server(&args).unwrap());
+
+    bench_concurrent_queries(b, &address);
+}
+
+#[derive(Deserialize)]
+struct BlockingParams {
+    q: String,
+}
+
+/// The autocomplete as it was served before the asynchronous elasticsearch queries:
+/// the worker is blocked by the `Rubber` during the whole search
+fn blocking_autocomplete(
+    rubber: web::Data<Rubber>,
+    params: web::Query<BlockingParams>,
+) -> HttpResponse {
+    let query = Query::build_match("full_label.prefix", params.q.clone())
+        .with_operator("and")
+        .build();
+    let indexes = ["munin_poi"];
+    let search = PlacesSearch::new(&indexes, &query).with_size(10);
+    match rubber.search_places(&search, None) {
+        Ok(places) => HttpResponse::Ok().json(places),
+        Err(e) => HttpResponse::ServiceUnavailable().body(e.to_string()),
+    }
+}
+
+/// Throughput of the previous blocking path, with the same number of workers as
+/// `bench_concurrent_autocomplete`: each worker waits for elasticsearch,
+/// so the queries are handled `NB_WORKERS` at a time.
+#[bench]
+fn bench_concurrent_blocking_autocomplete(b: &mut test::Bencher) {
+    let docker_wrapper = DockerWrapper::new().unwrap();
+    let mut es_wrapper = ElasticSearchWrapper::new(&docker_wrapper);
+    load_fixture(&mut es_wrapper);
+    let address = free_address();
+    let es_host = es_wrapper.host();
+    let bind = address.clone();
+    std::thread::spawn(move || {
+        HttpServer::new(move || {
+            App::new()
+                .data(Rubber::new(&es_host))
+                .route("/autocomplete", web::get().to(blocking_autocomplete))
+        })
+        .bind(&bind)
+        .unwrap()
+        .workers(NB_WORKERS)
+        .run()
+        .unwrap()
+    });
+
+    bench_concurrent_queries(b, &address);
+}
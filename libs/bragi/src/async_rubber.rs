@@ -0,0 +1,190 @@
+//! Asynchronous access to elasticsearch
+//!
+//! `mimir::rubber::Rubber` uses blocking http clients, so the actix workers are blocked during
+//! all the elasticsearch queries. `AsyncRubber` sends the searches with the actix http client,
+//! the workers can thus serve other requests while waiting for elasticsearch.
+
+use crate::model::BragiError;
+use actix_web::client::Client;
+use actix_web::http::StatusCode;
+use futures::future::{self, Either};
+use futures::Future;
+use mimir::objects::{Coord, Place};
+use mimir::provenance::{read_provenances, Provenance};
+use mimir::rubber::{read_search_places, PlacesSearch};
+use serde_json::Value;
+use slog_scope::debug;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use url::Url;
+
+// The elasticsearch responses can be quite big (with the explanations or some admins)
+// and the default limit of the actix client is 256kB
+const MAX_RESPONSE_SIZE: usize = 64 * 1024 * 1024;
+
+thread_local! {
+    // The actix client cannot be shared between threads,
+    // so each worker has its own client (and its own connection pool)
+    static CLIENT: Client = Client::build().disable_timeout().finish();
+}
+
+#[derive(Clone, Debug)]
+pub struct AsyncRubber {
+    base_url: Url,
+    // Note: The timeout is used for the http client AND for the ES internal query
+    pub timeout: Option<Duration>,
+}
+
+impl AsyncRubber {
+    pub fn new(cnx: &str, timeout: Option<Duration>) -> Result<Self, String> {
+        Ok(AsyncRubber {
+            base_url: Url::parse(cnx)
+                .map_err(|e| format!("invalid elasticsearch url `{}`: {}", cnx, e))?,
+            timeout,
+        })
+    }
+
+    /// Build a rubber with the timeout of a query.
+    /// The timeout is bounded by the timeout of this rubber.
+    pub fn with_timeout(&self, timeout: Option<Duration>) -> Self {
+        let timeout = match (timeout, self.timeout) {
+            (Some(timeout), Some(max_timeout)) => Some(timeout.min(max_timeout)),
+            (timeout, max_timeout) => timeout.or(max_timeout),
+        };
+        AsyncRubber {
+            base_url: self.base_url.clone(),
+            timeout,
+        }
+    }
+
+    /// Prepare a search to be sent later
+    pub fn prepare(&self, search: &PlacesSearch<'_>) -> Result<PreparedSearch, BragiError> {
+        let url = self
+            .base_url
+            .join(&search.path())
+            .map_err(|e| BragiError::EsQuery(format!("invalid search url: {}", e)))?;
+        Ok(PreparedSearch {
+            url: url.to_string(),
+            body: search.body(self.timeout).to_string(),
+            opaque_id: search.opaque_id().map(str::to_owned),
+            timeout: self.timeout,
+        })
+    }
+
+    /// Search some places, the coord is used to compute the distance of the places to it
+    pub fn search_places(
+        &self,
+        search: &PlacesSearch<'_>,
+        coord: Option<Coord>,
+    ) -> impl Future<Item = Vec<Place>, Error = BragiError> {
+        match self.prepare(search) {
+            Ok(search) => Either::A(search.send(coord)),
+            Err(e) => Either::B(future::err(e)),
+        }
+    }
+
+    /// Send a GET request to elasticsearch and read the json response
+    pub fn get_json(&self, path: &str) -> impl Future<Item = Value, Error = BragiError> {
+        let url = match self.base_url.join(path) {
+            Ok(url) => url,
+            Err(e) => {
+                return Either::B(future::err(BragiError::EsQuery(format!(
+                    "invalid url `{}`: {}",
+                    path, e
+                ))))
+            }
+        };
+        debug!("doing a get on {}", url);
+        let mut request = CLIENT.with(|client| client.get(url.as_str()));
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        Either::A(
+            request
+                .send()
+                .map_err(|e| BragiError::EsUnavailable(e.to_string()))
+                .and_then(|mut response| {
+                    let status = response.status();
+                    response
+                        .body()
+                        .limit(MAX_RESPONSE_SIZE)
+                        .map_err(|e| BragiError::EsUnavailable(e.to_string()))
+                        .and_then(move |body| {
+                            if status.is_success() {
+                                serde_json::from_slice(&body).map_err(|e| {
+                                    BragiError::EsQuery(format!("invalid response: {}", e))
+                                })
+                            } else {
+                                let msg = format!("{}: {}", status, String::from_utf8_lossy(&body));
+                                if status.is_server_error()
+                                    || status == StatusCode::TOO_MANY_REQUESTS
+                                {
+                                    Err(BragiError::EsUnavailable(msg))
+                                } else {
+                                    Err(BragiError::EsQuery(msg))
+                                }
+                            }
+                        })
+                }),
+        )
+    }
+
+    /// The provenances of the indexes aliased by `munin`,
+    /// stored in the `_meta` of their mapping by the import tools
+    pub fn get_provenances(
+        &self,
+    ) -> impl Future<Item = BTreeMap<String, Provenance>, Error = BragiError> {
+        self.get_json("munin/_mapping?filter_path=*.mappings.*._meta")
+            .map(|mappings| read_provenances(&mappings))
+    }
+}
+
+/// A search ready to be sent to elasticsearch
+///
+/// It does not borrow anything, so it can be sent from a future
+#[derive(Debug)]
+pub struct PreparedSearch {
+    url: String,
+    body: String,
+    opaque_id: Option<String>,
+    timeout: Option<Duration>,
+}
+
+impl PreparedSearch {
+    pub fn send(self, coord: Option<Coord>) -> impl Future<Item = Vec<Place>, Error = BragiError> {
+        debug!("doing a search on {} with {}", self.url, self.body);
+        let mut request = CLIENT
+            .with(|client| client.post(&self.url))
+            .content_type("application/json");
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        if let Some(opaque_id) = &self.opaque_id {
+            request = request.header("X-Opaque-Id", opaque_id.as_str());
+        }
+        request
+            .send_body(self.body)
+            .map_err(|e| BragiError::EsUnavailable(e.to_string()))
+            .and_then(move |mut response| {
+                let status = response.status();
+                response
+                    .body()
+                    .limit(MAX_RESPONSE_SIZE)
+                    .map_err(|e| BragiError::EsUnavailable(e.to_string()))
+                    .and_then(move |body| {
+                        if status.is_success() {
+                            read_search_places(&body, coord.as_ref()).map_err(|e| {
+                                BragiError::EsQuery(format!("invalid search response: {}", e))
+                            })
+                        } else {
+                            let msg = format!("{}: {}", status, String::from_utf8_lossy(&body));
+                            if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                                Err(BragiError::EsUnavailable(msg))
+                            } else {
+                                Err(BragiError::EsQuery(msg))
+                            }
+                        }
+                    })
+            })
+    }
+}
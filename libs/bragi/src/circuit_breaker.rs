@@ -0,0 +1,316 @@
+//! Circuit breaker around the elasticsearch searches
+//!
+//! When elasticsearch is overloaded, all the workers would block on their queries until the timeout.
+//! After `failure_threshold` consecutive failures, the circuit is opened and the queries are
+//! rejected right away (with a 503), then after `retry_delay` one query is let through to probe
+//! elasticsearch: the circuit is closed if it succeeds, and opened again if it fails.
+//! The success of a query sent before the opening does not close the circuit.
+//! If the probe gives no verdict (its future has been dropped, or it failed for a reason unrelated
+//! to elasticsearch), another query is let through after `retry_delay`.
+
+use crate::model::BragiError;
+use futures::Future;
+use prometheus::{opts, register_int_counter, register_int_gauge, IntCounter, IntGauge};
+use serde::{Deserialize, Serialize};
+use slog_scope::{info, warn};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+lazy_static::lazy_static! {
+    static ref CIRCUIT_STATE_GAUGE: IntGauge = register_int_gauge!(
+        "bragi_circuit_breaker_state",
+        "State of the circuit breaker toward elasticsearch (0: closed, 1: half open, 2: open)."
+    )
+    .unwrap();
+    static ref CIRCUIT_OPENING_COUNTER: IntCounter = register_int_counter!(
+        "bragi_circuit_breaker_openings_total",
+        "Number of times the circuit breaker toward elasticsearch has been opened."
+    )
+    .unwrap();
+    static ref CIRCUIT_REJECTION_COUNTER: IntCounter = register_int_counter!(
+        "bragi_circuit_breaker_rejections_total",
+        "Number of queries rejected because the circuit breaker toward elasticsearch was open."
+    )
+    .unwrap();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Everything is fine, the queries are sent to elasticsearch
+    Closed,
+    /// A query is probing elasticsearch, the other ones are rejected
+    HalfOpen,
+    /// The queries are rejected
+    Open,
+}
+
+impl CircuitState {
+    fn as_gauge_value(self) -> i64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::HalfOpen => 1,
+            CircuitState::Open => 2,
+        }
+    }
+}
+
+/// State of the circuit breaker, displayed in the `/status`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CircuitBreakerStatus {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Instant,
+    probe_started_at: Instant,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    // 0 means that the circuit breaker is disabled
+    failure_threshold: u32,
+    retry_delay: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, retry_delay: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold,
+            retry_delay,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: Instant::now(),
+                probe_started_at: Instant::now(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // the state is always coherent, even if another thread panicked while holding the lock
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_state(inner: &mut Inner, state: CircuitState) {
+        inner.state = state;
+        CIRCUIT_STATE_GAUGE.set(state.as_gauge_value());
+    }
+
+    /// Check if a query can be sent to elasticsearch.
+    /// If not, return the duration after which the client can retry.
+    pub fn acquire(&self) -> Result<(), Duration> {
+        if self.failure_threshold == 0 {
+            return Ok(());
+        }
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::Closed => Ok(()),
+            // a probe is already in progress, but we do not wait forever for its verdict
+            CircuitState::HalfOpen => {
+                let elapsed = inner.probe_started_at.elapsed();
+                if elapsed >= self.retry_delay {
+                    info!("no verdict from the previous probe, probing elasticsearch again");
+                    inner.probe_started_at = Instant::now();
+                    Ok(())
+                } else {
+                    Err(self.retry_delay - elapsed)
+                }
+            }
+            CircuitState::Open => {
+                let elapsed = inner.opened_at.elapsed();
+                if elapsed >= self.retry_delay {
+                    info!("circuit breaker half opened, probing elasticsearch");
+                    inner.probe_started_at = Instant::now();
+                    Self::set_state(&mut inner, CircuitState::HalfOpen);
+                    Ok(())
+                } else {
+                    Err(self.retry_delay - elapsed)
+                }
+            }
+        }
+    }
+
+    pub fn on_success(&self) {
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::Closed => inner.consecutive_failures = 0,
+            CircuitState::HalfOpen => {
+                inner.consecutive_failures = 0;
+                info!("circuit breaker closed, elasticsearch is available");
+                Self::set_state(&mut inner, CircuitState::Closed);
+            }
+            // a slow query sent before the circuit was opened does not close it,
+            // only a probe sent after `retry_delay` can
+            CircuitState::Open => {}
+        }
+    }
+
+    pub fn on_failure(&self) {
+        let mut inner = self.lock();
+        inner.consecutive_failures += 1;
+        let should_open = match inner.state {
+            CircuitState::Closed => {
+                self.failure_threshold != 0 && inner.consecutive_failures >= self.failure_threshold
+            }
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            warn!(
+                "circuit breaker opened after {} consecutive failures",
+                inner.consecutive_failures
+            );
+            inner.opened_at = Instant::now();
+            CIRCUIT_OPENING_COUNTER.inc();
+            Self::set_state(&mut inner, CircuitState::Open);
+        }
+    }
+
+    /// Elasticsearch is under pressure if the last queries failed
+    pub fn is_under_pressure(&self) -> bool {
+        let inner = self.lock();
+        inner.state != CircuitState::Closed || inner.consecutive_failures > 0
+    }
+
+    pub fn status(&self) -> CircuitBreakerStatus {
+        let inner = self.lock();
+        CircuitBreakerStatus {
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+        }
+    }
+
+    /// Check if a query can be sent to elasticsearch, if not the query is rejected
+    pub fn check(&self) -> Result<(), BragiError> {
+        self.acquire().map_err(|retry_after| {
+            CIRCUIT_REJECTION_COUNTER.inc();
+            BragiError::CircuitOpen(retry_after)
+        })
+    }
+
+    /// Record the result of a query toward elasticsearch (that has been allowed by `check`).
+    /// Only the elasticsearch errors are considered as failures, and the other errors
+    /// (invalid parameters, objects not found, ...) say nothing about elasticsearch, so they
+    /// are ignored.
+    pub fn watch<F>(
+        breaker: Arc<CircuitBreaker>,
+        query: F,
+    ) -> impl Future<Item = F::Item, Error = BragiError>
+    where
+        F: Future<Error = BragiError>,
+    {
+        query.then(move |res| {
+            match &res {
+                Ok(_) => breaker.on_success(),
+                Err(e) if e.is_es_failure() => breaker.on_failure(),
+                Err(_) => {}
+            }
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        breaker.on_failure();
+        breaker.on_failure();
+        breaker.on_success();
+        breaker.on_failure();
+        breaker.on_failure();
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.is_under_pressure());
+        breaker.on_failure();
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        assert!(breaker.acquire().is_err());
+    }
+
+    #[test]
+    fn probes_after_retry_delay() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.on_failure();
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        assert!(breaker.acquire().is_err());
+        std::thread::sleep(Duration::from_millis(20));
+        // the first query is a probe, the others are rejected while it runs
+        assert!(breaker.acquire().is_ok());
+        assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+        assert!(breaker.acquire().is_err());
+        // a failed probe opens the circuit again
+        breaker.on_failure();
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        // a successful probe closes it
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(breaker.acquire().is_ok());
+        breaker.on_success();
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert!(!breaker.is_under_pressure());
+    }
+
+    #[test]
+    fn stays_open_on_late_success() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        breaker.on_failure();
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        // a query sent before the opening succeeds
+        breaker.on_success();
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        assert!(breaker.acquire().is_err());
+    }
+
+    #[test]
+    fn probes_again_without_verdict() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.on_failure();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(breaker.acquire().is_ok());
+        // the probe is dropped without any verdict
+        assert!(breaker.acquire().is_err());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(breaker.acquire().is_ok());
+        assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+        breaker.on_success();
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+    }
+
+    #[test]
+    fn ignores_non_es_errors() {
+        let breaker = Arc::new(CircuitBreaker::new(2, Duration::from_secs(60)));
+        breaker.on_failure();
+        let res = CircuitBreaker::watch(
+            breaker.clone(),
+            futures::future::err::<(), _>(BragiError::ObjectNotFound),
+        )
+        .wait();
+        assert!(res.is_err());
+        // the previous failure is still counted
+        assert_eq!(breaker.status().consecutive_failures, 1);
+        let res = CircuitBreaker::watch(
+            breaker.clone(),
+            futures::future::err::<(), _>(BragiError::EsUnavailable("timeout".to_owned())),
+        )
+        .wait();
+        assert!(res.is_err());
+        assert_eq!(breaker.status().state, CircuitState::Open);
+    }
+
+    #[test]
+    fn disabled_circuit_breaker() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            breaker.on_failure();
+        }
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert!(breaker.acquire().is_ok());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::request_id::REQUEST_ID_HEADER;
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method, Uri};
+use serde::Deserialize;
+
+/// CORS policy of bragi
+#[derive(Clone, Debug, Deserialize)]
+pub struct CorsSettings {
+    /// Allowed origins, all origins are allowed if empty
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Allowed headers, all headers are allowed if empty
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    /// Max time (in seconds) the result of a preflight request can be cached
+    pub max_age: Option<usize>,
+}
+
+impl CorsSettings {
+    pub fn new(content: &str) -> Result<Self, String> {
+        let settings: CorsSettings = toml::from_str(content).map_err(|e| e.to_string())?;
+        settings.check()?;
+        Ok(settings)
+    }
+
+    /// Check the settings, actix_cors panics on invalid ones
+    pub fn check(&self) -> Result<(), String> {
+        for origin in &self.allowed_origins {
+            origin
+                .parse::<Uri>()
+                .map_err(|e| format!("invalid CORS origin `{}`: {}", origin, e))?;
+        }
+        for method in &self.allowed_methods {
+            Method::from_bytes(method.as_bytes())
+                .map_err(|e| format!("invalid CORS method `{}`: {}", method, e))?;
+        }
+        for header in &self.allowed_headers {
+            HeaderName::from_bytes(header.as_bytes())
+                .map_err(|e| format!("invalid CORS header `{}`: {}", header, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn build_cors(&self) -> Cors {
+        // the browser clients need to read the id of their requests
+        let mut cors = Cors::new()
+            .allowed_methods(self.allowed_methods.iter().map(String::as_str))
+            .expose_headers(vec![REQUEST_ID_HEADER]);
+        for origin in &self.allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+        if !self.allowed_headers.is_empty() {
+            cors = cors.allowed_headers(self.allowed_headers.iter().map(String::as_str));
+        }
+        if let Some(max_age) = self.max_age {
+            cors = cors.max_age(max_age);
+        }
+        cors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_cors_settings() {
+        let settings = CorsSettings::new(include_str!("../../../config/bragi-cors.toml")).unwrap();
+        assert!(settings.allowed_origins.is_empty());
+        assert_eq!(settings.allowed_methods, vec!["GET", "POST"]);
+    }
+
+    #[test]
+    fn invalid_cors_settings() {
+        let err = CorsSettings::new(
+            r#"
+            allowed_methods = ["GET", "PO ST"]
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.contains("PO ST"), "{}", err);
+    }
+}
@@ -6,6 +6,7 @@
 /// Note: we use serde_qs instead of the actix's default serde_urlencoded because serde_qs is more flexible
 /// (cf https://github.com/nox/serde_urlencoded/issues/6)
 use crate::model::ApiError;
+use crate::request_id;
 use actix_web::{dev::Payload, FromRequest, HttpRequest};
 use failure::Fail;
 use std::ops::{Deref, DerefMut};
@@ -41,19 +42,29 @@ impl actix_web::error::ResponseError for ActixError {
     }
 }
 
-pub struct BragiQuery<T>(T);
+pub struct BragiQuery<T> {
+    params: T,
+    request_id: String,
+}
+
+impl<T> BragiQuery<T> {
+    /// Id of the request, forwarded to elasticsearch to trace the request (cf `request_id`)
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+}
 
 impl<T> Deref for BragiQuery<T> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        &self.0
+        &self.params
     }
 }
 
 impl<T> DerefMut for BragiQuery<T> {
     fn deref_mut(&mut self) -> &mut T {
-        &mut self.0
+        &mut self.params
     }
 }
 
@@ -71,6 +82,9 @@ where
         serde_qs::Config::new(5, false)
             .deserialize_str(req.query_string())
             .map_err(|e| ActixError::InvalidQueryParam(format!("{}", e)))
-            .map(BragiQuery)
+            .map(|params| BragiQuery {
+                params,
+                request_id: request_id::get_request_id(req),
+            })
     }
 }
@@ -0,0 +1,145 @@
+//! Information about the indexes published in elasticsearch
+//!
+//! Some information about the indexes (like the known pt datasets, used to bound the
+//! cardinality of the metrics, or their provenance, given by `/status`) is needed on
+//! every request. It is cached for `ttl`, and refreshed in the background by the first
+//! request that finds it stale, so no request waits for elasticsearch because of it.
+
+use crate::async_rubber::AsyncRubber;
+use crate::circuit_breaker::{CircuitBreaker, CircuitState};
+use futures::Future;
+use mimir::provenance::Provenance;
+use serde_json::Value;
+use slog_scope::warn;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Default)]
+pub struct IndexesInfo {
+    /// The datasets of the published stop indexes
+    pub pt_datasets: BTreeSet<String>,
+    /// The provenances of the indexes aliased by `munin`, by index name.
+    /// None if they have never been fetched.
+    pub provenances: Option<BTreeMap<String, Provenance>>,
+}
+
+impl IndexesInfo {
+    /// Read the information from the aliases of the stop indexes
+    /// (the response of `GET munin_stop/_alias`)
+    pub fn from_stop_aliases(aliases: &Value) -> Self {
+        let pt_datasets = aliases
+            .as_object()
+            .into_iter()
+            .flat_map(|indexes| indexes.values())
+            .filter_map(|index| index.get("aliases").and_then(Value::as_object))
+            .flat_map(|aliases| aliases.keys())
+            .filter_map(|alias| alias.strip_prefix("munin_stop_"))
+            .map(str::to_owned)
+            .collect();
+        IndexesInfo {
+            pt_datasets,
+            provenances: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Cached {
+    info: Arc<IndexesInfo>,
+    // None if the information has never been fetched
+    refreshed_at: Option<Instant>,
+    refreshing: bool,
+}
+
+#[derive(Debug)]
+pub struct IndexesInfoCache {
+    ttl: Duration,
+    cached: Mutex<Cached>,
+}
+
+impl IndexesInfoCache {
+    pub fn new(ttl: Duration) -> Self {
+        IndexesInfoCache {
+            ttl,
+            cached: Mutex::new(Cached {
+                info: Arc::new(IndexesInfo::default()),
+                refreshed_at: None,
+                refreshing: false,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Cached> {
+        self.cached.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The cached information.
+    /// If it is stale, a refresh is started in the background (on the current actix worker).
+    pub fn get(
+        self: &Arc<Self>,
+        rubber: &AsyncRubber,
+        breaker: &Arc<CircuitBreaker>,
+    ) -> Arc<IndexesInfo> {
+        let mut cached = self.lock();
+        let is_stale = cached
+            .refreshed_at
+            .map_or(true, |refreshed_at| refreshed_at.elapsed() >= self.ttl);
+        // we do not want to add load on elasticsearch if it is not available
+        if is_stale && !cached.refreshing && breaker.status().state == CircuitState::Closed {
+            cached.refreshing = true;
+            let cache = self.clone();
+            let refresh = CircuitBreaker::watch(
+                breaker.clone(),
+                rubber
+                    .get_json("munin_stop/_alias")
+                    .join(rubber.get_provenances()),
+            )
+            .then(move |res| {
+                let mut cached = cache.lock();
+                match res {
+                    Ok((aliases, provenances)) => {
+                        cached.info = Arc::new(IndexesInfo {
+                            provenances: Some(provenances),
+                            ..IndexesInfo::from_stop_aliases(&aliases)
+                        })
+                    }
+                    Err(e) => warn!("impossible to get the information of the indexes: {}", e),
+                }
+                // on error, the next refresh is also delayed by the ttl
+                cached.refreshed_at = Some(Instant::now());
+                cached.refreshing = false;
+                Ok::<_, ()>(())
+            });
+            actix_rt::spawn(refresh);
+        }
+        cached.info.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pt_datasets_from_stop_aliases() {
+        let aliases = json!({
+            "munin_stop_fr_20200101_000000_000000": {
+                "aliases": {"munin_stop_fr": {}, "munin_stop": {}, "munin": {}}
+            },
+            "munin_stop_idf_20200101_000000_000000": {
+                "aliases": {"munin_stop_idf": {}, "munin_stop": {}, "munin": {}}
+            },
+            "munin_stop_old_20190101_000000_000000": {"aliases": {}}
+        });
+        let info = IndexesInfo::from_stop_aliases(&aliases);
+        assert_eq!(
+            info.pt_datasets
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            vec!["fr", "idf"]
+        );
+    }
+}
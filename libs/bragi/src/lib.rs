@@ -31,19 +31,29 @@
 #[macro_use]
 extern crate prometheus;
 
-use mimir::rubber::Rubber;
-use slog_scope::debug;
+use crate::async_rubber::AsyncRubber;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::cors::CorsSettings;
+use crate::indexes_info::{IndexesInfo, IndexesInfoCache};
 use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 use structopt::StructOpt;
 
 use std::fs::read_to_string;
 
+mod async_rubber;
+mod circuit_breaker;
+mod cors;
 mod extractors;
+mod indexes_info;
 mod model;
 pub mod prometheus_middleware;
 pub(crate) mod query;
+mod query_log;
 mod query_settings;
+pub mod request_id;
 mod routes;
 pub mod server;
 
@@ -110,17 +120,67 @@ pub struct Args {
     pub http_cache_duration: u32,
     #[structopt(long = "weight-config-file")]
     pub weight_config_file: Option<String>,
+
+    /// Optional file where all the autocomplete queries are logged, as json lines
+    /// (query, parameters, ids of the results, matching strategy and latency)
+    #[structopt(long = "query-log", env = "BRAGI_QUERY_LOG", parse(from_os_str))]
+    pub query_log: Option<PathBuf>,
+
+    /// Number of consecutive elasticsearch failures after which the circuit breaker is opened:
+    /// the queries are then directly rejected with a 503 until a probe query succeeds.
+    /// 0 disables the circuit breaker.
+    #[structopt(
+        long = "circuit-breaker-threshold",
+        env = "BRAGI_CIRCUIT_BREAKER_THRESHOLD",
+        default_value = "10"
+    )]
+    pub circuit_breaker_threshold: u32,
+    /// Delay in ms, when the circuit breaker is open, before probing elasticsearch again
+    #[structopt(
+        long = "circuit-breaker-retry-delay",
+        env = "BRAGI_CIRCUIT_BREAKER_RETRY_DELAY",
+        default_value = "5000"
+    )]
+    pub circuit_breaker_retry_delay: u64,
+    /// Degraded mode: skip the fuzzy search of the autocomplete while elasticsearch is under pressure
+    #[structopt(long = "degraded-mode", env = "BRAGI_DEGRADED_MODE")]
+    pub degraded_mode: bool,
+
+    /// Toml file with the CORS policy (cf `config/bragi-cors.toml` for the default policy).
+    /// The policy can be overridden with the other `--cors-*` parameters
+    #[structopt(long = "cors-config-file")]
+    pub cors_config_file: Option<String>,
+    /// Origin allowed to query bragi (can be given several times)
+    #[structopt(long = "cors-allowed-origin")]
+    pub cors_allowed_origins: Vec<String>,
+    /// Http method allowed by the CORS policy (can be given several times)
+    #[structopt(long = "cors-allowed-method")]
+    pub cors_allowed_methods: Vec<String>,
+    /// Header allowed by the CORS policy (can be given several times)
+    #[structopt(long = "cors-allowed-header")]
+    pub cors_allowed_headers: Vec<String>,
+    /// Max time (in seconds) the result of a CORS preflight request can be cached
+    #[structopt(long = "cors-max-age")]
+    pub cors_max_age: Option<usize>,
 }
 
+// How long the information about the published indexes is cached
+const INDEXES_INFO_TTL: Duration = Duration::from_secs(60);
+
 #[derive(Clone, Debug)]
 pub struct Context {
-    reverse_rubber: Rubber,
-    features_rubber: Rubber,
-    autocomplete_rubber: Rubber,
+    reverse_rubber: AsyncRubber,
+    features_rubber: AsyncRubber,
+    autocomplete_rubber: AsyncRubber,
     pub cnx_string: String,
     pub http_cache_duration: u32,
     // pub rubber: Rubber,
     query_settings: QuerySettings,
+    query_logger: Option<Arc<query_log::QueryLogger>>,
+    circuit_breaker: Arc<CircuitBreaker>,
+    degraded_mode: bool,
+    cors_settings: CorsSettings,
+    indexes_info: Arc<IndexesInfoCache>,
 }
 
 impl TryFrom<&Args> for Context {
@@ -146,18 +206,18 @@ impl TryFrom<&Args> for Context {
             None => include_str!("../../../config/bragi-settings.toml").to_owned(),
         };
         Ok(Self {
-            reverse_rubber: Rubber::new_with_timeout(
+            reverse_rubber: AsyncRubber::new(
                 &args.connection_string,
                 bounded_timeout(args.max_es_reverse_timeout),
-            ),
-            features_rubber: Rubber::new_with_timeout(
+            )?,
+            features_rubber: AsyncRubber::new(
                 &args.connection_string,
                 bounded_timeout(args.max_es_features_timeout),
-            ),
-            autocomplete_rubber: Rubber::new_with_timeout(
+            )?,
+            autocomplete_rubber: AsyncRubber::new(
                 &args.connection_string,
                 bounded_timeout(args.max_es_autocomplete_timeout),
-            ),
+            )?,
             cnx_string: args.connection_string.clone(),
             http_cache_duration: args.http_cache_duration,
             query_settings: QuerySettings::new(&content).map_err(|err| {
@@ -169,33 +229,84 @@ impl TryFrom<&Args> for Context {
                     err
                 )
             })?,
+            query_logger: match args.query_log {
+                Some(ref path) => Some(Arc::new(query_log::QueryLogger::new(path).map_err(
+                    |err| format!("Failed to open query log `{}`: {}", path.display(), err),
+                )?)),
+                None => None,
+            },
+            circuit_breaker: Arc::new(CircuitBreaker::new(
+                args.circuit_breaker_threshold,
+                Duration::from_millis(args.circuit_breaker_retry_delay),
+            )),
+            degraded_mode: args.degraded_mode,
+            cors_settings: cors_settings(args)?,
+            indexes_info: Arc::new(IndexesInfoCache::new(INDEXES_INFO_TTL)),
         })
     }
 }
 
 impl Context {
-    pub fn get_rubber_for_reverse(&self, timeout: Option<Duration>) -> Rubber {
-        clone_or_create(&self.reverse_rubber, timeout)
+    pub fn get_rubber_for_reverse(&self, timeout: Option<Duration>) -> AsyncRubber {
+        self.reverse_rubber.with_timeout(timeout)
     }
-    pub fn get_rubber_for_features(&self, timeout: Option<Duration>) -> Rubber {
-        clone_or_create(&self.features_rubber, timeout)
+    pub fn get_rubber_for_features(&self, timeout: Option<Duration>) -> AsyncRubber {
+        self.features_rubber.with_timeout(timeout)
     }
-    pub fn get_rubber_for_autocomplete(&self, timeout: Option<Duration>) -> Rubber {
-        clone_or_create(&self.autocomplete_rubber, timeout)
+    pub fn get_rubber_for_autocomplete(&self, timeout: Option<Duration>) -> AsyncRubber {
+        self.autocomplete_rubber.with_timeout(timeout)
+    }
+    /// CORS middleware of bragi
+    pub fn build_cors(&self) -> actix_cors::Cors {
+        self.cors_settings.build_cors()
     }
     pub fn get_query_settings(&self) -> &QuerySettings {
         &self.query_settings
     }
+    pub(crate) fn get_query_logger(&self) -> Option<&query_log::QueryLogger> {
+        self.query_logger.as_deref()
+    }
+    pub fn get_circuit_breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.circuit_breaker
+    }
+    /// The information about the published indexes, refreshed every `INDEXES_INFO_TTL`
+    pub(crate) fn get_indexes_info(&self) -> Arc<IndexesInfo> {
+        self.indexes_info
+            .get(&self.features_rubber, &self.circuit_breaker)
+    }
+    /// In degraded mode, the fuzzy search is skipped when elasticsearch is under pressure
+    pub fn allow_fuzzy_search(&self) -> bool {
+        !(self.degraded_mode && self.circuit_breaker.is_under_pressure())
+    }
 }
 
-fn clone_or_create(rubber: &Rubber, timeout: Option<Duration>) -> Rubber {
-    if rubber.timeout == timeout {
-        // we clone the rs_es_client, reusing the reqwest connection pool
-        rubber.clone()
-    } else {
-        // if the timeout is different, since there as no easy way to change the timeout for the moment
-        // we build a new Rubber (and thus a new connection)
-        debug!("creating a new rubber for timeout {:?}", &timeout);
-        Rubber::new_with_timeout(&rubber.cnx_string, timeout)
+fn cors_settings(args: &Args) -> Result<CorsSettings, String> {
+    let content = match args.cors_config_file {
+        Some(ref file_path) => read_to_string(&file_path)
+            .map_err(|e| format!("Failed to read `{}`: {}", file_path, e))?,
+        None => include_str!("../../../config/bragi-cors.toml").to_owned(),
+    };
+    let mut settings = CorsSettings::new(&content).map_err(|err| {
+        format!(
+            "failed to parse `{}`: {}",
+            args.cors_config_file
+                .as_deref()
+                .unwrap_or("config/bragi-cors.toml"),
+            err
+        )
+    })?;
+    if !args.cors_allowed_origins.is_empty() {
+        settings.allowed_origins = args.cors_allowed_origins.clone();
+    }
+    if !args.cors_allowed_methods.is_empty() {
+        settings.allowed_methods = args.cors_allowed_methods.clone();
+    }
+    if !args.cors_allowed_headers.is_empty() {
+        settings.allowed_headers = args.cors_allowed_headers.clone();
+    }
+    if args.cors_max_age.is_some() {
+        settings.max_age = args.cors_max_age;
     }
+    settings.check()?;
+    Ok(settings)
 }
@@ -34,6 +34,7 @@ use rs_es::error::EsError;
 use serde::{Deserialize, Serialize};
 use slog_scope::error;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Fail, Debug)]
 pub enum BragiError {
@@ -45,6 +46,12 @@ pub enum BragiError {
     Es(EsError),
     #[fail(display = "invalid shape: {}", _0)]
     InvalidShape(&'static str),
+    #[fail(display = "elasticsearch is unavailable, retry after {:?}", _0)]
+    CircuitOpen(Duration),
+    #[fail(display = "elasticsearch is unavailable: {}", _0)]
+    EsUnavailable(String),
+    #[fail(display = "invalid query {}", _0)]
+    EsQuery(String),
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -70,6 +77,33 @@ impl actix_web::error::ResponseError for BragiError {
                 short: "validation error".to_owned(),
                 long: format!("{}", self),
             }),
+            BragiError::CircuitOpen(retry_after) => {
+                // Retry-After is in seconds, we round it up
+                let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                actix_web::HttpResponse::ServiceUnavailable()
+                    .header(
+                        actix_web::http::header::RETRY_AFTER,
+                        retry_after.max(1).to_string(),
+                    )
+                    .json(ApiError {
+                        short: "query error".to_owned(),
+                        long: "service unavailable".to_owned(),
+                    })
+            }
+            BragiError::EsUnavailable(ref e) => {
+                error!("es error on query: {}", e);
+                actix_web::HttpResponse::ServiceUnavailable().json(ApiError {
+                    short: "query error".to_owned(),
+                    long: "service unavailable".to_owned(),
+                })
+            }
+            BragiError::EsQuery(ref e) => {
+                error!("es error on query: {}", e);
+                actix_web::HttpResponse::InternalServerError().json(ApiError {
+                    short: "query error".to_owned(),
+                    long: "internal server error".to_owned(),
+                })
+            }
             BragiError::Es(ref es_error) => {
                 error!("es error on query: {}", &es_error);
                 match es_error {
@@ -89,6 +123,16 @@ impl actix_web::error::ResponseError for BragiError {
     }
 }
 
+impl BragiError {
+    /// Errors due to elasticsearch (and not to the query)
+    pub fn is_es_failure(&self) -> bool {
+        matches!(
+            self,
+            BragiError::Es(_) | BragiError::EsUnavailable(_) | BragiError::EsQuery(_)
+        )
+    }
+}
+
 impl From<EsError> for BragiError {
     fn from(e: EsError) -> Self {
         BragiError::Es(e)
@@ -220,6 +264,11 @@ pub struct GeocodingResponse {
     pub bbox: Option<geo_types::Rect<f64>>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub country_codes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_type: Option<mimir::StopPointType>,
+    /// The stop area of a stop point
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<mimir::ParentStopArea>,
 }
 
 trait ToGeom {
@@ -230,10 +279,15 @@ impl ToGeom for mimir::Place {
     fn to_geom(&self) -> geojson::Geometry {
         match self {
             mimir::Place::Admin(ref admin) => admin.coord.to_geom(),
-            mimir::Place::Street(ref street) => street.coord.to_geom(),
+            mimir::Place::Street(ref street) => match street.geometry {
+                // the geometry is only fetched when asked
+                Some(ref geometry) => geojson::Geometry::new(geojson::Value::from(geometry)),
+                None => street.coord.to_geom(),
+            },
             mimir::Place::Addr(ref addr) => addr.coord.to_geom(),
             mimir::Place::Poi(ref poi) => poi.coord.to_geom(),
             mimir::Place::Stop(ref stop) => stop.coord.to_geom(),
+            mimir::Place::StopPoint(ref stop_point) => stop_point.coord.to_geom(),
         }
     }
 }
@@ -255,6 +309,9 @@ impl FromWithLang<mimir::Place> for Feature {
             mimir::Place::Addr(addr) => GeocodingResponse::from_with_lang(addr, lang),
             mimir::Place::Poi(poi) => GeocodingResponse::from_with_lang(poi, lang),
             mimir::Place::Stop(poi) => GeocodingResponse::from_with_lang(poi, lang),
+            mimir::Place::StopPoint(stop_point) => {
+                GeocodingResponse::from_with_lang(stop_point, lang)
+            }
         };
         Feature {
             feature_type: "Feature".to_string(),
@@ -338,9 +395,17 @@ fn get_citycode(admins: &[Arc<mimir::Admin>]) -> Option<String> {
 
 impl FromWithLang<mimir::Street> for GeocodingResponse {
     fn from_with_lang(other: mimir::Street, lang: Option<&str>) -> GeocodingResponse {
+        let (name, label) = if let Some(code) = lang {
+            (
+                other.names.get(code).unwrap_or(&other.name),
+                other.labels.get(code).unwrap_or(&other.label),
+            )
+        } else {
+            (other.name.as_ref(), other.label.as_ref())
+        };
+        let name = Some(name.to_owned());
+        let label = Some(label.to_owned());
         let type_ = "street".to_string();
-        let name = Some(other.name);
-        let label = Some(other.label);
         let admins = other.administrative_regions;
         let city = get_city_name(&admins);
         let postcode = if other.zip_codes.is_empty() {
@@ -504,6 +569,47 @@ impl FromWithLang<mimir::Stop> for GeocodingResponse {
     }
 }
 
+impl FromWithLang<mimir::StopPoint> for GeocodingResponse {
+    fn from_with_lang(other: mimir::StopPoint, lang: Option<&str>) -> GeocodingResponse {
+        let type_ = "public_transport:stop_point".to_string();
+        let label = Some(other.label);
+        let name = Some(other.name);
+        let admins = other.administrative_regions;
+        let city = get_city_name(&admins);
+        let postcode = if other.zip_codes.is_empty() {
+            None
+        } else {
+            Some(other.zip_codes.join(";"))
+        };
+        let citycode = get_citycode(&admins);
+
+        let associated_admins = admins
+            .iter()
+            .map(|a| AssociatedAdmin::from_with_lang(a, lang))
+            .collect();
+
+        GeocodingResponse {
+            id: other.id,
+            citycode,
+            place_type: type_,
+            name,
+            postcode,
+            label,
+            city,
+            administrative_regions: associated_admins,
+            commercial_modes: other.commercial_modes,
+            physical_modes: other.physical_modes,
+            lines: other.lines,
+            codes: other.codes,
+            properties: other.properties,
+            country_codes: other.country_codes,
+            stop_type: Some(other.stop_type),
+            parent: other.parent,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct Autocomplete {
     #[serde(rename = "type")]
@@ -27,19 +27,29 @@
 // IRC #navitia on freenode
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
-use super::model::{self, BragiError};
+use super::model::BragiError;
+use crate::async_rubber::{AsyncRubber, PreparedSearch};
 use crate::query_settings::{BuildWeight, Proximity, QuerySettings, Types};
+use futures::future::{self, Either};
+use futures::Future;
 use geojson::Geometry;
-use mimir::objects::{Addr, Admin, Coord, MimirObject, PlaceDocType, Poi, Stop, Street};
-use mimir::rubber::{get_indexes, read_places, Rubber};
-use prometheus::{self, exponential_buckets, histogram_opts, register_histogram_vec, HistogramVec};
-use rs_es::error::EsError;
-use rs_es::operations::search::Source;
+use mimir::objects::{
+    Addr, Admin, Coord, LieuDit, MimirObject, PlaceDocType, Poi, Stop, StopPoint, Street,
+};
+use mimir::rubber::{
+    build_reverse_query, get_features_indexes, get_indexes, get_reverse_indexes, keep_nearest,
+    square_around, PlacesSearch, ES_REVERSE_REQ_HISTOGRAM, REVERSE_NB_CANDIDATES,
+};
+use prometheus::{
+    self, exponential_buckets, histogram_opts, linear_buckets, opts, register_histogram_vec,
+    register_int_counter, register_int_counter_vec, HistogramVec, IntCounter, IntCounterVec,
+};
 use rs_es::query::compound::BoostMode;
 use rs_es::query::functions::{DecayOptions, FilteredFunction, Function, Modifier};
 use rs_es::query::Query;
 use rs_es::units as rs_u;
-use slog_scope::{debug, error, info, warn};
+use slog_scope::{debug, error, warn};
+use std::collections::BTreeSet;
 use std::{fmt, iter};
 
 lazy_static::lazy_static! {
@@ -50,6 +60,67 @@ lazy_static::lazy_static! {
         exponential_buckets(0.001, 1.5, 25).unwrap()
     )
     .unwrap();
+    static ref ZERO_RESULT_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "bragi_autocomplete_zero_results_total",
+        "Number of autocomplete queries without any result.",
+        &["pt_dataset"]
+    )
+    .unwrap();
+    static ref FUZZY_FALLBACK_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "bragi_autocomplete_fuzzy_fallbacks_total",
+        "Number of autocomplete queries for which the prefix search found nothing and a fuzzy search was needed.",
+        &["pt_dataset"]
+    )
+    .unwrap();
+    static ref RESULTS_BY_TYPE_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "bragi_autocomplete_results_total",
+        "Number of places returned by the autocomplete, by type of place.",
+        &["doc_type"]
+    )
+    .unwrap();
+    static ref SKIPPED_FUZZY_COUNTER: IntCounter = register_int_counter!(
+        "bragi_autocomplete_skipped_fuzzy_total",
+        "Number of fuzzy searches skipped because of the degraded mode."
+    )
+    .unwrap();
+    static ref NB_RESULTS_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "bragi_autocomplete_nb_results",
+        "Number of results returned by the autocomplete queries.",
+        &["pt_dataset"],
+        linear_buckets(0., 1., 21).unwrap()
+    )
+    .unwrap();
+}
+
+// The label used for the pt_dataset in the metrics.
+// To limit the cardinality of the metrics, we only give the name of the dataset
+// if there is only one and if it is known (the pt_dataset is given by the client).
+fn pt_dataset_label<'a>(pt_datasets: &[&'a str], known_pt_datasets: &BTreeSet<String>) -> &'a str {
+    match pt_datasets {
+        [] => "",
+        [dataset] if known_pt_datasets.contains(*dataset) => dataset,
+        [_] => "other",
+        _ => "multiple",
+    }
+}
+
+fn observe_autocomplete(pt_dataset: &str, places: &[mimir::Place], match_type: MatchType) {
+    if match_type == MatchType::Fuzzy {
+        FUZZY_FALLBACK_COUNTER
+            .with_label_values(&[pt_dataset])
+            .inc();
+    }
+    if places.is_empty() {
+        ZERO_RESULT_COUNTER.with_label_values(&[pt_dataset]).inc();
+    }
+    for place in places {
+        RESULTS_BY_TYPE_COUNTER
+            .with_label_values(&[place.doc_type().as_str()])
+            .inc();
+    }
+    NB_RESULTS_HISTOGRAM
+        .with_label_values(&[pt_dataset])
+        .observe(places.len() as f64);
 }
 
 /// takes a ES json blob and build a Place from it
@@ -68,9 +139,11 @@ pub fn make_place(doc_type: String, value: Option<Box<serde_json::Value>>) -> Op
         match doc_type.as_ref() {
             "addr" => convert(*v, mimir::Place::Addr),
             "street" => convert(*v, mimir::Place::Street),
+            "lieu_dit" => convert(*v, |lieu_dit: LieuDit| mimir::Place::Street(lieu_dit.0)),
             "admin" => convert(*v, mimir::Place::Admin),
             "poi" => convert(*v, mimir::Place::Poi),
             "stop" => convert(*v, mimir::Place::Stop),
+            "stop_point" => convert(*v, mimir::Place::StopPoint),
             _ => {
                 warn!("unknown ES return value, _type field = {}", doc_type);
                 None
@@ -80,18 +153,23 @@ pub fn make_place(doc_type: String, value: Option<Box<serde_json::Value>>) -> Op
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum MatchType {
+pub(crate) enum MatchType {
     Prefix,
     Fuzzy,
 }
 
-impl fmt::Display for MatchType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let printable = match *self {
+impl MatchType {
+    fn as_str(self) -> &'static str {
+        match self {
             MatchType::Prefix => "prefix",
             MatchType::Fuzzy => "fuzzy",
-        };
-        write!(f, "{}", printable)
+        }
+    }
+}
+
+impl fmt::Display for MatchType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -114,35 +192,65 @@ fn build_coverage_condition(pt_datasets: &[&str]) -> Query {
 
 /// Create a `rs_es::Query` that boosts results according to the
 /// distance to `coord`.
+/// The decay only uses the `coord` of the places, so the streets whose geometry
+/// passes within the offset of the decay are given the maximal proximity score.
 fn build_proximity_with_boost(coord: &Coord, infos: &Proximity, is_fuzzy: bool) -> Query {
-    Query::build_function_score()
-        .with_functions(vec![
+    let decay = DecayOptions::new(
+        rs_u::Location::LatLon(coord.lat(), coord.lon()),
+        rs_u::Distance::new(infos.gaussian.scale, rs_u::DistanceUnit::Kilometer),
+    )
+    .with_offset(rs_u::Distance::new(
+        infos.gaussian.offset,
+        rs_u::DistanceUnit::Kilometer,
+    ))
+    .with_decay(infos.gaussian.decay)
+    .build("coord")
+    .build_exp();
+
+    let mut functions = if infos.gaussian.offset > 0. {
+        let near_street = || {
+            Query::build_bool()
+                .with_must(vec![
+                    Query::build_bool()
+                        .with_should(vec![
+                            Query::build_term("_type", Street::doc_type()).build(),
+                            Query::build_term("_type", LieuDit::doc_type()).build(),
+                        ])
+                        .build(),
+                    Query::build_geo_shape("approx_coord")
+                        .with_geojson(square_around(coord, infos.gaussian.offset * 1000.))
+                        .build(),
+                ])
+                .build()
+        };
+        vec![
             FilteredFunction::build_filtered_function(
-                None,
-                DecayOptions::new(
-                    rs_u::Location::LatLon(coord.lat(), coord.lon()),
-                    rs_u::Distance::new(infos.gaussian.scale, rs_u::DistanceUnit::Kilometer),
-                )
-                .with_offset(rs_u::Distance::new(
-                    infos.gaussian.offset,
-                    rs_u::DistanceUnit::Kilometer,
-                ))
-                .with_decay(infos.gaussian.decay)
-                .build("coord")
-                .build_exp(),
+                Query::build_bool().with_must_not(near_street()).build(),
+                decay,
                 None,
             ),
             FilteredFunction::build_filtered_function(
-                None,
-                Function::build_weight(if is_fuzzy {
-                    infos.weight_fuzzy
-                } else {
-                    infos.weight
-                })
-                .build(),
+                near_street(),
+                Function::build_weight(1.).build(),
                 None,
             ),
-        ])
+        ]
+    } else {
+        vec![FilteredFunction::build_filtered_function(None, decay, None)]
+    };
+    functions.push(FilteredFunction::build_filtered_function(
+        None,
+        Function::build_weight(if is_fuzzy {
+            infos.weight_fuzzy
+        } else {
+            infos.weight
+        })
+        .build(),
+        None,
+    ));
+
+    Query::build_function_score()
+        .with_functions(functions)
         .with_boost_mode(BoostMode::Replace)
         .build()
 }
@@ -162,10 +270,12 @@ fn build_with_weight(build_weight: &BuildWeight, types: &Types) -> Query {
     Query::build_function_score()
         .with_functions(vec![
             weighted(Stop::doc_type(), types.stop),
+            weighted(StopPoint::doc_type(), types.stop),
             weighted(Addr::doc_type(), types.address),
             weighted(Admin::doc_type(), types.admin),
             weighted(Poi::doc_type(), types.poi),
             weighted(Street::doc_type(), types.street),
+            weighted(LieuDit::doc_type(), types.street),
         ])
         .with_boost_mode(BoostMode::Replace)
         .build()
@@ -196,8 +306,10 @@ fn build_query<'a>(
             match_type_with_boost::<Addr>(query_settings.type_query.boosts.address),
             match_type_with_boost::<Admin>(query_settings.type_query.boosts.admin),
             match_type_with_boost::<Stop>(query_settings.type_query.boosts.stop),
+            match_type_with_boost::<StopPoint>(query_settings.type_query.boosts.stop),
             match_type_with_boost::<Poi>(query_settings.type_query.boosts.poi),
             match_type_with_boost::<Street>(query_settings.type_query.boosts.street),
+            match_type_with_boost::<LieuDit>(query_settings.type_query.boosts.street),
         ])
         .with_boost(query_settings.type_query.global)
         .build();
@@ -226,6 +338,9 @@ fn build_query<'a>(
         build_multi_match("label.prefix", &format_labels_prefix_field)
             .with_boost(query_settings.string_query.boosts.label_prefix)
             .build(),
+        Query::build_match("aliases", q)
+            .with_boost(query_settings.string_query.boosts.aliases)
+            .build(),
         Query::build_match("zip_codes", q)
             .with_boost(query_settings.string_query.boosts.zip_codes)
             .build(),
@@ -402,9 +517,7 @@ fn build_query<'a>(
     if let Some(s) = shape {
         let filter_w_shape_term = Query::build_bool()
             .with_should(
-                shape_scope
-                    .iter()
-                    .map(PlaceDocType::as_str)
+                shape_scope_doc_types(shape_scope)
                     .map(|x| Query::build_term("_type", x).build())
                     .collect::<Vec<_>>(),
             )
@@ -415,9 +528,7 @@ fn build_query<'a>(
         let filter_w_shape = Query::build_bool()
             .with_must(vec![filter_w_shape_term, filter_w_shape_geo])
             .build();
-        let filter_wo_shape = shape_scope
-            .iter()
-            .map(PlaceDocType::as_str)
+        let filter_wo_shape = shape_scope_doc_types(shape_scope)
             .map(|x| Query::build_term("_type", x).build())
             .collect::<Vec<_>>();
         let filter_wo_shape = Query::build_bool().with_must_not(filter_wo_shape).build();
@@ -461,13 +572,59 @@ fn build_query<'a>(
     query.build()
 }
 
+/// Send a search to elasticsearch, with its latency measured in ES_REQ_HISTOGRAM.
+/// If there is no search, no places are found
+fn send_search(
+    search: Option<PreparedSearch>,
+    search_type: &str,
+    coord: Option<Coord>,
+) -> impl Future<Item = Vec<mimir::Place>, Error = BragiError> {
+    let search = match search {
+        Some(search) => search,
+        None => return Either::A(future::ok(vec![])),
+    };
+    let timer = ES_REQ_HISTOGRAM
+        .get_metric_with_label_values(&[search_type])
+        .map(|h| h.start_timer())
+        .map_err(
+            |err| error!("impossible to get ES_REQ_HISTOGRAM metrics"; "err" => err.to_string()),
+        )
+        .ok();
+    Either::B(search.send(coord).then(move |res| {
+        if let Some(t) = timer {
+            t.observe_duration();
+        }
+        res
+    }))
+}
+
+/// The ES doc types of the places of the shape scope (the lieux-dits are streets)
+fn shape_scope_doc_types(shape_scope: &[PlaceDocType]) -> impl Iterator<Item = &'static str> + '_ {
+    shape_scope.iter().flat_map(|doc_type| match doc_type {
+        PlaceDocType::Street => vec![Street::doc_type(), LieuDit::doc_type()],
+        _ => vec![doc_type.as_str()],
+    })
+}
+
+/// Fields of the places that are not fetched from ES.
+/// The geometry of the streets is only fetched on demand, as it can be large.
+fn excluded_fields(full_geometry: bool) -> &'static [&'static str] {
+    if full_geometry {
+        &["boundary"]
+    } else {
+        &["boundary", "geometry"]
+    }
+}
+
+/// Prepare an autocomplete search.
+/// There is no search if there is no index to search in.
 #[allow(clippy::too_many_arguments)]
 fn query(
     q: &str,
     pt_datasets: &[&str],
     poi_datasets: &[&str],
     all_data: bool,
-    rubber: &mut Rubber,
+    rubber: &AsyncRubber,
     match_type: MatchType,
     offset: u64,
     limit: u64,
@@ -479,14 +636,10 @@ fn query(
     poi_types: &[&str],
     langs: &[&str],
     debug: bool,
+    full_geometry: bool,
     query_settings: &QuerySettings,
     request_id: Option<&str>,
-) -> Result<Vec<mimir::Place>, EsError> {
-    if let Some(id) = request_id {
-        info!("query::autocomplete - enter - {} - ({})", id, q);
-    }
-
-    let query_type = match_type.to_string();
+) -> Result<Option<PreparedSearch>, BragiError> {
     let query = build_query(
         q,
         match_type,
@@ -509,63 +662,20 @@ fn query(
     debug!("ES indexes: {:?}", indexes);
 
     if indexes.is_empty() {
-        // if there is no indexes, rs_es search with index "_all"
+        // if there is no indexes, elasticsearch would search in all the indexes
         // but we want to return empty response in this case.
-        return Ok(vec![]);
+        return Ok(None);
     }
-    let timer = ES_REQ_HISTOGRAM
-        .get_metric_with_label_values(&[query_type.as_str()])
-        .map(|h| h.start_timer())
-        .map_err(
-            |err| error!("impossible to get ES_REQ_HISTOGRAM metrics"; "err" => err.to_string()),
-        )
-        .ok();
 
-    let timeout = rubber.timeout.map(|t| format!("{:?}", t));
-    let mut search_query = rubber.es_client.search_query();
-
-    let search_query = search_query
-        .with_ignore_unavailable(true)
-        .with_indexes(&indexes)
-        .with_query(&query)
+    let search = PlacesSearch::new(&indexes, &query)
         .with_from(offset)
         .with_size(limit)
         // No need to fetch "boundary" as it's not used in the geocoding response
         // and is very large in some documents (countries...)
-        .with_source(Source::exclude(&["boundary"]));
-
-    // We don't want to clutter the Query URL, so we only add an explanation if the option is used
-    let search_query = if debug {
-        search_query.with_explain(true)
-    } else {
-        search_query
-    };
-
-    if let Some(timeout) = &timeout {
-        search_query.with_timeout(timeout.as_str());
-    }
-
-    if let Some(id) = request_id {
-        info!("query::autocomplete - es - enter - {} - ({})", id, q);
-    }
-
-    let result = search_query.send()?;
-
-    if let Some(id) = request_id {
-        info!("query::autocomplete - es - exit - {} - ({})", id, q);
-    }
-
-    if let Some(t) = timer {
-        t.observe_duration();
-    }
-
-    let res = read_places(result, coord.as_ref());
-
-    if let Some(id) = request_id {
-        info!("query::autocomplete - exit - {} - ({})", id, q);
-    }
-
-    res
+        .with_excluded_fields(excluded_fields(full_geometry))
+        .with_explain(debug)
+        .with_opaque_id(request_id);
+    rubber.prepare(&search).map(Some)
 }
 
 pub fn features(
@@ -573,8 +683,10 @@ pub fn features(
     poi_datasets: &[&str],
     all_data: bool,
     id: &str,
-    mut rubber: Rubber,
-) -> Result<Vec<mimir::Place>, BragiError> {
+    full_geometry: bool,
+    rubber: AsyncRubber,
+    request_id: Option<&str>,
+) -> impl Future<Item = Vec<mimir::Place>, Error = BragiError> {
     let val = rs_es::units::JsonVal::String(id.into());
     let mut filters = vec![Query::build_ids(vec![val]).build()];
 
@@ -585,7 +697,7 @@ pub fn features(
     let filter = Query::build_bool().with_must(filters).build();
     let query = Query::build_bool().with_filter(filter).build();
 
-    let indexes = get_indexes(all_data, &pt_datasets, &poi_datasets, &[]);
+    let indexes = get_features_indexes(all_data, &pt_datasets, &poi_datasets);
     let indexes = indexes
         .iter()
         .map(|index| index.as_str())
@@ -594,48 +706,72 @@ pub fn features(
     debug!("ES indexes: {:?}", indexes);
 
     if indexes.is_empty() {
-        // if there is no indexes, rs_es search with index "_all"
+        // if there is no indexes, elasticsearch would search in all the indexes
         // but we want to return an error in this case.
-        return Err(BragiError::ObjectNotFound);
-    }
-
-    let timer = ES_REQ_HISTOGRAM
-        .get_metric_with_label_values(&["features"])
-        .map(|h| h.start_timer())
-        .map_err(
-            |err| error!("impossible to get ES_REQ_HISTOGRAM metrics"; "err" => err.to_string()),
-        )
-        .ok();
-
-    let timeout = rubber.timeout.map(|t| format!("{:?}", t));
-    let mut search_query = rubber.es_client.search_query();
-
-    let search_query = search_query
-        .with_ignore_unavailable(true)
-        .with_indexes(&indexes)
-        .with_query(&query);
-
-    if let Some(timeout) = &timeout {
-        search_query.with_timeout(timeout.as_str());
+        return Either::A(future::err(BragiError::ObjectNotFound));
     }
 
-    let result = search_query.send()?;
+    let search = PlacesSearch::new(&indexes, &query)
+        .with_excluded_fields(excluded_fields(full_geometry))
+        .with_opaque_id(request_id);
+    let search = match rubber.prepare(&search) {
+        Ok(search) => search,
+        Err(e) => return Either::A(future::err(e)),
+    };
 
-    if let Some(t) = timer {
-        t.observe_duration()
-    }
+    Either::B(
+        send_search(Some(search), "features", None).and_then(|places| {
+            if places.is_empty() {
+                Err(BragiError::ObjectNotFound)
+            } else {
+                Ok(places)
+            }
+        }),
+    )
+}
 
-    if result.hits.total == 0 {
-        Err(BragiError::ObjectNotFound)
-    } else {
-        read_places(result, None).map_err(model::BragiError::from)
-    }
+/// Search the nearest address or street of the coord.
+/// The geometry of the streets is needed to find the nearest one,
+/// it is removed afterward if not asked.
+pub fn reverse(
+    coord: Coord,
+    full_geometry: bool,
+    rubber: AsyncRubber,
+    request_id: Option<&str>,
+) -> impl Future<Item = Vec<mimir::Place>, Error = BragiError> {
+    let indexes = get_reverse_indexes();
+    let indexes = indexes
+        .iter()
+        .map(|index| index.as_str())
+        .collect::<Vec<&str>>();
+    let query = build_reverse_query(&coord);
+    let search = PlacesSearch::new(&indexes, &query)
+        .with_size(REVERSE_NB_CANDIDATES)
+        .with_excluded_fields(excluded_fields(true))
+        .with_opaque_id(request_id);
+
+    let timer = ES_REVERSE_REQ_HISTOGRAM.start_timer();
+    rubber.search_places(&search, Some(coord)).then(move |res| {
+        timer.observe_duration();
+        res.map(|places| {
+            keep_nearest(places)
+                .into_iter()
+                .map(|mut place| {
+                    if !full_geometry {
+                        place.remove_geometry();
+                    }
+                    place
+                })
+                .collect()
+        })
+    })
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn autocomplete(
     q: &str,
     pt_datasets: &[&str],
+    known_pt_datasets: &BTreeSet<String>,
     poi_datasets: &[&str],
     all_data: bool,
     offset: u64,
@@ -647,11 +783,13 @@ pub fn autocomplete(
     zone_types: &[&str],
     poi_types: &[&str],
     langs: &[&str],
-    mut rubber: Rubber,
+    rubber: AsyncRubber,
     debug: bool,
+    full_geometry: bool,
     query_settings: &QuerySettings,
+    allow_fuzzy: bool,
     request_id: Option<&str>,
-) -> Result<Vec<mimir::Place>, BragiError> {
+) -> Result<impl Future<Item = (Vec<mimir::Place>, MatchType), Error = BragiError>, BragiError> {
     // Perform parameters validation.
     if !zone_types.is_empty() && !types.iter().any(|s| *s == "zone") {
         return Err(BragiError::InvalidParam(
@@ -666,12 +804,13 @@ pub fn autocomplete(
 
     // First we try a pretty exact match on the prefix.
     // If there are no results then we do a new fuzzy search (matching ngrams)
-    let results = query(
+    // Note: both searches are prepared right away, as the futures cannot borrow the parameters
+    let prefix_search = query(
         &q,
         &pt_datasets,
         &poi_datasets,
         all_data,
-        &mut rubber,
+        &rubber,
         MatchType::Prefix,
         offset,
         limit,
@@ -683,17 +822,17 @@ pub fn autocomplete(
         &poi_types,
         &langs,
         debug,
+        full_geometry,
         query_settings,
         request_id,
-    )
-    .map_err(model::BragiError::from)?;
-    if results.is_empty() {
-        query(
+    )?;
+    let fuzzy_search = if allow_fuzzy {
+        Some(query(
             &q,
             &pt_datasets,
             &poi_datasets,
             all_data,
-            &mut rubber,
+            &rubber,
             MatchType::Fuzzy,
             offset,
             limit,
@@ -705,11 +844,52 @@ pub fn autocomplete(
             &poi_types,
             &langs,
             debug,
+            full_geometry,
             query_settings,
             request_id,
-        )
-        .map_err(model::BragiError::from)
+        )?)
     } else {
-        Ok(results)
+        None
+    };
+    let pt_dataset = pt_dataset_label(pt_datasets, known_pt_datasets).to_owned();
+
+    Ok(
+        send_search(prefix_search, MatchType::Prefix.as_str(), coord)
+            .and_then(move |results| {
+                if !results.is_empty() {
+                    return Either::A(future::ok((results, MatchType::Prefix)));
+                }
+                match fuzzy_search {
+                    Some(fuzzy_search) => Either::B(
+                        send_search(fuzzy_search, MatchType::Fuzzy.as_str(), coord)
+                            .map(|results| (results, MatchType::Fuzzy)),
+                    ),
+                    None => {
+                        // bragi is in degraded mode, we don't want to add load on elasticsearch
+                        SKIPPED_FUZZY_COUNTER.inc();
+                        Either::A(future::ok((results, MatchType::Prefix)))
+                    }
+                }
+            })
+            .map(move |(results, match_type)| {
+                observe_autocomplete(&pt_dataset, &results, match_type);
+                (results, match_type)
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pt_dataset_label() {
+        let known = vec!["fr".to_owned(), "idf".to_owned()]
+            .into_iter()
+            .collect();
+        assert_eq!(pt_dataset_label(&[], &known), "");
+        assert_eq!(pt_dataset_label(&["fr"], &known), "fr");
+        assert_eq!(pt_dataset_label(&["whatever"], &known), "other");
+        assert_eq!(pt_dataset_label(&["fr", "idf"], &known), "multiple");
     }
 }
@@ -0,0 +1,127 @@
+//! Structured log of the autocomplete queries
+//!
+//! Each query is written as a json line in a file, with its parameters, the ids of the results,
+//! the matching strategy used and the latency, so that the queries can be analyzed or replayed.
+
+use serde::Serialize;
+use slog_scope::warn;
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// An entry of the query log
+#[derive(Serialize)]
+pub struct QueryLogEntry<'a, P: Serialize> {
+    /// Timestamp of the query, in milliseconds since epoch
+    pub timestamp: u128,
+    pub request_id: &'a str,
+    pub q: &'a str,
+    pub params: &'a P,
+    /// Shape given in the body of the POST queries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shape: Option<&'a geojson::Geometry>,
+    /// Matching strategy used to find the results (prefix or fuzzy)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_type: Option<String>,
+    pub nb_results: usize,
+    pub result_ids: Vec<&'a str>,
+    /// Latency in milliseconds
+    pub latency: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl<'a, P: Serialize> QueryLogEntry<'a, P> {
+    pub fn new(request_id: &'a str, q: &'a str, params: &'a P, latency: Duration) -> Self {
+        QueryLogEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or(0),
+            request_id,
+            q,
+            params,
+            shape: None,
+            match_type: None,
+            nb_results: 0,
+            result_ids: vec![],
+            latency: latency.as_secs_f64() * 1000.,
+            error: None,
+        }
+    }
+}
+
+/// Sink of the query log, shared by all the workers
+#[derive(Debug)]
+pub struct QueryLogger {
+    writer: Mutex<LineWriter<File>>,
+}
+
+impl QueryLogger {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(QueryLogger {
+            writer: Mutex::new(LineWriter::new(file)),
+        })
+    }
+
+    /// Write an entry in the log.
+    /// Errors are only logged, we don't want to fail a query because of the query log
+    pub fn log<P: Serialize>(&self, entry: &QueryLogEntry<'_, P>) {
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("impossible to serialize query log entry: {}", e);
+                return;
+            }
+        };
+        match self.writer.lock() {
+            Ok(mut writer) => {
+                if let Err(e) = writeln!(writer, "{}", line) {
+                    warn!("impossible to write in the query log: {}", e);
+                }
+            }
+            Err(e) => warn!("impossible to lock the query log: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn query_log_lines() {
+        let path = std::env::temp_dir().join(format!("bragi_query_log_{}", std::process::id()));
+        let logger = QueryLogger::new(&path).unwrap();
+        let params = json!({"limit": 10});
+
+        let mut entry = QueryLogEntry::new("id1", "rue", &params, Duration::from_millis(12));
+        entry.match_type = Some("prefix".to_string());
+        entry.nb_results = 2;
+        entry.result_ids = vec!["addr:1", "street:2"];
+        logger.log(&entry);
+        let mut entry = QueryLogEntry::new("id2", "rvoli", &params, Duration::from_millis(3));
+        entry.error = Some("elasticsearch is unavailable".to_string());
+        logger.log(&entry);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["request_id"], json!("id1"));
+        assert_eq!(lines[0]["params"], params);
+        assert_eq!(lines[0]["match_type"], json!("prefix"));
+        assert_eq!(lines[0]["result_ids"], json!(["addr:1", "street:2"]));
+        assert_eq!(lines[0]["latency"], json!(12.));
+        assert!(lines[0].get("error").is_none());
+        assert_eq!(lines[1]["q"], json!("rvoli"));
+        assert_eq!(lines[1]["error"], json!("elasticsearch is unavailable"));
+    }
+}
@@ -20,6 +20,9 @@ pub struct StringQueryBoosts {
     pub name: f64,
     pub label: f64,
     pub label_prefix: f64,
+    // the settings written before the aliases do not boost them
+    #[serde(default)]
+    pub aliases: f64,
     pub zip_codes: f64,
     pub house_number: f64,
     pub label_ngram_with_coord: f64,
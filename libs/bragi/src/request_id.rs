@@ -0,0 +1,192 @@
+//! Tracing of the requests with an `X-Request-Id` header
+//!
+//! The id is read from the `X-Request-Id` header (or from the legacy `request_id` query parameter),
+//! and generated if the client did not give any.
+//! It is stored in the request extensions to be used by the extractors (cf `BragiQuery`),
+//! forwarded to elasticsearch and returned in the response headers.
+
+use actix_service::{Service, Transform};
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    Error, HttpMessage, HttpRequest,
+};
+use futures::future::{ok, FutureResult};
+use futures::{Future, Poll};
+use serde::Deserialize;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// we do not want to forward huge headers to elasticsearch and to the logs
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Id of the request, stored in the request extensions
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+#[derive(Deserialize)]
+struct RequestIdParam {
+    request_id: Option<String>,
+}
+
+// the id is sent in the headers of the requests to elasticsearch and of the response,
+// so it must be made of visible ascii characters
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_graphic())
+        && HeaderValue::from_str(id).is_ok()
+}
+
+fn read_or_generate(headers: &HeaderMap, query_string: &str) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .or_else(|| {
+            serde_qs::Config::new(5, false)
+                .deserialize_str::<RequestIdParam>(query_string)
+                .ok()
+                .and_then(|param| param.request_id)
+        })
+        .filter(|id| is_valid(id))
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Get the id of the request.
+///
+/// If the `RequestIdHeader` middleware is not used, the id is computed from the request.
+pub fn get_request_id(req: &HttpRequest) -> String {
+    req.extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_else(|| read_or_generate(req.headers(), req.query_string()))
+}
+
+/// Middleware giving an id to all requests and adding it in the `X-Request-Id` response header
+#[derive(Clone, Default)]
+#[must_use = "must be set up as middleware for actix-web"]
+pub struct RequestIdHeader;
+
+impl<S, B> Transform<S> for RequestIdHeader
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdHeaderMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdHeaderMiddleware { service })
+    }
+}
+
+#[doc(hidden)]
+pub struct RequestIdHeaderMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for RequestIdHeaderMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let request_id = read_or_generate(req.headers(), req.query_string());
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+
+        Box::new(self.service.call(req).map(move |mut res| {
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            res
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App};
+
+    #[test]
+    fn request_id_from_header_or_param() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(read_or_generate(&headers, "q=paris&request_id=42"), "42");
+
+        headers.insert(
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            HeaderValue::from_static("bob"),
+        );
+        assert_eq!(read_or_generate(&headers, "q=paris&request_id=42"), "bob");
+    }
+
+    #[test]
+    fn request_id_generated() {
+        let headers = HeaderMap::new();
+        let id = read_or_generate(&headers, "q=paris");
+        assert!(uuid::Uuid::parse_str(&id).is_ok(), "{}", id);
+        assert_ne!(read_or_generate(&headers, "q=paris"), id);
+
+        // an invalid id is replaced
+        let too_long = "a".repeat(MAX_REQUEST_ID_LEN + 1);
+        let id = read_or_generate(&headers, &format!("request_id={}", too_long));
+        assert!(uuid::Uuid::parse_str(&id).is_ok(), "{}", id);
+    }
+
+    #[test]
+    fn request_id_not_a_header_value() {
+        let headers = HeaderMap::new();
+        for query in &["request_id=%0A", "request_id=%C3%A9", "request_id=a%20b"] {
+            let id = read_or_generate(&headers, query);
+            assert!(uuid::Uuid::parse_str(&id).is_ok(), "{}: {}", query, id);
+        }
+        assert_eq!(read_or_generate(&headers, "request_id=a-b_c.1"), "a-b_c.1");
+    }
+
+    #[test]
+    fn request_id_propagated() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RequestIdHeader)
+                .route("/", web::get().to(|req: HttpRequest| get_request_id(&req))),
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .header(REQUEST_ID_HEADER, "bob")
+            .to_request();
+        let resp = test::call_service(&mut app, req);
+        assert_eq!(
+            resp.headers().get(REQUEST_ID_HEADER).unwrap(),
+            &HeaderValue::from_static("bob")
+        );
+        let req = test::TestRequest::get()
+            .uri("/")
+            .header(REQUEST_ID_HEADER, "bob")
+            .to_request();
+        assert_eq!(test::read_response(&mut app, req), "bob".as_bytes());
+
+        // the generated id is the one given to the handler
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&mut app, req);
+        let header = resp.headers().get(REQUEST_ID_HEADER).unwrap().clone();
+        assert_eq!(test::read_body(resp), header.as_bytes());
+    }
+}
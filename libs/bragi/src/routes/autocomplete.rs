@@ -1,14 +1,18 @@
+use crate::circuit_breaker::CircuitBreaker;
 use crate::extractors::BragiQuery;
 use crate::model::{Autocomplete, BragiError, FromWithLang};
+use crate::query_log::QueryLogEntry;
 use crate::routes::params;
+use crate::routes::ResponseFuture;
 use crate::{model, query, Context};
 use actix_http::http::header::{CacheControl, CacheDirective};
 use actix_web::web::{Data, HttpResponse, Json};
+use futures::future::{self, Future};
 use geojson::{GeoJson, Geometry};
 use mimir::objects::{Coord, PlaceDocType};
 use serde::{Deserialize, Serialize};
 use slog_scope::trace;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 enum Type {
@@ -20,6 +24,8 @@ enum Type {
     Poi,
     #[serde(rename = "public_transport:stop_area")]
     StopArea,
+    #[serde(rename = "public_transport:stop_point")]
+    StopPoint,
     #[serde(rename = "street")]
     Street,
     #[serde(rename = "zone")]
@@ -33,6 +39,7 @@ impl Type {
             Type::House => "house",
             Type::Poi => "poi",
             Type::StopArea => "public_transport:stop_area",
+            Type::StopPoint => "public_transport:stop_point",
             Type::Street => "street",
             Type::Zone => "zone",
         }
@@ -97,9 +104,11 @@ pub struct Params {
     // It is prefixed by an underscore to indicate its not a public parameter.
     #[serde(default, rename = "_debug")]
     debug: Option<bool>,
-
-    // Embeds a client id into the request to improve tracing
-    request_id: Option<String>,
+    // `full` to get the whole geometry of the streets instead of their coord
+    #[serde(default)]
+    geometry: params::GeometryType,
+    // Note: the client can also give a `request_id` param to trace the request,
+    // it is handled by the `BragiQuery` extractor (cf `request_id` module)
 }
 
 impl Params {
@@ -147,10 +156,11 @@ impl JsonParams {
 }
 
 pub fn call_autocomplete(
-    params: &Params,
-    state: &Context,
+    params: BragiQuery<Params>,
+    state: Data<Context>,
     shape: Option<Geometry>,
-) -> Result<HttpResponse, model::BragiError> {
+) -> ResponseFuture {
+    let start = Instant::now();
     let langs = params.langs();
     let rubber = state.get_rubber_for_autocomplete(params.timeout());
     let mut query_settings = state.get_query_settings().clone();
@@ -167,17 +177,31 @@ pub fn call_autocomplete(
         query_settings.importance_query.proximity.gaussian.decay = decay;
     }
 
-    if let Some(id) = &params.request_id {
-        trace!("routes::autocomplete by {} ({})", id, params.q);
-    }
+    trace!(
+        "routes::autocomplete by {} ({})",
+        params.request_id(),
+        params.q
+    );
 
-    let res = query::autocomplete(
+    // the shape is only kept to be logged if needed
+    let logged_shape = state.get_query_logger().and_then(|_| shape.clone());
+
+    let coord = match params.coord() {
+        Ok(coord) => coord,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    if let Err(e) = state.get_circuit_breaker().check() {
+        return Box::new(future::err(e));
+    }
+    let allow_fuzzy = state.allow_fuzzy_search();
+    let query = future::result(query::autocomplete(
         &params.q,
         &params
             .pt_dataset
             .iter()
             .map(String::as_str)
             .collect::<Vec<_>>(),
+        &state.get_indexes_info().pt_datasets,
         &params
             .poi_dataset
             .iter()
@@ -186,7 +210,7 @@ pub fn call_autocomplete(
         params.all_data,
         params.offset,
         params.limit,
-        params.coord()?,
+        coord,
         shape,
         &params.shape_scope,
         &params.types_as_str(),
@@ -195,34 +219,52 @@ pub fn call_autocomplete(
         &langs,
         rubber,
         params.debug.unwrap_or(false),
+        params.geometry == params::GeometryType::Full,
         &query_settings,
-        params.request_id.as_deref(),
-    );
-    res.map(|r| Autocomplete::from_with_lang(r, langs.into_iter().next()))
-        .map(|v| {
-            HttpResponse::Ok()
-                .set(CacheControl(vec![CacheDirective::MaxAge(
-                    state.http_cache_duration,
-                )]))
-                .json(v)
-        })
+        allow_fuzzy,
+        Some(params.request_id()),
+    ))
+    .flatten();
+    let res = CircuitBreaker::watch(state.get_circuit_breaker().clone(), query);
+
+    Box::new(res.then(move |res| {
+        if let Some(logger) = state.get_query_logger() {
+            let mut entry =
+                QueryLogEntry::new(params.request_id(), &params.q, &*params, start.elapsed());
+            entry.shape = logged_shape.as_ref();
+            match &res {
+                Ok((places, match_type)) => {
+                    entry.match_type = Some(match_type.to_string());
+                    entry.nb_results = places.len();
+                    entry.result_ids = places.iter().map(mimir::Place::id).collect();
+                }
+                Err(e) => entry.error = Some(e.to_string()),
+            }
+            logger.log(&entry);
+        }
+
+        res.map(|(r, _)| Autocomplete::from_with_lang(r, params.langs().into_iter().next()))
+            .map(|v| {
+                HttpResponse::Ok()
+                    .set(CacheControl(vec![CacheDirective::MaxAge(
+                        state.http_cache_duration,
+                    )]))
+                    .json(v)
+            })
+    }))
 }
 
-pub fn autocomplete(
-    params: BragiQuery<Params>,
-    state: Data<Context>,
-) -> Result<HttpResponse, model::BragiError> {
-    call_autocomplete(&*params, &*state, None)
+pub fn autocomplete(params: BragiQuery<Params>, state: Data<Context>) -> ResponseFuture {
+    call_autocomplete(params, state, None)
 }
 
 pub fn post_autocomplete(
     params: BragiQuery<Params>,
     state: Data<Context>,
     json_params: Json<JsonParams>,
-) -> Result<HttpResponse, model::BragiError> {
-    call_autocomplete(
-        &*params,
-        &*state,
-        Some(json_params.into_inner().get_geometry()?),
-    )
+) -> ResponseFuture {
+    match json_params.into_inner().get_geometry() {
+        Ok(shape) => call_autocomplete(params, state, Some(shape)),
+        Err(e) => Box::new(future::err(e)),
+    }
 }
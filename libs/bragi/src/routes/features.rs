@@ -1,7 +1,10 @@
+use crate::circuit_breaker::CircuitBreaker;
 use crate::extractors::BragiQuery;
+use crate::routes::{params, ResponseFuture};
 use crate::{model, model::FromWithLang, query, Context};
 use actix_http::http::header::{CacheControl, CacheDirective};
 use actix_web::web::{Data, HttpResponse, Path};
+use futures::future::{self, Future};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -15,13 +18,19 @@ pub struct Params {
     all_data: bool,
     /// timeout in milliseconds
     timeout: Option<u64>,
+    /// `full` to get the whole geometry of the street instead of its coord
+    #[serde(default)]
+    geometry: params::GeometryType,
 }
 
 pub fn features(
     params: BragiQuery<Params>,
     state: Data<Context>,
     id: Path<String>,
-) -> Result<HttpResponse, model::BragiError> {
+) -> ResponseFuture {
+    if let Err(e) = state.get_circuit_breaker().check() {
+        return Box::new(future::err(e));
+    }
     let rubber = state.get_rubber_for_features(params.timeout.map(Duration::from_millis));
     let features = query::features(
         &params
@@ -36,15 +45,19 @@ pub fn features(
             .collect::<Vec<_>>(),
         params.all_data,
         &*id,
+        params.geometry == params::GeometryType::Full,
         rubber,
+        Some(params.request_id()),
     );
-    features
-        .map(|r| model::Autocomplete::from_with_lang(r, None))
-        .map(|v| {
-            HttpResponse::Ok()
-                .set(CacheControl(vec![CacheDirective::MaxAge(
-                    state.http_cache_duration,
-                )]))
-                .json(v)
-        })
+    Box::new(
+        CircuitBreaker::watch(state.get_circuit_breaker().clone(), features)
+            .map(|r| model::Autocomplete::from_with_lang(r, None))
+            .map(move |v| {
+                HttpResponse::Ok()
+                    .set(CacheControl(vec![CacheDirective::MaxAge(
+                        state.http_cache_duration,
+                    )]))
+                    .json(v)
+            }),
+    )
 }
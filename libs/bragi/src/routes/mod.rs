@@ -1,3 +1,7 @@
+use crate::model::BragiError;
+use actix_web::HttpResponse;
+use futures::Future;
+
 mod autocomplete;
 mod entry_point;
 mod features;
@@ -10,3 +14,6 @@ pub use entry_point::entry_point;
 pub use features::features;
 pub use reverse::reverse;
 pub use status::status;
+
+/// Response of the routes querying elasticsearch
+pub type ResponseFuture = Box<dyn Future<Item = HttpResponse, Error = BragiError>>;
@@ -1,5 +1,23 @@
 use crate::model::BragiError;
 use mimir::objects::Coord;
+use serde::{Deserialize, Serialize};
+
+/// Geometry of the returned places
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeometryType {
+    /// The coord of the places
+    Point,
+    /// The whole geometry of the places when known (the streets for now),
+    /// their coord otherwise
+    Full,
+}
+
+impl Default for GeometryType {
+    fn default() -> Self {
+        GeometryType::Point
+    }
+}
 
 pub fn make_coord(lon: f64, lat: f64) -> Result<Coord, BragiError> {
     if !(-90f64..=90f64).contains(&lat) {
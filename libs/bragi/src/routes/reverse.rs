@@ -1,8 +1,10 @@
+use crate::circuit_breaker::CircuitBreaker;
 use crate::extractors::BragiQuery;
-use crate::routes::params;
-use crate::{model, model::FromWithLang, Context};
+use crate::routes::{params, ResponseFuture};
+use crate::{model, model::FromWithLang, query, Context};
 use actix_http::http::header::{CacheControl, CacheDirective};
 use actix_web::web::{Data, HttpResponse};
+use futures::future::{self, Future};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -12,23 +14,35 @@ pub struct Params {
     lon: f64,
     /// timeout in milliseconds
     timeout: Option<u64>,
+    /// `full` to get the whole geometry of the street instead of its coord
+    #[serde(default)]
+    geometry: params::GeometryType,
 }
 
-pub fn reverse(
-    params: BragiQuery<Params>,
-    state: Data<Context>,
-) -> Result<HttpResponse, model::BragiError> {
-    let mut rubber = state.get_rubber_for_reverse(params.timeout.map(Duration::from_millis));
-    let coord = params::make_coord(params.lon, params.lat)?;
-    rubber
-        .get_address(&coord)
-        .map_err(model::BragiError::from)
-        .map(|r| model::Autocomplete::from_with_lang(r, None))
-        .map(|v| {
-            HttpResponse::Ok()
-                .set(CacheControl(vec![CacheDirective::MaxAge(
-                    state.http_cache_duration,
-                )]))
-                .json(v)
-        })
+pub fn reverse(params: BragiQuery<Params>, state: Data<Context>) -> ResponseFuture {
+    let coord = match params::make_coord(params.lon, params.lat) {
+        Ok(coord) => coord,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    if let Err(e) = state.get_circuit_breaker().check() {
+        return Box::new(future::err(e));
+    }
+    let rubber = state.get_rubber_for_reverse(params.timeout.map(Duration::from_millis));
+    let places = query::reverse(
+        coord,
+        params.geometry == params::GeometryType::Full,
+        rubber,
+        Some(params.request_id()),
+    );
+    Box::new(
+        CircuitBreaker::watch(state.get_circuit_breaker().clone(), places)
+            .map(|r| model::Autocomplete::from_with_lang(r, None))
+            .map(move |v| {
+                HttpResponse::Ok()
+                    .set(CacheControl(vec![CacheDirective::MaxAge(
+                        state.http_cache_duration,
+                    )]))
+                    .json(v)
+            }),
+    )
 }
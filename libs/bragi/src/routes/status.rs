@@ -1,20 +1,72 @@
+use crate::circuit_breaker::{CircuitBreakerStatus, CircuitState};
 use crate::Context;
 use actix_web::web::{Data, Json};
+use mimir::provenance::{redact_credentials, Provenance};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// The public part of the provenance of an index.
+/// The arguments and the inputs of the import are not exposed, as they can hold credentials.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IndexStatus {
+    pub tool: String,
+    pub version: String,
+    pub dataset: Option<String>,
+    pub nb_docs: Option<u64>,
+    pub started_at: String,
+    pub published_at: Option<String>,
+}
+
+impl From<&Provenance> for IndexStatus {
+    fn from(provenance: &Provenance) -> Self {
+        IndexStatus {
+            tool: provenance.tool.clone(),
+            version: provenance.version.clone(),
+            dataset: provenance.dataset.clone(),
+            nb_docs: provenance.nb_docs,
+            started_at: provenance.started_at.clone(),
+            published_at: provenance.published_at.clone(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Status {
     pub version: String,
     pub es: String,
     pub status: String,
+    pub circuit_breaker: CircuitBreakerStatus,
+    /// Provenance of the published indexes, by index name, refreshed in the background.
+    /// It is not given when elasticsearch is unavailable, or before the first refresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indexes: Option<BTreeMap<String, IndexStatus>>,
 }
 
 pub fn status(state: Data<Context>) -> Json<Status> {
+    let circuit_breaker = state.get_circuit_breaker().status();
+    let (status, indexes) = match circuit_breaker.state {
+        CircuitState::Closed => (
+            "good",
+            state
+                .get_indexes_info()
+                .provenances
+                .as_ref()
+                .map(|provenances| {
+                    provenances
+                        .iter()
+                        .map(|(index, provenance)| (index.clone(), provenance.into()))
+                        .collect()
+                }),
+        ),
+        CircuitState::HalfOpen | CircuitState::Open => ("degraded", None),
+    };
     Json(Status {
         version: VERSION.to_string(),
-        es: state.cnx_string.clone(),
-        status: "good".to_string(),
+        es: redact_credentials(&state.cnx_string),
+        status: status.to_string(),
+        circuit_breaker,
+        indexes,
     })
 }
@@ -21,8 +21,8 @@ pub fn configure_server(cfg: &mut web::ServiceConfig) {
     .service(
         web::resource("/autocomplete")
             .name("autocomplete")
-            .route(web::get().to(autocomplete))
-            .route(web::post().to(post_autocomplete))
+            .route(web::get().to_async(autocomplete))
+            .route(web::post().to_async(post_autocomplete))
             .data(web::Json::<JsonParams>::configure(|cfg| {
                 cfg.error_handler(|err, _req| ActixError::InvalidJson(format!("{}", err)).into())
             })),
@@ -35,25 +35,30 @@ pub fn configure_server(cfg: &mut web::ServiceConfig) {
     .service(
         web::resource("/features/{id}")
             .name("features")
-            .route(web::get().to(features)),
+            .route(web::get().to_async(features)),
     )
     .service(
         web::resource("/reverse")
             .name("reverse")
-            .route(web::get().to(reverse)),
+            .route(web::get().to_async(reverse)),
     );
 }
 
 pub fn runserver() -> Result<(), String> {
     let args = Args::from_args();
-    let ctx: Context = (&args).try_into()?;
+    run_server(&args)
+}
+
+pub fn run_server(args: &Args) -> Result<(), String> {
+    let ctx: Context = args.try_into()?;
     let prometheus = crate::prometheus_middleware::PrometheusMetrics::new("bragi", "/metrics");
     HttpServer::new(move || {
         App::new()
             .data(ctx.clone())
             // NOTE: if some middlewares are added, don't forget to add them in the tests too (in BragiHandler::new)
-            .wrap(actix_cors::Cors::new().allowed_methods(vec!["GET"]))
+            .wrap(ctx.build_cors())
             .wrap(prometheus.clone())
+            .wrap(crate::request_id::RequestIdHeader)
             .wrap(middleware::Logger::default())
             .configure(configure_server)
             .default_service(web::resource("").route(web::get().to(default_404)))
@@ -0,0 +1,296 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Dump of the indexes into files, instead of Elasticsearch.
+//!
+//! When the connection string of a `Rubber` is a `file://` url, the documents are written as
+//! gzipped NDJSON, in the format of the Elasticsearch bulk API: an action line followed by the
+//! document. At the publication of an index, a metadata file describing how the index has
+//! to be published is written next to it, so that the dump can be loaded later.
+
+use super::objects::MimirObject;
+use super::provenance::Provenance;
+use super::rubber::IndexVisibility;
+use failure::{Error, ResultExt};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use slog_scope::{info, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const DUMP_EXTENSION: &str = ".ndjson.gz";
+const METADATA_EXTENSION: &str = ".json";
+
+/// Description of a dumped index, written at its publication
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DumpMetadata {
+    /// Name of the dumped index
+    pub index: String,
+    pub dataset: String,
+    pub doc_type: String,
+    pub is_geo_data: bool,
+    pub visibility: IndexVisibility,
+    /// Provenance of the index, stored with it when the dump is loaded
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+impl DumpMetadata {
+    /// Alias of the index for its doc type and dataset
+    pub fn dataset_index(&self) -> String {
+        format!("munin_{}_{}", self.doc_type, self.dataset)
+    }
+}
+
+/// File where the documents of an index are dumped
+pub fn dump_path(dir: &Path, index: &str) -> PathBuf {
+    dir.join(format!("{}{}", index, DUMP_EXTENSION))
+}
+
+fn metadata_path(dir: &Path, index: &str) -> PathBuf {
+    dir.join(format!("{}{}", index, METADATA_EXTENSION))
+}
+
+/// Append the documents to the dump of the index, as they would be bulk indexed
+pub fn write_documents<T, I>(dir: &Path, index: &str, iter: I) -> Result<usize, Error>
+where
+    T: MimirObject,
+    I: Iterator<Item = T>,
+{
+    fs::create_dir_all(dir).with_context(|_| format!("Impossible to create {:?}", dir))?;
+    let path = dump_path(dir, index);
+    info!("dumping {} into {:?}", index, path);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|_| format!("Impossible to open the dump {:?}", path))?;
+    let mut writer = GzEncoder::new(BufWriter::new(file), Compression::default());
+    let mut nb = 0;
+    for doc in iter {
+        let action = match doc.es_id() {
+            Some(id) => serde_json::json!({"index": {"_type": T::doc_type(), "_id": id}}),
+            None => serde_json::json!({"index": {"_type": T::doc_type()}}),
+        };
+        serde_json::to_writer(&mut writer, &action)?;
+        writer.write_all(b"\n")?;
+        serde_json::to_writer(&mut writer, &doc)?;
+        writer.write_all(b"\n")?;
+        nb += 1;
+    }
+    writer.finish()?.flush()?;
+    Ok(nb)
+}
+
+/// Write the metadata of a published index, and remove the previous dumps of its
+/// doc type and dataset, as it is done for the indexes in Elasticsearch
+pub fn publish(dir: &Path, metadata: &DumpMetadata) -> Result<(), Error> {
+    fs::create_dir_all(dir).with_context(|_| format!("Impossible to create {:?}", dir))?;
+    let previous: Vec<_> = read_all_metadata(dir)?
+        .into_iter()
+        .filter(|m| m.index != metadata.index && m.dataset_index() == metadata.dataset_index())
+        .collect();
+    let path = metadata_path(dir, &metadata.index);
+    serde_json::to_writer_pretty(File::create(&path)?, metadata)
+        .with_context(|_| format!("Impossible to write the metadata {:?}", path))?;
+    for m in previous {
+        info!("removing the previous dump {}", m.index);
+        fs::remove_file(metadata_path(dir, &m.index))?;
+        fs::remove_file(dump_path(dir, &m.index))
+            .unwrap_or_else(|e| warn!("Impossible to remove the dump {}: {}", m.index, e));
+    }
+    Ok(())
+}
+
+/// Metadata of all the published dumps of the directory
+/// (none if nothing has been dumped in it yet)
+pub fn read_all_metadata(dir: &Path) -> Result<Vec<DumpMetadata>, Error> {
+    let mut all_metadata = vec![];
+    if !dir.exists() {
+        return Ok(all_metadata);
+    }
+    for entry in fs::read_dir(dir).with_context(|_| format!("Impossible to read {:?}", dir))? {
+        let path = entry?.path();
+        let is_metadata = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.ends_with(METADATA_EXTENSION));
+        if is_metadata {
+            let metadata: DumpMetadata = serde_json::from_reader(File::open(&path)?)
+                .with_context(|_| format!("Invalid dump metadata {:?}", path))?;
+            all_metadata.push(metadata);
+        }
+    }
+    all_metadata.sort_by(|a, b| a.index.cmp(&b.index));
+    Ok(all_metadata)
+}
+
+/// Read the raw lines of a dump, an action line followed by a document line.
+/// A dump that cannot be read entirely (truncated or corrupted) gives an error,
+/// as a missing line would shift the actions and the documents.
+pub fn read_lines(
+    dir: &Path,
+    index: &str,
+) -> Result<impl Iterator<Item = Result<String, Error>>, Error> {
+    let path = dump_path(dir, index);
+    let file = File::open(&path).with_context(|_| format!("Impossible to open {:?}", path))?;
+    Ok(BufReader::new(MultiGzDecoder::new(file))
+        .lines()
+        .map(move |line| {
+            line.with_context(|_| format!("Impossible to read {:?}", path))
+                .map_err(Error::from)
+        }))
+}
+
+/// Read the documents of the published dumps aliased by `alias`,
+/// which is either an index, a `munin_<type>_<dataset>` or a public `munin_<type>` alias
+pub fn read_objects<T>(dir: &Path, alias: &str) -> Result<Vec<T>, Error>
+where
+    for<'de> T: MimirObject + Deserialize<'de>,
+{
+    read_objects_filtered(dir, alias, |_| true)
+}
+
+/// Read the documents of the published dumps aliased by `alias` that match the filter,
+/// the other documents are not kept in memory
+pub fn read_objects_filtered<T, F>(dir: &Path, alias: &str, mut filter: F) -> Result<Vec<T>, Error>
+where
+    for<'de> T: MimirObject + Deserialize<'de>,
+    F: FnMut(&T) -> bool,
+{
+    let mut result = vec![];
+    for metadata in read_all_metadata(dir)? {
+        let type_index = format!("munin_{}", metadata.doc_type);
+        let is_public = matches!(metadata.visibility, IndexVisibility::Public);
+        let matches = metadata.index == alias
+            || metadata.dataset_index() == alias
+            || (type_index == alias && is_public);
+        if !matches || metadata.doc_type != T::doc_type() {
+            continue;
+        }
+        for (i, line) in read_lines(dir, &metadata.index)?.enumerate() {
+            let line = line?;
+            // the documents are every second line, after their action
+            if i % 2 == 0 {
+                continue;
+            }
+            let object = serde_json::from_str(&line)
+                .with_context(|_| format!("Invalid document in dump {}", metadata.index))?;
+            if filter(&object) {
+                result.push(object);
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Stop;
+
+    #[test]
+    fn test_dump_and_read() {
+        let dir = std::env::temp_dir().join(format!("mimir_dump_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let stops = vec![
+            Stop {
+                id: "stop_area:1".to_string(),
+                name: "Gare".to_string(),
+                ..Default::default()
+            },
+            Stop {
+                id: "stop_area:2".to_string(),
+                name: "Mairie".to_string(),
+                ..Default::default()
+            },
+        ];
+        for index in &["munin_stop_fr_1", "munin_stop_fr_2"] {
+            assert_eq!(
+                write_documents(&dir, index, stops.clone().into_iter()).unwrap(),
+                2
+            );
+            publish(
+                &dir,
+                &DumpMetadata {
+                    index: index.to_string(),
+                    dataset: "fr".to_string(),
+                    doc_type: "stop".to_string(),
+                    is_geo_data: false,
+                    visibility: IndexVisibility::Public,
+                    provenance: None,
+                },
+            )
+            .unwrap();
+        }
+
+        // the first dump has been replaced by the second one
+        let all_metadata = read_all_metadata(&dir).unwrap();
+        assert_eq!(all_metadata.len(), 1);
+        assert_eq!(all_metadata[0].index, "munin_stop_fr_2");
+        assert!(!dump_path(&dir, "munin_stop_fr_1").exists());
+
+        let lines: Vec<_> = read_lines(&dir, "munin_stop_fr_2")
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            r#"{"index":{"_id":"stop_area:1","_type":"stop"}}"#
+        );
+
+        let read: Vec<Stop> = read_objects(&dir, "munin_stop").unwrap();
+        assert_eq!(
+            read.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            vec!["Gare", "Mairie"]
+        );
+        assert!(read_objects::<Stop>(&dir, "munin_stop_be")
+            .unwrap()
+            .is_empty());
+
+        // a truncated dump is not read partially
+        let content = fs::read(dump_path(&dir, "munin_stop_fr_2")).unwrap();
+        fs::write(
+            dump_path(&dir, "munin_stop_fr_2"),
+            &content[..content.len() / 2],
+        )
+        .unwrap();
+        assert!(read_lines(&dir, "munin_stop_fr_2")
+            .unwrap()
+            .any(|line| line.is_err()));
+        assert!(read_objects::<Stop>(&dir, "munin_stop").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -35,7 +35,9 @@
 // #[macro_use]
 // extern crate failure;
 
+pub mod dump;
 pub mod objects;
+pub mod provenance;
 pub mod rubber;
 
 pub use crate::objects::*;
@@ -28,7 +28,7 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 use cosmogony::ZoneType;
-use geo_types::{Coordinate, MultiPolygon, Rect};
+use geo_types::{Coordinate, LineString, MultiLineString, MultiPolygon, Point, Rect};
 use geojson::Geometry;
 use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::ser::{SerializeStruct, Serializer};
@@ -57,6 +57,7 @@ pub enum Place {
     Addr(Addr),
     Poi(Poi),
     Stop(Stop),
+    StopPoint(StopPoint),
 }
 
 /// Object stored in elastic search
@@ -89,6 +90,10 @@ impl Place {
         matches!(self, Place::Stop(_))
     }
 
+    pub fn is_stop_point(&self) -> bool {
+        matches!(self, Place::StopPoint(_))
+    }
+
     pub fn poi(&self) -> Option<&Poi> {
         match *self {
             Place::Poi(ref poi) => Some(poi),
@@ -96,6 +101,28 @@ impl Place {
         }
     }
 
+    pub fn doc_type(&self) -> PlaceDocType {
+        match *self {
+            Place::Admin(_) => PlaceDocType::Admin,
+            Place::Street(_) => PlaceDocType::Street,
+            Place::Addr(_) => PlaceDocType::Addr,
+            Place::Poi(_) => PlaceDocType::Poi,
+            Place::Stop(_) => PlaceDocType::Stop,
+            Place::StopPoint(_) => PlaceDocType::StopPoint,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        match *self {
+            Place::Admin(ref o) => &o.id,
+            Place::Street(ref o) => &o.id,
+            Place::Addr(ref o) => &o.id,
+            Place::Poi(ref o) => &o.id,
+            Place::Stop(ref o) => &o.id,
+            Place::StopPoint(ref o) => &o.id,
+        }
+    }
+
     pub fn label(&self) -> &str {
         match *self {
             Place::Admin(ref o) => o.label(),
@@ -103,6 +130,7 @@ impl Place {
             Place::Addr(ref o) => o.label(),
             Place::Poi(ref o) => o.label(),
             Place::Stop(ref o) => o.label(),
+            Place::StopPoint(ref o) => o.label(),
         }
     }
 
@@ -113,6 +141,7 @@ impl Place {
             Place::Addr(ref o) => o.admins(),
             Place::Poi(ref o) => o.admins(),
             Place::Stop(ref o) => o.admins(),
+            Place::StopPoint(ref o) => o.admins(),
         }
     }
 
@@ -123,6 +152,7 @@ impl Place {
             Place::Addr(ref o) => Some(Address::Addr(o.clone())),
             Place::Poi(_) => None,
             Place::Stop(_) => None,
+            Place::StopPoint(_) => None,
         }
     }
 
@@ -133,6 +163,7 @@ impl Place {
             Place::Addr(ref o) => o.distance,
             Place::Poi(ref o) => o.distance,
             Place::Stop(ref o) => o.distance,
+            Place::StopPoint(ref o) => o.distance,
         }
     }
 
@@ -143,6 +174,24 @@ impl Place {
             Place::Addr(ref mut o) => o.distance = Some(d),
             Place::Poi(ref mut o) => o.distance = Some(d),
             Place::Stop(ref mut o) => o.distance = Some(d),
+            Place::StopPoint(ref mut o) => o.distance = Some(d),
+        }
+    }
+
+    /// Distance in meters between the place and the point.
+    /// For the streets, the distance to their geometry is used when available.
+    pub fn distance_to(&self, point: &Point<f64>) -> f64 {
+        use geo::algorithm::haversine_distance::HaversineDistance;
+        match self {
+            Place::Street(ref o) => o.distance_to(point),
+            _ => point.haversine_distance(&self.coord().0.into()),
+        }
+    }
+
+    /// Remove the geometry of the place, only its coord being kept
+    pub fn remove_geometry(&mut self) {
+        if let Place::Street(ref mut o) = self {
+            o.geometry = None;
         }
     }
 
@@ -153,6 +202,7 @@ impl Place {
             Place::Addr(ref o) => &o.coord,
             Place::Poi(ref o) => &o.coord,
             Place::Stop(ref o) => &o.coord,
+            Place::StopPoint(ref o) => &o.coord,
         }
     }
 
@@ -163,6 +213,7 @@ impl Place {
             Place::Addr(ref mut o) => o.context = Some(context),
             Place::Poi(ref mut o) => o.context = Some(context),
             Place::Stop(ref mut o) => o.context = Some(context),
+            Place::StopPoint(ref mut o) => o.context = Some(context),
         }
     }
 
@@ -175,6 +226,7 @@ impl Place {
             Place::Addr(ref o) => o.context.clone(),
             Place::Poi(ref o) => o.context.clone(),
             Place::Stop(ref o) => o.context.clone(),
+            Place::StopPoint(ref o) => o.context.clone(),
         }
     }
 }
@@ -188,6 +240,8 @@ pub enum PlaceDocType {
     Addr,
     Poi,
     Stop,
+    #[serde(rename = "stop_point")]
+    StopPoint,
 }
 
 impl PlaceDocType {
@@ -198,6 +252,7 @@ impl PlaceDocType {
             PlaceDocType::Addr => "addr",
             PlaceDocType::Poi => "poi",
             PlaceDocType::Stop => "stop",
+            PlaceDocType::StopPoint => "stop_point",
         }
     }
 }
@@ -287,6 +342,9 @@ pub struct Poi {
     pub names: I18nProperties,
     #[serde(default)]
     pub labels: I18nProperties,
+    /// Alternative names, searchable but not displayed
+    #[serde(default)]
+    pub aliases: Vec<String>,
 
     /// Distance to the coord in query.
     /// Not serialized as is because it is returned in the `Feature` object
@@ -503,6 +561,9 @@ pub struct Stop {
     pub properties: Vec<Property>,
     #[serde(default)]
     pub feed_publishers: Vec<FeedPublisher>,
+    /// Alternative names, searchable but not displayed
+    #[serde(default)]
+    pub aliases: Vec<String>,
     /// Distance to the coord in query.
     /// Not serialized as is because it is returned in the `Feature` object
     #[serde(default, skip)]
@@ -535,6 +596,94 @@ impl Members for Stop {
     }
 }
 
+/// Kind of a `StopPoint`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopPointType {
+    /// A platform, a bus bay... where the vehicles stop
+    StopPoint,
+    /// An entrance (or exit) of the station
+    Entrance,
+}
+
+impl Default for StopPointType {
+    fn default() -> Self {
+        StopPointType::StopPoint
+    }
+}
+
+/// The stop area a stop point belongs to
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ParentStopArea {
+    pub id: String,
+    pub name: String,
+}
+
+/// A stop point or an entrance of a stop area.
+/// Unlike the `Stop`s, the stop points are only indexed on demand, in a private index,
+/// so they are only searched when explicitly asked.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StopPoint {
+    pub id: String,
+    pub label: String,
+    pub name: String,
+    /// coord used for some geograhic queries in ES, less precise but  faster than `coord`
+    /// https://www.elastic.co/guide/en/elasticsearch/reference/2.4/geo-shape.html
+    #[serde(skip_deserializing)]
+    pub approx_coord: Option<Geometry>,
+    pub coord: Coord,
+    pub administrative_regions: Vec<Arc<Admin>>,
+    pub weight: f64,
+    pub zip_codes: Vec<String>,
+    #[serde(default)]
+    pub stop_type: StopPointType,
+    #[serde(default)]
+    pub parent: Option<ParentStopArea>,
+    #[serde(default)]
+    pub commercial_modes: Vec<CommercialMode>,
+    #[serde(default)]
+    pub physical_modes: Vec<PhysicalMode>,
+    #[serde(default)]
+    pub coverages: Vec<String>,
+    #[serde(default)]
+    pub codes: Vec<Code>,
+    #[serde(default)]
+    pub properties: Vec<Property>,
+    /// Alternative names, searchable but not displayed
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Distance to the coord in query.
+    /// Not serialized as is because it is returned in the `Feature` object
+    #[serde(default, skip)]
+    pub distance: Option<u32>,
+    #[serde(default)]
+    pub lines: Vec<Line>,
+    #[serde(default)]
+    pub country_codes: Vec<String>,
+
+    pub context: Option<Context>,
+}
+
+impl MimirObject for StopPoint {
+    fn is_geo_data() -> bool {
+        false
+    }
+    fn doc_type() -> &'static str {
+        PlaceDocType::StopPoint.as_str()
+    }
+    fn es_id(&self) -> Option<String> {
+        Some(self.id.clone())
+    }
+}
+impl Members for StopPoint {
+    fn label(&self) -> &str {
+        &self.label
+    }
+    fn admins(&self) -> Vec<Arc<Admin>> {
+        self.administrative_regions.clone()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Admin {
     pub id: String,
@@ -582,6 +731,9 @@ pub struct Admin {
 
     #[serde(default)]
     pub labels: I18nProperties,
+    /// Alternative names, searchable but not displayed
+    #[serde(default)]
+    pub aliases: Vec<String>,
     /// Distance to the coord in query.
     /// Not serialized as is because it is returned in the `Feature` object
     #[serde(default, skip)]
@@ -713,12 +865,28 @@ pub struct Street {
     pub name: String,
     pub administrative_regions: Vec<Arc<Admin>>,
     pub label: String,
+    #[serde(default)]
+    pub names: I18nProperties,
+    #[serde(default)]
+    pub labels: I18nProperties,
+    /// Alternative names, searchable but not displayed
+    #[serde(default)]
+    pub aliases: Vec<String>,
     pub weight: f64,
     /// coord used for some geograhic queries in ES, less precise but  faster than `coord`
     /// https://www.elastic.co/guide/en/elasticsearch/reference/2.4/geo-shape.html
     #[serde(skip_deserializing)]
     pub approx_coord: Option<Geometry>,
     pub coord: Coord,
+    /// The ways of the street, merged into lines.
+    /// It is not fetched by default from ES, as it can be large.
+    #[serde(
+        serialize_with = "custom_multi_line_string_serialize",
+        deserialize_with = "custom_multi_line_string_deserialize",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub geometry: Option<MultiLineString<f64>>,
     pub zip_codes: Vec<String>,
     #[serde(default)]
     pub country_codes: Vec<String>,
@@ -729,6 +897,86 @@ pub struct Street {
 
     pub context: Option<Context>,
 }
+
+impl Street {
+    /// Set the geometry of the street.
+    /// It is also indexed in `approx_coord`, so that the geographic queries
+    /// match any part of the street. It is indexed with a 20m precision: the index only selects
+    /// the candidates of a reverse geocoding (their exact distance is computed from `geometry`),
+    /// and the number of indexed cells of a long street grows as the precision gets finer.
+    pub fn set_geometry(&mut self, geometry: MultiLineString<f64>) {
+        self.approx_coord = Some(Geometry::new(geojson::Value::from(&geometry)));
+        self.geometry = Some(geometry);
+    }
+
+    /// Distance in meters between the street and the point.
+    /// The geometry of the street is used if known, its coord otherwise.
+    pub fn distance_to(&self, point: &Point<f64>) -> f64 {
+        use geo::algorithm::closest_point::ClosestPoint;
+        use geo::algorithm::haversine_distance::HaversineDistance;
+        use geo::Closest;
+
+        self.geometry
+            .iter()
+            .flat_map(|geometry| geometry.0.iter())
+            .filter_map(|line| match line.closest_point(point) {
+                Closest::Intersection(p) | Closest::SinglePoint(p) => {
+                    Some(point.haversine_distance(&p))
+                }
+                Closest::Indeterminate => None,
+            })
+            .min_by(|d1, d2| d1.partial_cmp(d2).unwrap_or(Ordering::Equal))
+            .unwrap_or_else(|| point.haversine_distance(&self.coord.0.into()))
+    }
+}
+
+fn custom_multi_line_string_serialize<S>(
+    multi_line_string_option: &Option<MultiLineString<f64>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    use geojson::{GeoJson, Value};
+
+    match *multi_line_string_option {
+        Some(ref multi_line_string) => {
+            GeoJson::Geometry(Geometry::new(Value::from(multi_line_string))).serialize(serializer)
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+fn custom_multi_line_string_deserialize<'de, D>(
+    d: D,
+) -> Result<Option<MultiLineString<f64>>, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    use std::convert::TryInto;
+
+    Option::<geojson::GeoJson>::deserialize(d).map(|option| {
+        option.and_then(|geojson| match geojson {
+            geojson::GeoJson::Geometry(geojson_geometry) => {
+                let res: Result<MultiLineString<f64>, _> = match geojson_geometry.value {
+                    line_string @ geojson::Value::LineString(_) => line_string
+                        .try_into()
+                        .map(|line: LineString<f64>| MultiLineString(vec![line])),
+                    value => value.try_into(),
+                };
+                match res {
+                    Ok(multi_line_string) => Some(multi_line_string),
+                    Err(err) => {
+                        warn!("Cannot deserialize into MultiLineString: {}", err);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        })
+    })
+}
+
 impl Incr for Street {
     fn id(&self) -> &str {
         &self.id
@@ -750,6 +998,25 @@ impl MimirObject for Street {
     }
 }
 
+/// A lieu-dit (a named place that is not a street, from the BAN).
+/// It is searched and returned as a street, but indexed with its own doc type,
+/// so that it is not replaced by the import of the streets of its dataset.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(transparent)]
+pub struct LieuDit(pub Street);
+
+impl MimirObject for LieuDit {
+    fn is_geo_data() -> bool {
+        true
+    }
+    fn doc_type() -> &'static str {
+        "lieu_dit"
+    }
+    fn es_id(&self) -> Option<String> {
+        Some(self.0.id.clone())
+    }
+}
+
 impl Members for Street {
     fn label(&self) -> &str {
         &self.label
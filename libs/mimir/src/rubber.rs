@@ -28,22 +28,29 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 
-use super::objects::{Admin, Context, Explanation, MimirObject};
+use super::objects::{Admin, Context, Explanation, LieuDit, MimirObject};
 use super::objects::{AliasOperation, AliasOperations, AliasParameter, Coord, Place};
-use failure::{bail, format_err, Error, ResultExt};
+use crate::dump::{self, DumpMetadata};
+use crate::provenance::{read_provenances, Provenance};
+use failure::{bail, ensure, format_err, Error, ResultExt};
+use geojson::Geometry;
 use prometheus::{exponential_buckets, histogram_opts, register_histogram, Histogram};
 use reqwest::StatusCode;
 use rs_es::error::EsError;
 use rs_es::operations::search::ScanResult;
 use rs_es::operations::search::SearchResult;
+use rs_es::query::compound::BoostMode;
 use rs_es::query::functions::{FilteredFunction, Function};
 use rs_es::query::Query;
 use rs_es::units as rs_u;
 use rs_es::units::Duration;
 use rs_es::EsResponse;
+use serde::{Deserialize, Serialize};
 use slog_scope::{debug, info, warn};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
 use std::marker::PhantomData;
+use std::path::PathBuf;
 use std::time;
 
 const SYNONYMS: [&str; 17] = [
@@ -67,7 +74,7 @@ const SYNONYMS: [&str; 17] = [
 ];
 
 lazy_static::lazy_static! {
-    static ref ES_REQ_HISTOGRAM: Histogram = register_histogram!(
+    pub static ref ES_REVERSE_REQ_HISTOGRAM: Histogram = register_histogram!(
         "bragi_elasticsearch_reverse_duration_seconds",
         "The elasticsearch reverse request latencies in seconds.",
         exponential_buckets(0.001, 1.5, 25).unwrap()
@@ -97,6 +104,128 @@ pub struct Rubber {
     pub cnx_string: String,
     pub nb_insert_threads: usize,
     max_bulk_errors: usize,
+    // if set, the documents are dumped in this directory instead of being sent to elasticsearch
+    pub dump_dir: Option<PathBuf>,
+    pub publication_settings: PublicationSettings,
+}
+
+/// A publication check of the indexes of a doc type, or of all the doc types if none is given.
+/// It is written `<doc_type>=<value>` or `<value>`, e.g. `street=1000`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocTypeCheck<T> {
+    pub doc_type: Option<String>,
+    pub value: T,
+}
+
+impl<T> DocTypeCheck<T> {
+    pub fn new(doc_type: Option<&str>, value: T) -> Self {
+        DocTypeCheck {
+            doc_type: doc_type.map(str::to_string),
+            value,
+        }
+    }
+
+    fn applies_to(&self, doc_type: &str) -> bool {
+        self.doc_type.as_deref().map_or(true, |t| t == doc_type)
+    }
+}
+
+impl<T> std::str::FromStr for DocTypeCheck<T>
+where
+    T: std::str::FromStr,
+    T::Err: Display,
+{
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        // the value can contain a '=' (in a query string), it is only a doc type if it is known
+        let mut parts = s.splitn(2, '=');
+        let (doc_type, value) = match (parts.next(), parts.next()) {
+            (Some(doc_type), Some(value)) if DOC_TYPES.contains(&doc_type) => {
+                (Some(doc_type), value)
+            }
+            _ => (None, s),
+        };
+        let value = value
+            .parse()
+            .map_err(|e| format_err!("invalid value {:?}: {}", value, e))?;
+        Ok(DocTypeCheck::new(doc_type, value))
+    }
+}
+
+/// The value of a check for a doc type,
+/// the last one given for the doc type having priority over the ones given for all of them
+fn check_value<T: Copy>(checks: &[DocTypeCheck<T>], doc_type: &str) -> Option<T> {
+    checks
+        .iter()
+        .filter(|c| c.applies_to(doc_type))
+        .max_by_key(|c| c.doc_type.is_some())
+        .map(|c| c.value)
+}
+
+/// Settings of the publication of a new index.
+/// Checks are made on it before it replaces the previous one. If one of them fails,
+/// the publication is aborted and the new index is kept for inspection.
+/// The checks can be given for a doc type, as a tool can publish several of them.
+#[derive(Clone, Debug, Default)]
+pub struct PublicationSettings {
+    /// Minimum number of documents of the new index
+    pub min_docs: Vec<DocTypeCheck<u64>>,
+    /// Maximum drop of the number of documents versus the replaced index (0.2 for 20%)
+    pub max_drop: Vec<DocTypeCheck<f64>>,
+    /// Query strings that must each match at least a document of the new index
+    pub smoke_queries: Vec<DocTypeCheck<String>>,
+    /// Number of previous versions of the index kept after the publication, to be able
+    /// to roll back to them
+    pub keep_versions: usize,
+    /// Provenance stored with the index at its publication
+    pub provenance: Option<Provenance>,
+}
+
+impl PublicationSettings {
+    /// The settings whose checks given for all the doc types only apply to this one
+    pub fn scoped_to(mut self, doc_type: &str) -> Self {
+        fn scope<T>(checks: &mut Vec<DocTypeCheck<T>>, doc_type: &str) {
+            for check in checks.iter_mut() {
+                check.doc_type.get_or_insert_with(|| doc_type.to_string());
+            }
+        }
+        scope(&mut self.min_docs, doc_type);
+        scope(&mut self.max_drop, doc_type);
+        scope(&mut self.smoke_queries, doc_type);
+        self
+    }
+
+    fn check_counts(
+        &self,
+        doc_type: &str,
+        nb_docs: u64,
+        previous_nb_docs: Option<u64>,
+    ) -> Result<(), Error> {
+        if let Some(min_docs) = check_value(&self.min_docs, doc_type) {
+            ensure!(
+                nb_docs >= min_docs,
+                "the new index has {} documents, at least {} are expected",
+                nb_docs,
+                min_docs
+            );
+        }
+        let max_drop = check_value(&self.max_drop, doc_type);
+        if let (Some(max_drop), Some(previous_nb_docs)) = (max_drop, previous_nb_docs) {
+            if previous_nb_docs > 0 && nb_docs < previous_nb_docs {
+                let drop = (previous_nb_docs - nb_docs) as f64 / previous_nb_docs as f64;
+                ensure!(
+                    drop <= max_drop,
+                    "the new index has {} documents instead of {}, a drop of {:.1}% (max {:.1}%)",
+                    nb_docs,
+                    previous_nb_docs,
+                    drop * 100.,
+                    max_drop * 100.
+                );
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -115,6 +244,8 @@ impl<T> TypedIndex<T> {
 }
 
 /// Index Visibility
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
 pub enum IndexVisibility {
     /// Public means the index is aliased to the global indices
     Public,
@@ -123,12 +254,27 @@ pub enum IndexVisibility {
     Private,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IndexSettings {
     pub nb_shards: usize,
     pub nb_replicas: usize,
 }
 
+/// The lieux-dits are indexed with the settings of the streets
+fn lieu_dit_settings() -> Result<String, Error> {
+    let mut settings: serde_json::Value =
+        serde_json::from_str(include_str!("../../../config/street_settings.json"))?;
+    settings["template"] = format!("munin_{}_*", LieuDit::doc_type()).into();
+    let mapping = settings
+        .pointer_mut("/mappings/street")
+        .map(serde_json::Value::take)
+        .ok_or_else(|| format_err!("no street mapping in the street settings"))?;
+    let mut mappings = serde_json::Map::new();
+    mappings.insert(LieuDit::doc_type().to_string(), mapping);
+    settings["mappings"] = mappings.into();
+    Ok(settings.to_string())
+}
+
 /// return the index associated to the given type and dataset
 /// this will be an alias over another real index
 pub fn get_main_type_and_dataset_index<T: MimirObject>(dataset: &str) -> String {
@@ -149,6 +295,167 @@ pub fn get_date_index_name(base_index_name: &str) -> String {
     )
 }
 
+/// Tell if an index is a version of a base index, ie if it has been named
+/// by `get_date_index_name(base_index_name)`
+pub fn is_index_version(index: &str, base_index_name: &str) -> bool {
+    index
+        .strip_prefix(base_index_name)
+        .and_then(|date| date.strip_prefix('_'))
+        .map(|date| {
+            let parts: Vec<&str> = date.split('_').collect();
+            parts.len() == 3
+                && parts
+                    .iter()
+                    .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+        })
+        .unwrap_or(false)
+}
+
+/// The types of the documents, the longest first as some of them prefix the others
+const DOC_TYPES: [&str; 7] = [
+    "stop_point",
+    "lieu_dit",
+    "street",
+    "admin",
+    "addr",
+    "stop",
+    "poi",
+];
+
+/// The type and the dataset of an index named by `get_date_index_name`
+pub fn parse_index_version(index: &str) -> Option<(&'static str, String)> {
+    let name = index.strip_prefix("munin_")?;
+    DOC_TYPES.iter().find_map(|&doc_type| {
+        let dataset = name
+            .strip_prefix(doc_type)?
+            .strip_prefix('_')?
+            .rsplitn(4, '_')
+            .nth(3)?;
+        let dataset_index = format!("munin_{}_{}", doc_type, dataset);
+        if is_index_version(index, &dataset_index) {
+            Some((doc_type, dataset.to_string()))
+        } else {
+            None
+        }
+    })
+}
+
+/// Field of the `_meta` of the mapping of an index marking it as published
+const PUBLISHED_META: &str = "published";
+
+/// A dated index of a type and a dataset
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IndexVersion {
+    pub name: String,
+    /// the version is the one aliased by the index of the type and the dataset
+    pub is_published: bool,
+    /// the version is or has been published (cf `Rubber::mark_published`),
+    /// it can then be rolled back to
+    pub was_published: bool,
+    /// the aliases held by the version
+    pub aliases: Vec<String>,
+}
+
+/// The aliases of a version of the index of a type and a dataset, from the index of the
+/// dataset to the most general one. The private indexes are only searched by dataset.
+fn version_aliases(
+    doc_type: &str,
+    dataset: &str,
+    is_geo_data: bool,
+    visibility: IndexVisibility,
+) -> Vec<String> {
+    let mut aliases = vec![format!("munin_{}_{}", doc_type, dataset)];
+    if let IndexVisibility::Public = visibility {
+        aliases.push(format!("munin_{}", doc_type));
+        if is_geo_data {
+            aliases.push("munin_geo_data".to_string());
+        }
+        aliases.push("munin".to_string());
+    }
+    aliases
+}
+
+/// The versions, sorted the most recent first, to delete to only keep the `keep` most
+/// recent previously published ones. The versions that have never been published are
+/// deleted if they are older than the published one (left by failed imports), the more
+/// recent ones can be being filled by an import.
+pub fn pruned_versions(versions: &[IndexVersion], keep: usize) -> Vec<&IndexVersion> {
+    let previous = versions
+        .iter()
+        .filter(|v| v.was_published && !v.is_published)
+        .skip(keep);
+    let leftovers = versions
+        .iter()
+        .skip_while(|v| !v.is_published)
+        .filter(|v| !v.was_published && !v.is_published);
+    let mut pruned: Vec<_> = previous.chain(leftovers).collect();
+    pruned.sort_by(|a, b| b.name.cmp(&a.name));
+    pruned
+}
+
+/// The indexes marked as published in their mappings, as returned by `GET <index>/_mapping`
+fn read_published_indexes(mappings: &serde_json::Value) -> BTreeSet<String> {
+    let published_pointer = format!("/_meta/{}", PUBLISHED_META);
+    mappings
+        .as_object()
+        .into_iter()
+        .flat_map(|indexes| indexes.iter())
+        .filter(|(_, mapping)| {
+            mapping
+                .pointer("/mappings")
+                .and_then(serde_json::Value::as_object)
+                .map_or(false, |types| {
+                    types.values().any(|t| {
+                        t.pointer(&published_pointer) == Some(&serde_json::Value::Bool(true))
+                    })
+                })
+        })
+        .map(|(index, _)| index.clone())
+        .collect()
+}
+
+/// The versions that have never been published (cf `Rubber::mark_published`),
+/// left by failed imports, or being filled by an import.
+pub fn orphan_versions(versions: &[IndexVersion]) -> impl Iterator<Item = &IndexVersion> {
+    versions
+        .iter()
+        .filter(|v| !v.is_published && !v.was_published)
+}
+
+/// The number of shards and replicas of the first index, as returned by `GET <index>/_settings`
+fn read_index_settings(settings: &serde_json::Value) -> Option<IndexSettings> {
+    let settings = settings
+        .as_object()?
+        .values()
+        .next()?
+        .pointer("/settings/index")?;
+    let read = |name: &str| settings.get(name)?.as_str()?.parse().ok();
+    Some(IndexSettings {
+        nb_shards: read("number_of_shards")?,
+        nb_replicas: read("number_of_replicas")?,
+    })
+}
+
+/// The creation dates of the indexes, as returned by `GET <index>/_settings`
+fn read_creation_dates(
+    settings: &serde_json::Value,
+) -> BTreeMap<String, chrono::DateTime<chrono::Utc>> {
+    use chrono::TimeZone;
+    settings
+        .as_object()
+        .into_iter()
+        .flat_map(|indexes| indexes.iter())
+        .filter_map(|(index, settings)| {
+            let millis: i64 = settings
+                .pointer("/settings/index/creation_date")?
+                .as_str()?
+                .parse()
+                .ok()?;
+            Some((index.clone(), chrono::Utc.timestamp_millis(millis)))
+        })
+        .collect()
+}
+
 pub fn get_indexes_by_type(a_type: &str) -> String {
     let doc_type = match a_type {
         "public_transport:stop_area" => "stop",
@@ -168,23 +475,160 @@ pub fn read_places(
         "{} documents found in {} ms",
         result.hits.total, result.took
     );
-    let point: Option<geo_types::Point<f64>> = coord.map(|c| c.0.into());
     // for the moment rs-es does not handle enum Document,
     // so we need to convert the ES glob to a Place
-    Ok(result
-        .hits
-        .hits
-        .into_iter()
-        .filter_map(|hit| make_place(hit.doc_type, hit.source, hit.explanation))
+    Ok(with_distance(
+        result
+            .hits
+            .hits
+            .into_iter()
+            .filter_map(|hit| make_place(hit.doc_type, hit.source, hit.explanation)),
+        coord,
+    ))
+}
+
+fn with_distance<I: Iterator<Item = Place>>(places: I, coord: Option<&Coord>) -> Vec<Place> {
+    let point: Option<geo_types::Point<f64>> = coord.map(|c| c.0.into());
+    places
         .map(|mut place| {
             if let Some(ref p) = point {
-                use geo::algorithm::haversine_distance::HaversineDistance;
-                let distance = p.haversine_distance(&place.coord().0.into()) as u32;
+                let distance = place.distance_to(p) as u32;
                 place.set_distance(distance);
             }
             place
         })
-        .collect())
+        .collect()
+}
+
+// Subset of an elasticsearch search response, used by `Rubber::search_places`
+#[derive(Deserialize)]
+struct RawSearchResult {
+    took: u64,
+    hits: RawSearchHits,
+}
+
+impl RawSearchResult {
+    fn into_places(self, coord: Option<&Coord>) -> Vec<Place> {
+        debug!("{} documents found in {} ms", self.hits.total, self.took);
+        with_distance(
+            self.hits
+                .hits
+                .into_iter()
+                .filter_map(|hit| make_place(hit.doc_type, hit.source, hit.explanation)),
+            coord,
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct RawSearchHits {
+    total: u64,
+    hits: Vec<RawSearchHit>,
+}
+
+#[derive(Deserialize)]
+struct RawSearchHit {
+    #[serde(rename = "_type")]
+    doc_type: String,
+    #[serde(rename = "_source")]
+    source: Option<Box<serde_json::Value>>,
+    #[serde(rename = "_explanation")]
+    explanation: Option<serde_json::Value>,
+}
+
+/// Read the response of a search of places (cf `PlacesSearch`).
+/// The coord is used to compute the distance of the places to it.
+pub fn read_search_places(
+    response: &[u8],
+    coord: Option<&Coord>,
+) -> Result<Vec<Place>, serde_json::Error> {
+    let result: RawSearchResult = serde_json::from_slice(response)?;
+    Ok(result.into_places(coord))
+}
+
+/// A search of places, sent by `Rubber::search_places`
+///
+/// rs_es does not let us add custom headers to a search, and we need them to give
+/// an `X-Opaque-Id` to elasticsearch (visible in its slow logs and tasks api),
+/// so the query is sent with our raw http client.
+pub struct PlacesSearch<'a> {
+    indexes: &'a [&'a str],
+    query: &'a Query,
+    from: Option<u64>,
+    size: Option<u64>,
+    explain: bool,
+    excluded_fields: &'a [&'a str],
+    opaque_id: Option<&'a str>,
+}
+
+impl<'a> PlacesSearch<'a> {
+    pub fn new(indexes: &'a [&'a str], query: &'a Query) -> Self {
+        PlacesSearch {
+            indexes,
+            query,
+            from: None,
+            size: None,
+            explain: false,
+            excluded_fields: &[],
+            opaque_id: None,
+        }
+    }
+
+    pub fn with_from(mut self, from: u64) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    pub fn with_excluded_fields(mut self, fields: &'a [&'a str]) -> Self {
+        self.excluded_fields = fields;
+        self
+    }
+
+    pub fn with_opaque_id(mut self, opaque_id: Option<&'a str>) -> Self {
+        self.opaque_id = opaque_id;
+        self
+    }
+
+    /// Path of the search, relative to the elasticsearch url
+    pub fn path(&self) -> String {
+        format!("{}/_search?ignore_unavailable=true", self.indexes.join(","))
+    }
+
+    pub fn opaque_id(&self) -> Option<&'a str> {
+        self.opaque_id
+    }
+
+    /// Body of the search. The timeout is given to elasticsearch
+    pub fn body(&self, timeout: Option<time::Duration>) -> serde_json::Value {
+        let mut body = serde_json::json!({ "query": self.query });
+        if let Some(from) = self.from {
+            body["from"] = from.into();
+        }
+        if let Some(size) = self.size {
+            body["size"] = size.into();
+        }
+        // We don't want to clutter the query, so we only add an explanation if needed
+        if self.explain {
+            body["explain"] = true.into();
+        }
+        if !self.excluded_fields.is_empty() {
+            body["_source"] = serde_json::json!({ "excludes": self.excluded_fields });
+        }
+        if let Some(timeout) = timeout {
+            body["timeout"] = format!("{:?}", timeout).into();
+        }
+        body
+    }
 }
 
 /// takes a ES json blob and build a Place from it
@@ -207,9 +651,11 @@ pub fn make_place(
         match doc_type.as_ref() {
             "addr" => convert(*v, Place::Addr),
             "street" => convert(*v, Place::Street),
+            "lieu_dit" => convert(*v, |lieu_dit: LieuDit| Place::Street(lieu_dit.0)),
             "admin" => convert(*v, Place::Admin),
             "poi" => convert(*v, Place::Poi),
             "stop" => convert(*v, Place::Stop),
+            "stop_point" => convert(*v, Place::StopPoint),
             _ => {
                 warn!("unknown ES return value, _type field = {}", doc_type);
                 None
@@ -251,6 +697,100 @@ pub fn build_proximity_with_boost(coord: &Coord, boost: f64) -> Query {
         .build()
 }
 
+/// Indexes in which the reverse geocoding is done
+pub fn get_reverse_indexes() -> Vec<String> {
+    get_indexes(false, &[], &[], &["house", "street"])
+}
+
+/// Maximum distance of the reverse geocoding, in meters
+pub const REVERSE_MAX_DISTANCE: f64 = 1000.;
+
+/// Number of places fetched by a reverse geocoding, among which the nearest one is kept.
+/// The candidates are ranked by the ring of `REVERSE_RINGS` they are in, so the nearest place
+/// is among them unless there are more candidates in its ring.
+pub const REVERSE_NB_CANDIDATES: u64 = 10;
+
+/// Half sides (in meters) of the nested squares in which the places are ranked by a
+/// reverse geocoding. A street is in a ring if its geometry intersects it, even if its
+/// coord (used by the proximity decay) is far.
+const REVERSE_RINGS: [f64; 3] = [50., 200., REVERSE_MAX_DISTANCE];
+
+/// Square polygon around the coord, with a half side of `meters`
+pub fn square_around(coord: &Coord, meters: f64) -> Geometry {
+    const METERS_PER_DEGREE: f64 = 111_320.;
+    let delta_lat = meters / METERS_PER_DEGREE;
+    // the degrees of longitude are shorter far from the equator
+    let delta_lon = delta_lat / coord.lat().to_radians().cos().max(0.01);
+    let (min_lon, max_lon) = (coord.lon() - delta_lon, coord.lon() + delta_lon);
+    let (min_lat, max_lat) = (coord.lat() - delta_lat, coord.lat() + delta_lat);
+    Geometry::new(geojson::Value::Polygon(vec![vec![
+        vec![min_lon, min_lat],
+        vec![max_lon, min_lat],
+        vec![max_lon, max_lat],
+        vec![min_lon, max_lat],
+        vec![min_lon, min_lat],
+    ]]))
+}
+
+/// Query for the nearest addresses or streets of the coord, in 1km.
+/// The streets are matched on their geometry (indexed in `approx_coord`),
+/// so that a long street is found even if its coord is far.
+/// The score of a place doubles for each ring of `REVERSE_RINGS` it is in,
+/// and the proximity of its coord orders the places of the same ring.
+pub fn build_reverse_query(coord: &Coord) -> Query {
+    let within = |meters: f64| {
+        let distance = rs_u::Distance::new(meters, rs_u::DistanceUnit::Meter);
+        Query::build_bool()
+            .with_should(vec![
+                Query::build_geo_distance("coord", (coord.lat(), coord.lon()), distance).build(),
+                Query::build_geo_shape("approx_coord")
+                    .with_geojson(square_around(coord, meters))
+                    .build(),
+            ])
+            .build()
+    };
+    let mut functions: Vec<_> = REVERSE_RINGS
+        .iter()
+        .map(|&meters| {
+            FilteredFunction::build_filtered_function(
+                within(meters),
+                Function::build_weight(2.).build(),
+                None,
+            )
+        })
+        .collect();
+    functions.push(FilteredFunction::build_filtered_function(
+        None,
+        Function::build_decay(
+            "coord",
+            rs_u::Location::LatLon(coord.lat(), coord.lon()),
+            rs_u::Distance::new(50f64, rs_u::DistanceUnit::Kilometer),
+        )
+        .build_exp(),
+        None,
+    ));
+    Query::build_function_score()
+        .with_query(within(REVERSE_MAX_DISTANCE))
+        .with_functions(functions)
+        .with_boost_mode(BoostMode::Replace)
+        .build()
+}
+
+/// Keep the nearest place among the results of a reverse geocoding
+/// (the distance of the places must have been set).
+pub fn keep_nearest(places: Vec<Place>) -> Vec<Place> {
+    places
+        .into_iter()
+        .filter(|place| {
+            place
+                .distance()
+                .map_or(false, |d| f64::from(d) <= REVERSE_MAX_DISTANCE)
+        })
+        .min_by_key(|place| place.distance())
+        .into_iter()
+        .collect()
+}
+
 pub fn get_indexes(
     all_data: bool,
     pt_datasets: &[&str],
@@ -259,17 +799,23 @@ pub fn get_indexes(
 ) -> Vec<String> {
     // If we want it all, we return 'munin', which an alias over all public indices,
     // and 'munin_poi_*' which returns all private indices (for poi).
+    // The private indices of the stop points are only added when they are asked.
     if all_data {
-        return vec!["munin".to_string(), "munin_poi_*".to_string()];
+        let mut result = vec!["munin".to_string(), "munin_poi_*".to_string()];
+        if types.contains(&"public_transport:stop_point") {
+            result.push("munin_stop_point_*".to_string());
+        }
+        return result;
     }
 
     let mut result: Vec<String> = vec![];
 
     let select_type = |t: &str| -> bool {
+        let is_pt = t == "public_transport:stop_area" || t == "public_transport:stop_point";
         if poi_datasets.is_empty() {
-            t != "public_transport:stop_area"
+            !is_pt
         } else {
-            t != "public_transport:stop_area" && t != "poi"
+            !is_pt && t != "poi"
         }
     };
 
@@ -278,6 +824,10 @@ pub fn get_indexes(
     } else {
         for type_ in types.iter().filter(|&&t| select_type(t)) {
             result.push(get_indexes_by_type(type_));
+            // the lieux-dits are searched as streets
+            if *type_ == "street" {
+                result.push(format!("munin_{}", LieuDit::doc_type()));
+            }
         }
     }
 
@@ -291,6 +841,13 @@ pub fn get_indexes(
         };
     }
 
+    // the stop points are in private indexes, only searched when explicitly asked
+    if types.contains(&"public_transport:stop_point") {
+        pt_datasets
+            .iter()
+            .for_each(|dataset| result.push(format!("munin_stop_point_{}", dataset)));
+    }
+
     if types.is_empty() || types.contains(&"poi") {
         poi_datasets
             .iter()
@@ -299,8 +856,30 @@ pub fn get_indexes(
     result
 }
 
+/// The indexes in which a place is looked up by id: the ones of `get_indexes` without type,
+/// and the stop points of the pt datasets, so that a stop point returned by an autocomplete
+/// can be found.
+pub fn get_features_indexes(
+    all_data: bool,
+    pt_datasets: &[&str],
+    poi_datasets: &[&str],
+) -> Vec<String> {
+    let mut result = get_indexes(all_data, pt_datasets, poi_datasets, &[]);
+    if all_data {
+        result.push("munin_stop_point_*".to_string());
+    } else {
+        result.extend(
+            pt_datasets
+                .iter()
+                .map(|dataset| format!("munin_stop_point_{}", dataset)),
+        );
+    }
+    result
+}
+
 impl Rubber {
     // build a rubber with a connection string (http://host:port/)
+    // or with a directory (file:///path/to/dir) to dump the documents instead of indexing them
     pub fn new(cnx: &str) -> Rubber {
         Rubber::new_with_timeout(cnx, None)
     }
@@ -312,8 +891,18 @@ impl Rubber {
         debug!("elastic search host {} ", cnx);
 
         let timeout = timeout.into();
+        let dump_dir = cnx.strip_prefix("file://").map(PathBuf::from);
+        let es_cnx = if dump_dir.is_some() {
+            "http://localhost:9200"
+        } else {
+            cnx
+        };
+        if let Some(ref dir) = dump_dir {
+            // the directory is created when the first documents are dumped
+            info!("dump mode, the documents will be written in {:?}", dir);
+        }
         Rubber {
-            es_client: rs_es::Client::init_with_timeout(&cnx, timeout).unwrap(),
+            es_client: rs_es::Client::init_with_timeout(&es_cnx, timeout).unwrap(),
             http_client: reqwest::blocking::Client::builder()
                 .timeout(timeout)
                 .build()
@@ -322,6 +911,8 @@ impl Rubber {
             timeout,
             nb_insert_threads: 1,
             max_bulk_errors: 0,
+            dump_dir,
+            publication_settings: PublicationSettings::default(),
         }
     }
 
@@ -338,6 +929,13 @@ impl Rubber {
         self
     }
 
+    /// Change the checks made on the new indexes before publishing them,
+    /// and the number of previous indexes kept
+    pub fn with_publication_settings(mut self, settings: PublicationSettings) -> Self {
+        self.publication_settings = settings;
+        self
+    }
+
     pub fn get(&self, path: &str) -> Result<reqwest::blocking::Response, EsError> {
         // Note: a bit duplicate on rs_es because some ES operations are not implemented
         debug!("doing a get on {}", path);
@@ -373,6 +971,9 @@ impl Rubber {
 
     pub fn create_index(&self, name: &str, index_settings: &IndexSettings) -> Result<(), Error> {
         debug!("creating index");
+        if self.dump_dir.is_some() {
+            return Ok(());
+        }
         // Note: in rs_es it can be done with MappingOperation but for the moment I think
         // storing the mapping in json is more convenient
         let settings = include_str!("../../../config/settings.json");
@@ -417,6 +1018,9 @@ impl Rubber {
 
     pub fn create_template(&self, name: &str, settings: &str) -> Result<(), Error> {
         debug!("creating template");
+        if self.dump_dir.is_some() {
+            return Ok(());
+        }
         self.put(&format!("_template/{}", name), settings)
             .map_err(|e| {
                 info!("Error while creating template {}", name);
@@ -452,6 +1056,7 @@ impl Rubber {
             &"template_poi",
             include_str!("../../../config/poi_settings.json"),
         )?;
+        self.create_template(&"template_lieu_dit", &lieu_dit_settings()?)?;
         Ok(())
     }
 
@@ -461,6 +1066,10 @@ impl Rubber {
         &self,
         base_index: &str,
     ) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        if self.dump_dir.is_some() {
+            // the previous dumps are handled at the publication
+            return Ok(BTreeMap::new());
+        }
         let res = self
             .get(&format!("{}_*/_aliases", base_index))
             .with_context(|_| format!("Error occurred when getting {}*/_aliases", base_index))?;
@@ -492,58 +1101,211 @@ impl Rubber {
         }
     }
 
-    // get the last indexes for this doc_type/dataset
-    // Note: to be resilient to ghost ES indexes, we return all indexes for this doc_type/dataset
-    // but the new index
-    fn get_last_index<T: MimirObject>(
+    /// The versions of the index of a type and a dataset, the most recent first
+    pub fn get_index_versions(
         &self,
-        new_index: &TypedIndex<T>,
+        doc_type: &str,
         dataset: &str,
-    ) -> Result<Vec<String>, Error> {
-        let base_index = get_main_type_and_dataset_index::<T>(dataset);
-        // we don't want to remove the newly created index
-        Ok(self
-            .get_all_aliased_index(&base_index)?
+    ) -> Result<Vec<IndexVersion>, Error> {
+        let dataset_index = format!("munin_{}_{}", doc_type, dataset);
+        let published = self.get_published_indexes(&format!("{}_*", dataset_index))?;
+        let mut versions: Vec<IndexVersion> = self
+            .get_all_aliased_index(&dataset_index)?
+            .into_iter()
+            .filter(|(index, _)| is_index_version(index, &dataset_index))
+            .map(|(name, aliases)| IndexVersion {
+                is_published: aliases.contains(&dataset_index),
+                was_published: published.contains(&name),
+                aliases,
+                name,
+            })
+            .collect();
+        // the dates in the names sort the versions chronologically
+        versions.sort_by(|a, b| b.name.cmp(&a.name));
+        Ok(versions)
+    }
+
+    /// The versions of the indexes of all the types and datasets, the most recent first
+    pub fn get_all_index_versions(
+        &self,
+    ) -> Result<BTreeMap<(String, String), Vec<IndexVersion>>, Error> {
+        let mut all_versions = BTreeMap::new();
+        let published = self.get_published_indexes("munin_*")?;
+        for (index, aliases) in self.get_all_aliased_index("munin")? {
+            if let Some((doc_type, dataset)) = parse_index_version(&index) {
+                let dataset_index = format!("munin_{}_{}", doc_type, dataset);
+                all_versions
+                    .entry((doc_type.to_string(), dataset))
+                    .or_insert_with(Vec::new)
+                    .push(IndexVersion {
+                        is_published: aliases.contains(&dataset_index),
+                        was_published: published.contains(&index),
+                        aliases,
+                        name: index,
+                    });
+            }
+        }
+        for versions in all_versions.values_mut() {
+            versions.sort_by(|a, b| b.name.cmp(&a.name));
+        }
+        Ok(all_versions)
+    }
+
+    /// Delete all the versions of the index of a type and a dataset.
+    /// As the aliases are held by the dated indexes, the dataset is removed from all of them.
+    /// The names of the deleted indexes are returned.
+    pub fn delete_dataset(&mut self, doc_type: &str, dataset: &str) -> Result<Vec<String>, Error> {
+        let deleted: Vec<String> = self
+            .get_index_versions(doc_type, dataset)?
             .into_iter()
-            .map(|(k, _)| k)
-            .filter(|i| i.as_str() != new_index.name)
-            .collect())
+            .map(|v| v.name)
+            .collect();
+        for i in &deleted {
+            self.delete_index(i)
+                .with_context(|err| format!("Error occurred when deleting index {}: {}", i, err))?;
+        }
+        Ok(deleted)
     }
 
-    pub fn get_address(&mut self, coord: &Coord) -> Result<Vec<Place>, EsError> {
-        let types = vec!["house", "street"];
-        let indexes = get_indexes(false, &[], &[], &types);
-        let indexes = indexes
+    /// Re-point the index of a type and a dataset to another version, by default to the
+    /// previously published one preceding the published version. All the aliases of the
+    /// published version are moved at once. Only a version that has been published can be
+    /// rolled back to.
+    /// The name of the newly published version is returned.
+    pub fn rollback_index(
+        &self,
+        doc_type: &str,
+        dataset: &str,
+        version: Option<&str>,
+    ) -> Result<String, Error> {
+        let dataset_index = format!("munin_{}_{}", doc_type, dataset);
+        let versions = self.get_index_versions(doc_type, dataset)?;
+        let current = versions
             .iter()
-            .map(|index| index.as_str())
-            .collect::<Vec<&str>>();
+            .position(|v| v.is_published)
+            .ok_or_else(|| format_err!("no version of {} is published", dataset_index))?;
+        let target = match version {
+            Some(version) => versions
+                .iter()
+                .find(|v| v.name == version)
+                .ok_or_else(|| format_err!("{} is not a version of {}", version, dataset_index))?,
+            None => versions[current + 1..]
+                .iter()
+                .find(|v| v.was_published && !v.is_published)
+                .ok_or_else(|| {
+                    format_err!(
+                        "no previously published version of {} precedes {}",
+                        dataset_index,
+                        versions[current].name
+                    )
+                })?,
+        };
+        ensure!(!target.is_published, "{} is already published", target.name);
+        ensure!(
+            target.was_published,
+            "{} has never been published, it can not be rolled back to",
+            target.name
+        );
 
-        let distance = rs_u::Distance::new(1000., rs_u::DistanceUnit::Meter);
-        let geo_distance =
-            Query::build_geo_distance("coord", (coord.lat(), coord.lon()), distance).build();
-        let query = Query::build_bool()
-            .with_should(build_proximity_with_boost(coord, 1.))
-            .with_must(geo_distance)
-            .build();
+        // the aliases of the published version depend on its visibility and its type
+        let aliases: Vec<String> =
+            version_aliases(doc_type, dataset, true, IndexVisibility::Public)
+                .into_iter()
+                .filter(|alias| {
+                    versions
+                        .iter()
+                        .any(|v| v.is_published && v.aliases.contains(alias))
+                })
+                .collect();
+        self.move_aliases(&aliases, &target.name, &versions)
+            .with_context(|err| {
+                format!(
+                    "Error occurred when making the aliases of {}: {}",
+                    target.name, err
+                )
+            })?;
+        Ok(target.name.clone())
+    }
 
-        let timer = ES_REQ_HISTOGRAM.start_timer();
+    /// Delete the versions of the index of a type and a dataset that are not published,
+    /// but the `keep` most recent previously published ones (cf `pruned_versions`).
+    /// The names of the deleted indexes are returned.
+    pub fn prune_index_versions(
+        &mut self,
+        doc_type: &str,
+        dataset: &str,
+        keep: usize,
+    ) -> Result<Vec<String>, Error> {
+        let versions = self.get_index_versions(doc_type, dataset)?;
+        let deleted: Vec<String> = pruned_versions(&versions, keep)
+            .into_iter()
+            .map(|v| v.name.clone())
+            .collect();
+        for i in &deleted {
+            self.delete_index(i)
+                .with_context(|err| format!("Error occurred when deleting index {}: {}", i, err))?;
+        }
+        Ok(deleted)
+    }
 
-        let timeout = self.timeout.map(|t| format!("{:?}", t));
-        let mut search_query = self.es_client.search_query();
+    /// Search some places, the search is sent to elasticsearch with our raw http client
+    /// (cf `PlacesSearch`).
+    /// The coord is used to compute the distance of the places to it.
+    /// The places cannot be searched in dump mode.
+    pub fn search_places(
+        &self,
+        search: &PlacesSearch<'_>,
+        coord: Option<&Coord>,
+    ) -> Result<Vec<Place>, EsError> {
+        if let Some(ref dir) = self.dump_dir {
+            return Err(EsError::EsError(format!(
+                "the places cannot be searched in the dump {:?}",
+                dir
+            )));
+        }
+        let url = self.es_client.full_url(&search.path());
+        let body = search.body(self.timeout).to_string();
+        debug!("doing a search on {} with {}", url, body);
+        let mut request = self.http_client.post(url).body(body);
+        if let Some(opaque_id) = search.opaque_id() {
+            request = request.header("X-Opaque-Id", opaque_id);
+        }
+        let result: RawSearchResult = check_response(request.send()?)?.read_response()?;
+        Ok(result.into_places(coord))
+    }
 
-        let search_query = search_query
-            .with_ignore_unavailable(true)
-            .with_indexes(&indexes)
-            .with_query(&query)
-            .with_size(1);
+    pub fn get_address(&mut self, coord: &Coord) -> Result<Vec<Place>, EsError> {
+        self.reverse(coord, None)
+    }
 
-        if let Some(timeout) = &timeout {
-            search_query.with_timeout(timeout.as_str());
-        }
-        let result = search_query.send()?;
+    /// Search the nearest address or street of the coord.
+    /// The opaque id is given to elasticsearch to trace the request
+    pub fn reverse(&self, coord: &Coord, opaque_id: Option<&str>) -> Result<Vec<Place>, EsError> {
+        let indexes = get_reverse_indexes();
+        let indexes = indexes
+            .iter()
+            .map(|index| index.as_str())
+            .collect::<Vec<&str>>();
+        let query = build_reverse_query(coord);
+
+        let timer = ES_REVERSE_REQ_HISTOGRAM.start_timer();
+
+        let search = PlacesSearch::new(&indexes, &query)
+            .with_size(REVERSE_NB_CANDIDATES)
+            .with_opaque_id(opaque_id);
+        // the geometry of the streets is not needed in the addresses of the objects
+        let result = self.search_places(&search, Some(coord)).map(|places| {
+            keep_nearest(places)
+                .into_iter()
+                .map(|mut place| {
+                    place.remove_geometry();
+                    place
+                })
+                .collect()
+        });
 
         timer.observe_duration();
-        read_places(result, Some(coord))
+        result
     }
 
     /// publish the index as the new index for this doc_type and this dataset
@@ -555,52 +1317,253 @@ impl Rubber {
         dataset: &str,
         index: TypedIndex<T>,
         visibility: IndexVisibility,
+    ) -> Result<(), Error> {
+        self.publish_index_by_type(
+            dataset,
+            T::doc_type(),
+            T::is_geo_data(),
+            &index.name,
+            visibility,
+        )
+    }
+
+    /// Same as `publish_index`, for an index given by its name and the characteristics of its type
+    pub fn publish_index_by_type(
+        &mut self,
+        dataset: &str,
+        doc_type: &str,
+        is_geo_data: bool,
+        index_name: &str,
+        visibility: IndexVisibility,
     ) -> Result<(), Error> {
         debug!("publishing index");
 
+        if let Some(ref dir) = self.dump_dir {
+            let metadata = DumpMetadata {
+                index: index_name.to_string(),
+                dataset: dataset.to_string(),
+                doc_type: doc_type.to_string(),
+                is_geo_data,
+                visibility,
+                provenance: self
+                    .publication_settings
+                    .provenance
+                    .as_ref()
+                    .map(|p| p.published(dataset, None)),
+            };
+            return dump::publish(dir, &metadata)
+                .with_context(|err| format!("Error occurred when publishing dump: {}", err))
+                .map_err(Error::from);
+        }
+
         // Refresh index before publishing
         self.es_client
             .refresh()
-            .with_indexes(&[&index.name])
+            .with_indexes(&[index_name])
             .send()?;
-        let last_indexes = self.get_last_index(&index, dataset)?;
 
-        let dataset_index = get_main_type_and_dataset_index::<T>(dataset);
-        self.alias(&dataset_index, &[index.name], &last_indexes)
+        let dataset_index = format!("munin_{}_{}", doc_type, dataset);
+
+        self.check_publication(doc_type, index_name, &dataset_index)
             .with_context(|err| {
                 format!(
-                    "Error occurred when making alias {}: {}",
-                    dataset_index, err
+                    "Publication of {} aborted, the index is kept for inspection: {}",
+                    index_name, err
                 )
             })?;
 
-        let type_index = get_main_type_index::<T>();
-        if let IndexVisibility::Public = visibility {
-            self.alias(&type_index, &[dataset_index], &last_indexes)
-                .with_context(|err| {
-                    format!("Error occurred when making alias {}: {}", type_index, err)
-                })?;
+        // the version being replaced may have been published before the publication marker
+        // existed, it is marked so that it is kept and can be rolled back to
+        let versions = self.get_index_versions(doc_type, dataset)?;
+        for version in versions
+            .iter()
+            .filter(|v| v.is_published && !v.was_published)
+        {
+            let provenance = self.get_provenances(&version.name)?.remove(&version.name);
+            self.mark_published(&version.name, doc_type, provenance.as_ref())?;
         }
 
-        if let IndexVisibility::Public = visibility {
-            if T::is_geo_data() {
-                self.alias("munin_geo_data", &[type_index], &[])
-                    .context("Error occurred when making alias: munin_geo_data")?;
-                self.alias("munin", &["munin_geo_data".to_string()], &[])
-                    .context("Error occurred when making alias: munin")?;
-            } else {
-                self.alias("munin", &[type_index], &[])
-                    .context("Error occurred when making alias: munin")?;
+        let provenance = match &self.publication_settings.provenance {
+            Some(provenance) => {
+                let nb_docs = self.count(index_name, None)?;
+                Some(provenance.published(dataset, Some(nb_docs)))
             }
-        }
+            None => None,
+        };
+        self.mark_published(index_name, doc_type, provenance.as_ref())?;
 
-        for i in last_indexes {
-            self.delete_index(&i)
-                .with_context(|err| format!("Error occurred when deleting index {}: {}", i, err))?;
+        // all the aliases are moved at once from the previous versions, so that the ones
+        // kept to roll back are not searched along with the new one
+        let aliases = version_aliases(doc_type, dataset, is_geo_data, visibility);
+        self.move_aliases(&aliases, index_name, &versions)
+            .with_context(|err| {
+                format!(
+                    "Error occurred when making the aliases of {}: {}",
+                    index_name, err
+                )
+            })?;
+
+        // the previous versions are deleted, except the most recent ones to be able to roll back
+        let keep = self.publication_settings.keep_versions;
+        let deleted = self.prune_index_versions(doc_type, dataset, keep)?;
+        info!("previous indexes deleted: {:?}", deleted);
+        Ok(())
+    }
+
+    /// Check the new index of the doc type against the `PublicationSettings`,
+    /// the index being replaced being the one aliased by `replaced_alias`
+    pub fn check_publication(
+        &self,
+        doc_type: &str,
+        new_index: &str,
+        replaced_alias: &str,
+    ) -> Result<(), Error> {
+        if self.dump_dir.is_some() {
+            // the dumps are checked when they are loaded
+            return Ok(());
+        }
+        let checks = &self.publication_settings;
+        let has_max_drop = check_value(&checks.max_drop, doc_type).is_some();
+        if has_max_drop || check_value(&checks.min_docs, doc_type).is_some() {
+            let nb_docs = self.count(new_index, None)?;
+            let previous_nb_docs = if has_max_drop {
+                let mut previous_nb_docs = None;
+                for (index, aliases) in self.get_all_aliased_index(replaced_alias)? {
+                    if index != new_index && aliases.iter().any(|a| a == replaced_alias) {
+                        *previous_nb_docs.get_or_insert(0) += self.count(&index, None)?;
+                    }
+                }
+                previous_nb_docs
+            } else {
+                None
+            };
+            info!(
+                "{} documents in {}, {:?} in the index it replaces",
+                nb_docs, new_index, previous_nb_docs
+            );
+            checks.check_counts(doc_type, nb_docs, previous_nb_docs)?;
+        }
+        for query in checks
+            .smoke_queries
+            .iter()
+            .filter(|q| q.applies_to(doc_type))
+        {
+            let query = &query.value;
+            let nb_docs = self.count(new_index, Some(query))?;
+            ensure!(
+                nb_docs > 0,
+                "the smoke query {:?} matches no document of {}",
+                query,
+                new_index
+            );
         }
         Ok(())
     }
 
+    /// Mark an index as published in the `_meta` of its mapping, with its provenance if any
+    pub fn mark_published(
+        &self,
+        index: &str,
+        doc_type: &str,
+        provenance: Option<&Provenance>,
+    ) -> Result<(), Error> {
+        let mut meta = match provenance {
+            Some(provenance) => serde_json::to_value(provenance)?,
+            None => serde_json::json!({}),
+        };
+        meta[PUBLISHED_META] = true.into();
+        let body = serde_json::json!({ "_meta": meta });
+        self.put(
+            &format!("{}/_mapping/{}", index, doc_type),
+            &body.to_string(),
+        )
+        .with_context(|_| format!("Error occurred when marking {} as published", index))?;
+        Ok(())
+    }
+
+    /// The number of shards and replicas of an index (or of the index of an alias),
+    /// None if it does not exist
+    pub fn get_index_settings(&self, index: &str) -> Result<Option<IndexSettings>, Error> {
+        let res = self
+            .get(&format!("{}/_settings", index))
+            .with_context(|_| format!("Error occurred when getting the settings of {}", index))?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let settings: serde_json::Value = res.read_response()?;
+        Ok(read_index_settings(&settings))
+    }
+
+    /// The creation dates of the indexes matching a pattern
+    pub fn get_creation_dates(
+        &self,
+        indexes: &str,
+    ) -> Result<BTreeMap<String, chrono::DateTime<chrono::Utc>>, Error> {
+        let res = self
+            .get(&format!(
+                "{}/_settings?filter_path=*.settings.index.creation_date",
+                indexes
+            ))
+            .with_context(|_| format!("Error occurred when getting the settings of {}", indexes))?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(BTreeMap::new());
+        }
+        let settings: serde_json::Value = res.read_response()?;
+        Ok(read_creation_dates(&settings))
+    }
+
+    /// The indexes matching a pattern that have been published (cf `mark_published`)
+    fn get_published_indexes(&self, indexes: &str) -> Result<BTreeSet<String>, Error> {
+        if self.dump_dir.is_some() {
+            return Ok(BTreeSet::new());
+        }
+        let res = self
+            .get(&format!(
+                "{}/_mapping?filter_path=*.mappings.*._meta.{}",
+                indexes, PUBLISHED_META
+            ))
+            .with_context(|_| format!("Error occurred when getting the mappings of {}", indexes))?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(BTreeSet::new());
+        }
+        let mappings: serde_json::Value = res.read_response()?;
+        Ok(read_published_indexes(&mappings))
+    }
+
+    /// The provenances of the indexes matching a pattern (an index, an alias or a wildcard),
+    /// for the indexes having one
+    pub fn get_provenances(&self, indexes: &str) -> Result<BTreeMap<String, Provenance>, Error> {
+        let res = self
+            .get(&format!("{}/_mapping", indexes))
+            .with_context(|_| format!("Error occurred when getting the mappings of {}", indexes))?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(BTreeMap::new());
+        }
+        let mappings: serde_json::Value = res.read_response()?;
+        Ok(read_provenances(&mappings))
+    }
+
+    /// Number of documents of an index, or of the ones matching a query string
+    pub fn count(&self, index: &str, query: Option<&str>) -> Result<u64, Error> {
+        ensure!(
+            self.dump_dir.is_none(),
+            "the documents of {} cannot be counted in a dump",
+            index
+        );
+        let body = match query {
+            Some(query) => serde_json::json!({"query": {"query_string": {"query": query}}}),
+            None => serde_json::json!({"query": {"match_all": {}}}),
+        };
+        let res = self
+            .post(&format!("{}/_count", index), &body.to_string())
+            .with_context(|_| format!("Error occurred when counting the documents of {}", index))?;
+        let value: serde_json::Value = res.read_response()?;
+        value
+            .pointer("/count")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| format_err!("invalid count response for {}: {}", index, value))
+    }
+
     /// add a list of new indexes to the alias
     /// remove a list of indexes from the alias
     pub fn alias(&self, alias: &str, add: &[String], remove: &[String]) -> Result<(), Error> {
@@ -608,6 +1571,9 @@ impl Rubber {
             "for {}, adding alias {:?}, removing {:?}",
             alias, add, remove
         );
+        if self.dump_dir.is_some() {
+            return Ok(());
+        }
         let add_operations = add.iter().map(|x| AliasOperation {
             remove: None,
             add: Some(AliasParameter {
@@ -622,21 +1588,61 @@ impl Rubber {
                 alias: alias.to_string(),
             }),
         });
-        let operations = AliasOperations {
-            actions: add_operations.chain(remove_operations).collect(),
-        };
-        let json = serde_json::to_string(&operations)?;
+        self.post_aliases(add_operations.chain(remove_operations).collect())
+            .with_context(|err| format!("failed to post aliases for {}: {}", alias, err))?;
+        Ok(())
+    }
+
+    /// Move the aliases to a version, from the other versions holding them, in one request
+    fn move_aliases(
+        &self,
+        aliases: &[String],
+        version: &str,
+        versions: &[IndexVersion],
+    ) -> Result<(), Error> {
+        info!("moving the aliases {:?} to {}", aliases, version);
+        let add_operations = aliases.iter().map(|alias| AliasOperation {
+            remove: None,
+            add: Some(AliasParameter {
+                index: version.to_string(),
+                alias: alias.clone(),
+            }),
+        });
+        let remove_operations = versions.iter().filter(|v| v.name != version).flat_map(|v| {
+            v.aliases
+                .iter()
+                .filter(|alias| aliases.contains(alias))
+                .map(move |alias| AliasOperation {
+                    add: None,
+                    remove: Some(AliasParameter {
+                        index: v.name.clone(),
+                        alias: alias.clone(),
+                    }),
+                })
+        });
+        self.post_aliases(add_operations.chain(remove_operations).collect())
+    }
+
+    /// Apply the operations on the aliases, atomically
+    fn post_aliases(&self, actions: Vec<AliasOperation>) -> Result<(), Error> {
+        if self.dump_dir.is_some() {
+            return Ok(());
+        }
+        let json = serde_json::to_string(&AliasOperations { actions })?;
         let res = self
             .post("_aliases", &json)
             .context("Error occurred when POSTing: _alias")?;
         match res.status() {
             StatusCode::OK => Ok(()),
-            _ => bail!("failed to post aliases for {}: {:?}", alias, res),
+            _ => bail!("failed to post aliases: {:?}", res),
         }
     }
 
     pub fn delete_index(&mut self, index: &str) -> Result<(), Error> {
         debug!("deleting index {}", &index);
+        if self.dump_dir.is_some() {
+            return Ok(());
+        }
         let res = self
             .es_client
             .delete_index(&index)
@@ -657,6 +1663,10 @@ impl Rubber {
         use par_map::ParMap;
         use rs_es::operations::bulk::Action;
 
+        if let Some(ref dir) = self.dump_dir {
+            return dump::write_documents(dir, &index.name, iter);
+        }
+
         struct BulkResultCount {
             nb_ok: usize,
             nb_errors: usize,
@@ -745,6 +1755,35 @@ impl Rubber {
             .map(|counts| counts.nb_ok)
     }
 
+    /// Bulk index some raw lines, already in the format of the bulk api of elasticsearch
+    /// (like the lines of a dump), and return the number of indexed documents
+    pub fn bulk_index_raw(&self, index_name: &str, lines: &[String]) -> Result<usize, Error> {
+        let mut body = lines.join("\n");
+        body.push('\n');
+        let res = self
+            .post(&format!("{}/_bulk", index_name), &body)
+            .with_context(|_| format!("Error occurred when bulk indexing in {}", index_name))?;
+        let value: serde_json::Value = res.read_response()?;
+        let items = value
+            .pointer("/items")
+            .and_then(|items| items.as_array())
+            .ok_or_else(|| format_err!("invalid bulk response for {}", index_name))?;
+        let nb_errors = items
+            .iter()
+            .filter_map(|item| item.pointer("/index/error"))
+            .inspect(|error| {
+                warn!(
+                    "An error occured while importing in {}: {}",
+                    index_name, error
+                )
+            })
+            .count();
+        if nb_errors > self.max_bulk_errors {
+            bail!("too many errors during bulk_index in {}", index_name);
+        }
+        Ok(items.len() - nb_errors)
+    }
+
     /// Shortcut to `index` for a public index
     pub fn public_index<T, I>(
         &mut self,
@@ -809,6 +1848,10 @@ impl Rubber {
     where
         for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
     {
+        if let Some(ref dir) = self.dump_dir {
+            return dump::read_objects(dir, index)
+                .map_err(|e| EsError::EsError(format!("Impossible to read the dump: {}", e)));
+        }
         let mut result: Vec<T> = vec![];
         let mut scan: ScanResult<T> = self
             .es_client
@@ -833,6 +1876,102 @@ impl Rubber {
         scan.close(&mut self.es_client)?;
         Ok(result)
     }
+
+    /// Get the objects of an index whose `coord`, or geometry for the streets,
+    /// is in the bounding box
+    pub fn get_objects_in_bbox<T>(
+        &self,
+        index: &str,
+        top_left: &Coord,
+        bottom_right: &Coord,
+    ) -> Result<Vec<T>, Error>
+    where
+        for<'de> T: MimirObject + serde::de::Deserialize<'de>,
+    {
+        ensure!(
+            self.dump_dir.is_none(),
+            "the objects of {} cannot be searched in a dump",
+            index
+        );
+        // the streets are also matched on their geometry (indexed in `approx_coord`),
+        // as in the reverse geocoding
+        let body = serde_json::json!({
+            "size": 1000,
+            "query": {
+                "bool": {
+                    "filter": {
+                        "bool": {
+                            "should": [
+                                {
+                                    "geo_bounding_box": {
+                                        "coord": {
+                                            "top_left": {
+                                                "lat": top_left.lat(),
+                                                "lon": top_left.lon()
+                                            },
+                                            "bottom_right": {
+                                                "lat": bottom_right.lat(),
+                                                "lon": bottom_right.lon()
+                                            }
+                                        }
+                                    }
+                                },
+                                {
+                                    "geo_shape": {
+                                        "approx_coord": {
+                                            "shape": {
+                                                "type": "envelope",
+                                                "coordinates": [
+                                                    [top_left.lon(), top_left.lat()],
+                                                    [bottom_right.lon(), bottom_right.lat()]
+                                                ]
+                                            }
+                                        }
+                                    }
+                                }
+                            ]
+                        }
+                    }
+                }
+            }
+        });
+        let res = self
+            .post(
+                &format!(
+                    "{}/{}/_search?scroll=1m&ignore_unavailable=true",
+                    index,
+                    T::doc_type()
+                ),
+                &body.to_string(),
+            )
+            .with_context(|_| format!("Error occurred when searching in {}", index))?;
+        let mut page: serde_json::Value = res.read_response()?;
+        let mut result = vec![];
+        loop {
+            let hits = page
+                .pointer("/hits/hits")
+                .and_then(serde_json::Value::as_array)
+                .ok_or_else(|| format_err!("invalid search response for {}", index))?;
+            if hits.is_empty() {
+                break;
+            }
+            for hit in hits {
+                result.push(
+                    serde_json::from_value(hit["_source"].clone())
+                        .with_context(|_| format!("Invalid document in {}", index))?,
+                );
+            }
+            let scroll_id = page["_scroll_id"]
+                .as_str()
+                .ok_or_else(|| format_err!("no scroll id in the search response for {}", index))?;
+            let body = serde_json::json!({"scroll": "1m", "scroll_id": scroll_id});
+            let res = self
+                .post("_search/scroll", &body.to_string())
+                .with_context(|_| format!("Error occurred when scrolling in {}", index))?;
+            page = res.read_response()?;
+        }
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
@@ -840,6 +1979,161 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_is_index_version() {
+        assert!(is_index_version(
+            "munin_addr_fr_20201019_101112_123456789",
+            "munin_addr_fr"
+        ));
+        assert!(!is_index_version(
+            "munin_addr_fr_bis_20201019_101112_123456789",
+            "munin_addr_fr"
+        ));
+        assert!(!is_index_version("munin_addr_fr", "munin_addr_fr"));
+        assert!(!is_index_version("munin_addr_fr_20201019", "munin_addr_fr"));
+        assert!(is_index_version(
+            &get_date_index_name("munin_poi_fr"),
+            "munin_poi_fr"
+        ));
+    }
+
+    #[test]
+    fn test_parse_index_version() {
+        assert_eq!(
+            parse_index_version("munin_addr_fr_20201019_101112_123456789"),
+            Some(("addr", "fr".to_string()))
+        );
+        assert_eq!(
+            parse_index_version("munin_stop_point_idf_bis_20201019_101112_123456789"),
+            Some(("stop_point", "idf_bis".to_string()))
+        );
+        assert_eq!(
+            parse_index_version("munin_lieu_dit_fr_20201019_101112_123456789"),
+            Some(("lieu_dit", "fr".to_string()))
+        );
+        assert_eq!(
+            parse_index_version("munin_global_stops_20201019_101112_123456789"),
+            None
+        );
+        assert_eq!(parse_index_version("munin_addr_fr"), None);
+    }
+
+    #[test]
+    fn test_orphan_versions() {
+        let version = |name: &str, is_published| IndexVersion {
+            name: name.to_string(),
+            is_published,
+            ..Default::default()
+        };
+        let mut versions = vec![
+            version("munin_addr_fr_20201019_101112_4", false),
+            version("munin_addr_fr_20201019_101112_3", false),
+            version("munin_addr_fr_20201018_101112_2", true),
+            version("munin_addr_fr_20201017_101112_1", false),
+        ];
+        // a version rolled back from
+        versions[0].was_published = true;
+        let orphans: Vec<_> = orphan_versions(&versions)
+            .map(|v| v.name.as_str())
+            .collect();
+        assert_eq!(
+            orphans,
+            vec![
+                "munin_addr_fr_20201019_101112_3",
+                "munin_addr_fr_20201017_101112_1"
+            ]
+        );
+        assert_eq!(orphan_versions(&versions[2..3]).count(), 0);
+    }
+
+    #[test]
+    fn test_read_index_settings() {
+        let settings = serde_json::json!({
+            "munin_global_stops_1": {
+                "settings": {"index": {"number_of_shards": "2", "number_of_replicas": "1"}}
+            }
+        });
+        let settings = read_index_settings(&settings).unwrap();
+        assert_eq!((settings.nb_shards, settings.nb_replicas), (2, 1));
+        assert!(read_index_settings(&serde_json::json!({})).is_none());
+    }
+
+    #[test]
+    fn test_read_creation_dates() {
+        let settings = serde_json::json!({
+            "munin_addr_fr_1": {"settings": {"index": {"creation_date": "1603100000000"}}},
+            "munin_addr_fr_2": {"settings": {"index": {}}}
+        });
+        let dates = read_creation_dates(&settings);
+        assert_eq!(dates.len(), 1);
+        assert_eq!(dates["munin_addr_fr_1"].timestamp(), 1_603_100_000);
+    }
+
+    #[test]
+    fn test_pruned_versions() {
+        let version = |name: &str, is_published, was_published| IndexVersion {
+            name: name.to_string(),
+            is_published,
+            was_published,
+            ..Default::default()
+        };
+        let versions = vec![
+            // being filled by an import
+            version("munin_addr_fr_6", false, false),
+            // rolled back
+            version("munin_addr_fr_5", false, true),
+            version("munin_addr_fr_4", true, true),
+            // failed import
+            version("munin_addr_fr_3", false, false),
+            version("munin_addr_fr_2", false, true),
+            version("munin_addr_fr_1", false, true),
+        ];
+        let pruned = |keep| -> Vec<&str> {
+            pruned_versions(&versions, keep)
+                .into_iter()
+                .map(|v| v.name.as_str())
+                .collect()
+        };
+        assert_eq!(
+            pruned(0),
+            vec![
+                "munin_addr_fr_5",
+                "munin_addr_fr_3",
+                "munin_addr_fr_2",
+                "munin_addr_fr_1"
+            ]
+        );
+        assert_eq!(pruned(2), vec!["munin_addr_fr_3", "munin_addr_fr_1"]);
+        assert_eq!(pruned(3), vec!["munin_addr_fr_3"]);
+    }
+
+    #[test]
+    fn test_version_aliases() {
+        assert_eq!(
+            version_aliases("addr", "fr", true, IndexVisibility::Public),
+            vec!["munin_addr_fr", "munin_addr", "munin_geo_data", "munin"]
+        );
+        assert_eq!(
+            version_aliases("stop", "fr", false, IndexVisibility::Public),
+            vec!["munin_stop_fr", "munin_stop", "munin"]
+        );
+        assert_eq!(
+            version_aliases("poi", "fr", true, IndexVisibility::Private),
+            vec!["munin_poi_fr"]
+        );
+    }
+
+    #[test]
+    fn test_read_published_indexes() {
+        let mappings = serde_json::json!({
+            "munin_addr_fr_2": {"mappings": {"addr": {"_meta": {"published": true}}}},
+        });
+        let published: Vec<_> = read_published_indexes(&mappings).into_iter().collect();
+        assert_eq!(published, vec!["munin_addr_fr_2"]);
+        // the indexes without the field are filtered out of the response
+        assert!(read_published_indexes(&serde_json::json!({})).is_empty());
+    }
+
     #[test]
     pub fn test_valid_url() {
         Rubber::new("http://localhost:9200");
@@ -853,6 +2147,85 @@ mod tests {
         Rubber::new("localhost");
     }
 
+    #[test]
+    fn test_publication_checks() {
+        let checks = PublicationSettings {
+            min_docs: vec![DocTypeCheck::new(None, 100)],
+            max_drop: vec![DocTypeCheck::new(None, 0.2)],
+            ..Default::default()
+        };
+        assert!(checks.check_counts("addr", 1000, None).is_ok());
+        assert!(checks.check_counts("addr", 1000, Some(1200)).is_ok());
+        assert!(checks.check_counts("addr", 1000, Some(2000)).is_err());
+        assert!(checks.check_counts("addr", 50, None).is_err());
+        // an increase is always fine
+        assert!(checks.check_counts("addr", 1000, Some(100)).is_ok());
+        assert!(PublicationSettings::default()
+            .check_counts("addr", 0, Some(1000))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_doc_type_checks() {
+        let min_docs: Vec<DocTypeCheck<u64>> =
+            vec!["street=1000".parse().unwrap(), "10".parse().unwrap()];
+        assert_eq!(check_value(&min_docs, "street"), Some(1000));
+        assert_eq!(check_value(&min_docs, "admin"), Some(10));
+        assert!("street=many".parse::<DocTypeCheck<u64>>().is_err());
+
+        // a query string can contain a '='
+        let query: DocTypeCheck<String> = "name:\"a=b\"".parse().unwrap();
+        assert_eq!(query, DocTypeCheck::new(None, "name:\"a=b\"".to_string()));
+        let query: DocTypeCheck<String> = "poi=name:a".parse().unwrap();
+        assert_eq!(query, DocTypeCheck::new(Some("poi"), "name:a".to_string()));
+
+        let checks = PublicationSettings {
+            min_docs,
+            ..Default::default()
+        }
+        .scoped_to("addr");
+        assert!(checks.check_counts("addr", 50, None).is_err());
+        assert!(checks.check_counts("street", 50, None).is_err());
+        assert!(checks.check_counts("admin", 50, None).is_ok());
+    }
+
+    #[test]
+    pub fn test_dump_url() {
+        let dir = std::env::temp_dir().join(format!("mimir_rubber_dump_{}", std::process::id()));
+        let rubber = Rubber::new(&format!("file://{}", dir.display()));
+        assert_eq!(rubber.dump_dir, Some(dir.clone()));
+        assert!(rubber.count("munin_addr", None).is_err());
+        assert!(rubber.reverse(&Coord::new(2.3, 48.8), None).is_err());
+        // nothing is written before the first documents
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_get_features_indexes() {
+        assert_eq!(
+            get_features_indexes(false, &["fr"], &["mti"]),
+            vec![
+                "munin_geo_data",
+                "munin_stop_fr",
+                "munin_poi_mti",
+                "munin_stop_point_fr"
+            ]
+        );
+        assert_eq!(
+            get_features_indexes(false, &["fr", "be"], &[]),
+            vec![
+                "munin_geo_data",
+                "munin_global_stops",
+                "munin_stop_point_fr",
+                "munin_stop_point_be"
+            ]
+        );
+        assert_eq!(
+            get_features_indexes(true, &[], &[]),
+            vec!["munin", "munin_poi_*", "munin_stop_point_*"]
+        );
+    }
+
     #[test]
     fn test_get_indexes_impl() {
         // all_data
@@ -885,7 +2258,13 @@ mod tests {
                     "public_transport:stop_area",
                 ],
             ),
-            vec!["munin_poi", "munin_admin", "munin_street", "munin_addr"]
+            vec![
+                "munin_poi",
+                "munin_admin",
+                "munin_street",
+                "munin_lieu_dit",
+                "munin_addr"
+            ]
         );
 
         // no dataset fr + type public_transport:stop_area only
@@ -912,6 +2291,7 @@ mod tests {
                 "munin_poi",
                 "munin_admin",
                 "munin_street",
+                "munin_lieu_dit",
                 "munin_addr",
                 "munin_stop_fr",
             ]
@@ -921,7 +2301,36 @@ mod tests {
         //  => munin_stop_fr is not included
         assert_eq!(
             get_indexes(false, &["fr"], &[], &["poi", "city", "street", "house"],),
-            vec!["munin_poi", "munin_admin", "munin_street", "munin_addr"]
+            vec![
+                "munin_poi",
+                "munin_admin",
+                "munin_street",
+                "munin_lieu_dit",
+                "munin_addr"
+            ]
+        );
+
+        // dataset fr + types street and public_transport:stop_point
+        assert_eq!(
+            get_indexes(
+                false,
+                &["fr"],
+                &[],
+                &["street", "public_transport:stop_point"],
+            ),
+            vec!["munin_street", "munin_lieu_dit", "munin_stop_point_fr"]
+        );
+
+        // no dataset + type public_transport:stop_point only
+        assert_eq!(
+            get_indexes(false, &[], &[], &["public_transport:stop_point"]),
+            Vec::<String>::new()
+        );
+
+        // all_data + type public_transport:stop_point
+        assert_eq!(
+            get_indexes(true, &[], &[], &["public_transport:stop_point"]),
+            vec!["munin", "munin_poi_*", "munin_stop_point_*"]
         );
 
         // dataset fr with poi mti...
@@ -933,7 +2342,13 @@ mod tests {
                 &["mti"],
                 &["poi", "city", "street", "house"],
             ),
-            vec!["munin_admin", "munin_street", "munin_addr", "munin_poi_mti"]
+            vec![
+                "munin_admin",
+                "munin_street",
+                "munin_lieu_dit",
+                "munin_addr",
+                "munin_poi_mti"
+            ]
         );
     }
 }
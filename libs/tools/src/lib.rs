@@ -179,8 +179,9 @@ impl BragiHandler {
             actix_http::HttpService::new(
                 actix_web::App::new()
                     .data(ctx.clone())
-                    .wrap(actix_cors::Cors::new().allowed_methods(vec!["GET"]))
+                    .wrap(ctx.build_cors())
                     .wrap(prometheus.clone())
+                    .wrap(bragi::request_id::RequestIdHeader)
                     .wrap(actix_web::middleware::Logger::default())
                     .configure(bragi::server::configure_server)
                     .default_service(
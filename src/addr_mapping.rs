@@ -0,0 +1,413 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Import of addresses from any CSV/TSV file, the columns being described by a TOML mapping.
+//!
+//! ```toml
+//! delimiter = "\t"
+//! has_headers = true
+//! # the `{column}` are replaced by the value of the column
+//! id = "addr:{EGID}:{EDID}"
+//! street_id = "street:{ESID}"
+//!
+//! [columns]
+//! number = "DEINR"
+//! street = "STRNAME"
+//! postcode = "DPLZ4"
+//! city = "DPLZNAME"
+//! lat = 9 # a column can also be given by its index
+//! lon = 10
+//! ```
+
+use crate::admin_geofinder::AdminGeoFinder;
+use crate::rejects::{RejectReason, Rejection};
+use crate::{labels, utils, Error};
+use failure::{bail, format_err, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use std::path::Path;
+
+/// A column of the file, given by its name in the header or by its index
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Columns {
+    pub number: Column,
+    pub street: Column,
+    pub postcode: Option<Column>,
+    pub city: Option<Column>,
+    pub lat: Column,
+    pub lon: Column,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AddressMapping {
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_has_headers")]
+    pub has_headers: bool,
+    /// Template of the id of the addresses, by default it is built from the coordinates
+    /// and the house number
+    pub id: Option<String>,
+    /// Template of the id of the streets, by default it is built from the address id
+    pub street_id: Option<String>,
+    pub columns: Columns,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_has_headers() -> bool {
+    true
+}
+
+impl AddressMapping {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|_| format!("Impossible to read the mapping {:?}", path))?;
+        let mapping: AddressMapping =
+            toml::from_str(&content).with_context(|_| format!("Invalid mapping {:?}", path))?;
+        if !mapping.delimiter.is_ascii() {
+            bail!("the delimiter must be an ascii character");
+        }
+        Ok(mapping)
+    }
+
+    pub fn csv_reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter as u8)
+            .has_headers(self.has_headers)
+            .flexible(true);
+        builder
+    }
+
+    /// Resolve the columns of the mapping, with the given header if the file has one
+    pub fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<ColumnIndexes, Error> {
+        let find = |column: &Column| -> Result<usize, Error> {
+            match (column, headers) {
+                (Column::Index(idx), _) => Ok(*idx),
+                (Column::Name(name), Some(headers)) => headers
+                    .iter()
+                    .position(|h| h.trim() == name)
+                    .ok_or_else(|| format_err!("column {} not found in the header", name)),
+                (Column::Name(name), None) => Err(format_err!(
+                    "column {} is given by its name but the file has no header",
+                    name
+                )),
+            }
+        };
+        let parse_template = |template: &str| -> Result<Vec<TemplatePart>, Error> {
+            let mut parts = vec![];
+            let mut rest = template;
+            while let Some(start) = rest.find('{') {
+                let end = rest[start..]
+                    .find('}')
+                    .map(|end| start + end)
+                    .ok_or_else(|| format_err!("unclosed '{{' in the template {}", template))?;
+                if start > 0 {
+                    parts.push(TemplatePart::Literal(rest[..start].to_string()));
+                }
+                let name = &rest[start + 1..end];
+                let column = name
+                    .parse()
+                    .map(Column::Index)
+                    .unwrap_or_else(|_| Column::Name(name.to_string()));
+                parts.push(TemplatePart::Column(find(&column)?));
+                rest = &rest[end + 1..];
+            }
+            if !rest.is_empty() {
+                parts.push(TemplatePart::Literal(rest.to_string()));
+            }
+            Ok(parts)
+        };
+        Ok(ColumnIndexes {
+            number: find(&self.columns.number)?,
+            street: find(&self.columns.street)?,
+            postcode: self.columns.postcode.as_ref().map(find).transpose()?,
+            city: self.columns.city.as_ref().map(find).transpose()?,
+            lat: find(&self.columns.lat)?,
+            lon: find(&self.columns.lon)?,
+            id: self.id.as_deref().map(parse_template).transpose()?,
+            street_id: self.street_id.as_deref().map(parse_template).transpose()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Literal(String),
+    Column(usize),
+}
+
+/// The columns of a mapping, resolved to their index
+#[derive(Debug, Clone)]
+pub struct ColumnIndexes {
+    number: usize,
+    street: usize,
+    postcode: Option<usize>,
+    city: Option<usize>,
+    lat: usize,
+    lon: usize,
+    id: Option<Vec<TemplatePart>>,
+    street_id: Option<Vec<TemplatePart>>,
+}
+
+impl ColumnIndexes {
+    pub fn read(&self, record: &csv::StringRecord) -> Result<GenericAddress, Error> {
+        let get = |idx: usize| -> Result<String, Error> {
+            record
+                .get(idx)
+                .map(|v| v.trim().to_string())
+                .ok_or_else(|| format_err!("no column {} in line {:?}", idx, record))
+        };
+        let get_float = |idx: usize| -> Result<f64, Error> {
+            let value = get(idx)?;
+            value.replace(',', ".").parse::<f64>().map_err(|_| {
+                Rejection::new(
+                    RejectReason::InvalidCoord,
+                    format!("invalid coordinate {}", value),
+                )
+                .into()
+            })
+        };
+        let render = |template: &Vec<TemplatePart>| -> Result<String, Error> {
+            template
+                .iter()
+                .map(|part| match part {
+                    TemplatePart::Literal(s) => Ok(s.clone()),
+                    TemplatePart::Column(idx) => get(*idx),
+                })
+                .collect()
+        };
+        Ok(GenericAddress {
+            id: self.id.as_ref().map(render).transpose()?,
+            street_id: self.street_id.as_ref().map(render).transpose()?,
+            number: get(self.number)?,
+            street: get(self.street)?,
+            postcode: self.postcode.map(get).transpose()?,
+            city: self.city.map(get).transpose()?,
+            lat: get_float(self.lat)?,
+            lon: get_float(self.lon)?,
+        })
+    }
+}
+
+/// An address read from a file with an `AddressMapping`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GenericAddress {
+    pub id: Option<String>,
+    pub street_id: Option<String>,
+    pub number: String,
+    pub street: String,
+    pub postcode: Option<String>,
+    pub city: Option<String>,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl GenericAddress {
+    pub fn into_addr(
+        self,
+        admins_geofinder: &AdminGeoFinder,
+        id_precision: usize,
+    ) -> Result<mimir::Addr, Error> {
+        let admins = admins_geofinder.get(&geo::Coordinate {
+            x: self.lon,
+            y: self.lat,
+        });
+        let country_codes = utils::find_country_codes(admins.iter().map(|a| a.deref()));
+        let weight = admins.iter().find(|a| a.is_city()).map_or(0., |a| a.weight);
+
+        // as for bano, the city of the file is used for the labels when given
+        let city = self
+            .city
+            .as_ref()
+            .filter(|c| !c.is_empty())
+            .map(|c| mimir::Admin {
+                name: c.to_string(),
+                zone_type: Some(cosmogony::ZoneType::City),
+                ..Default::default()
+            });
+        let zones_for_label_formatting = admins
+            .iter()
+            .map(|a| a.deref())
+            .filter(|a| city.is_none() || !a.is_city())
+            .chain(city.iter());
+
+        let street_label = labels::format_street_label(
+            &self.street,
+            zones_for_label_formatting.clone(),
+            &country_codes,
+        );
+        let (addr_name, addr_label) = labels::format_addr_name_and_label(
+            &self.number,
+            &self.street,
+            zones_for_label_formatting,
+            &country_codes,
+        );
+
+        let id = self.id.unwrap_or_else(|| {
+            format!(
+                "addr:{:.precision$};{:.precision$}:{}",
+                self.lon,
+                self.lat,
+                utils::normalize_house_number_id(&self.number),
+                precision = id_precision
+            )
+        });
+        let street_id = self
+            .street_id
+            .unwrap_or_else(|| format!("street:{}", id.trim_start_matches("addr:")));
+
+        let zip_codes: Vec<_> = self
+            .postcode
+            .iter()
+            .flat_map(|p| p.split(';'))
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .collect();
+        let coord = mimir::Coord::new(self.lon, self.lat);
+        let street = mimir::Street {
+            id: street_id,
+            name: self.street,
+            label: street_label,
+            names: mimir::I18nProperties::default(),
+            labels: mimir::I18nProperties::default(),
+            aliases: vec![],
+            administrative_regions: admins,
+            weight,
+            zip_codes: zip_codes.clone(),
+            coord,
+            approx_coord: None,
+            geometry: None,
+            distance: None,
+            country_codes: country_codes.clone(),
+            context: None,
+        };
+
+        Ok(mimir::Addr {
+            id,
+            name: addr_name,
+            label: addr_label,
+            house_number: self.number,
+            street,
+            coord,
+            approx_coord: Some(coord.into()),
+            weight,
+            zip_codes,
+            distance: None,
+            country_codes,
+            context: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_with_mapping() {
+        let mapping =
+            AddressMapping::from_path(Path::new("./tests/fixtures/generic_addresses/mapping.toml"))
+                .unwrap();
+        assert_eq!(mapping.delimiter, '\t');
+
+        let mut rdr = mapping
+            .csv_reader_builder()
+            .from_path("./tests/fixtures/generic_addresses/addresses.tsv")
+            .unwrap();
+        let indexes = mapping.resolve(Some(rdr.headers().unwrap())).unwrap();
+        let addresses: Vec<_> = rdr
+            .records()
+            .map(|r| indexes.read(&r.unwrap()).unwrap())
+            .collect();
+
+        assert_eq!(addresses.len(), 2);
+        let address = &addresses[0];
+        assert_eq!(address.id, Some("addr:190365:1".to_string()));
+        assert_eq!(address.street_id, Some("street:10004217".to_string()));
+        assert_eq!(address.number, "12a");
+        assert_eq!(address.street, "Bahnhofstrasse");
+        assert_eq!(address.postcode, Some("8001".to_string()));
+        assert_eq!(address.city, Some("Zürich".to_string()));
+        assert_float_eq::assert_f64_near!(address.lat, 47.3769);
+        assert_float_eq::assert_f64_near!(address.lon, 8.5397);
+
+        let addr = addresses
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_addr(&AdminGeoFinder::default(), 6)
+            .unwrap();
+        assert_eq!(addr.id, "addr:190365:1");
+        assert_eq!(addr.house_number, "12a");
+        assert_eq!(addr.street.id, "street:10004217");
+        assert_eq!(addr.zip_codes, vec!["8001"]);
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let mapping: AddressMapping = toml::from_str(
+            r#"
+            has_headers = false
+            [columns]
+            number = 0
+            street = "street"
+            lat = 2
+            lon = 3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(mapping.columns.number, Column::Index(0));
+        assert!(mapping.resolve(None).is_err());
+
+        let headers = csv::StringRecord::from(vec!["nb", "street", "lat", "lon"]);
+        let indexes = mapping.resolve(Some(&headers)).unwrap();
+        let address = indexes
+            .read(&csv::StringRecord::from(vec![
+                "3",
+                "Main street",
+                "1,5",
+                "2.5",
+            ]))
+            .unwrap();
+        assert_float_eq::assert_f64_near!(address.lat, 1.5);
+        assert_eq!(address.id, None);
+        assert_eq!(address.city, None);
+    }
+}
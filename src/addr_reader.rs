@@ -1,3 +1,5 @@
+use crate::addr_mapping::{AddressMapping, GenericAddress};
+use crate::rejects::{self, RejectReason, Rejects};
 use crate::Error;
 use failure::ResultExt;
 use flate2::read::GzDecoder;
@@ -5,6 +7,7 @@ use mimir::rubber::{IndexSettings, IndexVisibility, Rubber};
 use mimir::Addr;
 use par_map::ParMap;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use slog_scope::{error, info, warn};
 use std::collections::HashMap;
 use std::fs::File;
@@ -12,17 +15,20 @@ use std::io::Read;
 use std::marker::{Send, Sync};
 use std::path::PathBuf;
 
-fn import_addresses<T, F>(
+/// Index the addresses in a new index of the dataset, and publish it.
+/// The addresses that can't be converted are written in the rejects.
+pub fn import_addresses<T, F>(
     rubber: &mut Rubber,
     nb_threads: usize,
     index_settings: IndexSettings,
     dataset: &str,
     addresses: impl IntoIterator<Item = T>,
     into_addr: F,
+    rejects: &Rejects,
 ) -> Result<(), Error>
 where
     F: Fn(T) -> Result<Addr, Error> + Send + Sync + 'static,
-    T: DeserializeOwned + Send + 'static,
+    T: Serialize + Send + 'static,
 {
     let addr_index = rubber
         .make_index(dataset, &index_settings)
@@ -32,14 +38,24 @@ where
 
     let mut country_stats = HashMap::new();
 
+    // the input record is only kept to be written in the rejects
+    let record_rejects = rejects.clone();
     let iter = addresses
         .into_iter()
         .with_nb_threads(nb_threads)
-        .par_map(into_addr)
-        .filter_map(|ra| match ra {
+        .par_map(move |address| {
+            let record = record_rejects.record_of(&address);
+            (into_addr(address), record)
+        })
+        .filter_map(|(ra, record)| match ra {
             Ok(a) => {
                 if a.street.name.is_empty() {
                     warn!("Address {} has no street name and has been ignored.", a.id);
+                    rejects.reject(
+                        RejectReason::MissingStreet,
+                        &record,
+                        format!("address {} has no street name", a.id),
+                    );
                     None
                 } else {
                     Some(a)
@@ -47,6 +63,7 @@ where
             }
             Err(err) => {
                 warn!("Address Error ignored: {}", err);
+                rejects.reject_error(RejectReason::InvalidData, &record, &err);
                 None
             }
         })
@@ -91,25 +108,28 @@ pub fn import_addresses_from_streams<T, F>(
     dataset: &str,
     streams: impl IntoIterator<Item = impl Read>,
     into_addr: F,
+    rejects: &Rejects,
 ) -> Result<(), Error>
 where
     F: Fn(T) -> Result<Addr, Error> + Send + Sync + 'static,
-    T: DeserializeOwned + Send + 'static,
+    T: DeserializeOwned + Serialize + Send + 'static,
 {
-    let iter = streams
-        .into_iter()
-        .flat_map(|stream| {
-            csv::ReaderBuilder::new()
-                .has_headers(has_headers)
-                .from_reader(stream)
-                .into_deserialize()
-        })
-        .filter_map(|line| {
-            line.map_err(|e| warn!("Impossible to read line, error: {}", e))
-                .ok()
-        });
+    let iter = streams.into_iter().flat_map(|stream| {
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(has_headers)
+            .from_reader(stream);
+        rejects::deserialize_records(reader, rejects.clone())
+    });
 
-    import_addresses(rubber, nb_threads, index_settings, dataset, iter, into_addr)
+    import_addresses(
+        rubber,
+        nb_threads,
+        index_settings,
+        dataset,
+        iter,
+        into_addr,
+        rejects,
+    )
 }
 
 pub fn import_addresses_from_files<T, F>(
@@ -120,12 +140,93 @@ pub fn import_addresses_from_files<T, F>(
     dataset: &str,
     files: impl IntoIterator<Item = PathBuf>,
     into_addr: F,
+    rejects: &Rejects,
 ) -> Result<(), Error>
 where
     F: Fn(T) -> Result<Addr, Error> + Send + Sync + 'static,
-    T: DeserializeOwned + Send + 'static,
+    T: DeserializeOwned + Serialize + Send + 'static,
+{
+    import_addresses_from_streams(
+        rubber,
+        has_headers,
+        nb_threads,
+        index_settings,
+        dataset,
+        open_files(files),
+        into_addr,
+        rejects,
+    )
+}
+
+/// Import the addresses of csv files whose columns are described by an `AddressMapping`
+pub fn import_addresses_with_mapping<F>(
+    rubber: &mut Rubber,
+    nb_threads: usize,
+    index_settings: IndexSettings,
+    dataset: &str,
+    mapping: &AddressMapping,
+    streams: impl IntoIterator<Item = impl Read>,
+    into_addr: F,
+    rejects: &Rejects,
+) -> Result<(), Error>
+where
+    F: Fn(GenericAddress) -> Result<Addr, Error> + Send + Sync + 'static,
 {
-    let streams = files.into_iter().filter_map(|path| {
+    let line_rejects = rejects.clone();
+    let iter = streams
+        .into_iter()
+        .filter_map(|stream| {
+            let mut reader = mapping.csv_reader_builder().from_reader(stream);
+            let headers = if mapping.has_headers {
+                match reader.headers() {
+                    Ok(headers) => Some(headers.clone()),
+                    Err(err) => {
+                        error!("Impossible to read the header, error: {}", err);
+                        return None;
+                    }
+                }
+            } else {
+                None
+            };
+            mapping
+                .resolve(headers.as_ref())
+                .map_err(|err| error!("Impossible to use the mapping, error: {}", err))
+                .ok()
+                .map(|indexes| (reader, indexes))
+        })
+        .flat_map(move |(reader, indexes)| {
+            let line_rejects = line_rejects.clone();
+            reader.into_records().filter_map(move |line| match line {
+                Ok(record) => indexes
+                    .read(&record)
+                    .map_err(|e| {
+                        warn!("Impossible to read line, error: {}", e);
+                        let fields: Vec<_> = record.iter().collect();
+                        line_rejects.reject_error(RejectReason::InvalidRecord, &fields, &e)
+                    })
+                    .ok(),
+                Err(e) => {
+                    warn!("Impossible to read line, error: {}", e);
+                    line_rejects.reject(RejectReason::InvalidRecord, &serde_json::Value::Null, e);
+                    None
+                }
+            })
+        });
+
+    import_addresses(
+        rubber,
+        nb_threads,
+        index_settings,
+        dataset,
+        iter,
+        into_addr,
+        rejects,
+    )
+}
+
+/// Open the files, the ones with a `.gz` extension being decompressed on the fly
+pub fn open_files(files: impl IntoIterator<Item = PathBuf>) -> impl Iterator<Item = Box<dyn Read>> {
+    files.into_iter().filter_map(|path| {
         info!("importing {:?}...", &path);
 
         let with_gzip = path
@@ -145,15 +246,5 @@ where
                 }
             })
             .ok()
-    });
-
-    import_addresses_from_streams(
-        rubber,
-        has_headers,
-        nb_threads,
-        index_settings,
-        dataset,
-        streams,
-        into_addr,
-    )
+    })
 }
@@ -0,0 +1,503 @@
+// Copyright © 2020, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! In memory reverse geocoding
+//!
+//! The importers need the address of each object they import (pois for example). Instead of
+//! querying Elasticsearch for each object, the addresses and streets around the objects are
+//! loaded once in an RTree.
+//!
+//! As in the reverse geocoding of Elasticsearch, the streets are located by the segments of
+//! their geometry, so that the nearest street is the same with both.
+
+use crate::Error;
+use failure::ResultExt;
+use geo::algorithm::closest_point::ClosestPoint;
+use geo::algorithm::haversine_distance::HaversineDistance;
+use mimir::rubber::{get_main_type_index, Rubber};
+use mimir::{Addr, Address, Coord, LieuDit, MimirObject, Place, Street};
+use rstar::primitives::Rectangle;
+use rstar::{Envelope, RTree, RTreeObject, AABB};
+use slog_scope::info;
+use std::collections::{BTreeMap, HashMap};
+use std::iter::FromIterator;
+use std::path::Path;
+
+/// Same maximum distance as the reverse geocoding in Elasticsearch (cf `build_reverse_query`)
+const MAX_DISTANCE_METERS: f64 = 1000.;
+const METERS_PER_DEGREE: f64 = 111_320.;
+/// Side of the tiles in which the objects are grouped to load their addresses, in degrees
+const TILE_DEGREES: f64 = 0.1;
+
+// Where an address is, as stored in the RTree
+#[derive(Clone, Copy, Debug)]
+enum Location {
+    // the coord of an address, or of a street without geometry
+    Point([f64; 2]),
+    // a segment of the geometry of a street
+    Segment([f64; 2], [f64; 2]),
+}
+
+impl Location {
+    fn envelope(&self) -> AABB<[f64; 2]> {
+        match *self {
+            Location::Point(point) => AABB::from_point(point),
+            Location::Segment(start, end) => AABB::from_corners(start, end),
+        }
+    }
+
+    // Distance in meters, computed as `Street::distance_to` for the segments
+    fn distance_to(&self, point: &geo::Point<f64>) -> f64 {
+        match *self {
+            Location::Point(p) => point.haversine_distance(&geo::Point::new(p[0], p[1])),
+            Location::Segment(start, end) => {
+                let line = geo::Line::new((start[0], start[1]), (end[0], end[1]));
+                match line.closest_point(point) {
+                    geo::Closest::Intersection(p) | geo::Closest::SinglePoint(p) => {
+                        point.haversine_distance(&p)
+                    }
+                    geo::Closest::Indeterminate => {
+                        point.haversine_distance(&geo::Point::new(start[0], start[1]))
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn coord_location(coord: &Coord) -> Location {
+    Location::Point([coord.lon(), coord.lat()])
+}
+
+fn street_locations(street: &Street) -> Vec<Location> {
+    let segments: Vec<_> = street
+        .geometry
+        .iter()
+        .flat_map(|geometry| geometry.0.iter())
+        .flat_map(|line| line.lines())
+        .map(|segment| {
+            Location::Segment(
+                [segment.start.x, segment.start.y],
+                [segment.end.x, segment.end.y],
+            )
+        })
+        .collect();
+    if segments.is_empty() {
+        vec![coord_location(&street.coord)]
+    } else {
+        segments
+    }
+}
+
+fn address_locations(address: &Address) -> Vec<Location> {
+    match address {
+        Address::Street(street) => street_locations(street),
+        Address::Addr(addr) => vec![coord_location(&addr.coord)],
+    }
+}
+
+// This is the object stored in the RTree: a location of an address,
+// with the position of the address in `AddressGeoFinder::addresses`
+struct LocatedAddress {
+    location: Location,
+    address: usize,
+}
+
+impl RTreeObject for LocatedAddress {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.location.envelope()
+    }
+}
+
+// The area in which the addresses are searched around a coord
+fn search_envelope(coord: &Coord) -> AABB<[f64; 2]> {
+    let delta_lat = MAX_DISTANCE_METERS / METERS_PER_DEGREE;
+    // the degrees of longitude are shorter far from the equator
+    let delta_lon = delta_lat / coord.lat().to_radians().cos().max(0.01);
+    AABB::from_corners(
+        [coord.lon() - delta_lon, coord.lat() - delta_lat],
+        [coord.lon() + delta_lon, coord.lat() + delta_lat],
+    )
+}
+
+/// The area where the addresses of some objects can be found.
+///
+/// The objects are grouped by tiles of `TILE_DEGREES`, the area of a tile being the envelope
+/// of the search envelopes of its objects. So the loaded addresses only depend on where the
+/// objects are, not on the extent of the whole set of objects.
+pub struct SearchArea {
+    tiles: RTree<Rectangle<[f64; 2]>>,
+}
+
+impl SearchArea {
+    pub fn around<'a>(coords: impl IntoIterator<Item = &'a Coord>) -> Self {
+        let mut tiles: HashMap<(i64, i64), AABB<[f64; 2]>> = HashMap::new();
+        for coord in coords {
+            let tile = (
+                (coord.lon() / TILE_DEGREES).floor() as i64,
+                (coord.lat() / TILE_DEGREES).floor() as i64,
+            );
+            let envelope = search_envelope(coord);
+            tiles
+                .entry(tile)
+                .and_modify(|area| *area = area.merged(&envelope))
+                .or_insert(envelope);
+        }
+        SearchArea {
+            tiles: RTree::bulk_load(
+                tiles
+                    .values()
+                    .map(|area| Rectangle::from_corners(area.lower(), area.upper()))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.size() == 0
+    }
+
+    /// The areas of the tiles, that can overlap
+    pub fn tiles(&self) -> impl Iterator<Item = AABB<[f64; 2]>> + '_ {
+        self.tiles.iter().map(|tile| tile.envelope())
+    }
+
+    fn contains(&self, locations: &[Location]) -> bool {
+        locations.iter().any(|location| {
+            self.tiles
+                .locate_in_envelope_intersecting(&location.envelope())
+                .next()
+                .is_some()
+        })
+    }
+}
+
+pub struct AddressGeoFinder {
+    addresses: Vec<Address>,
+    rtree: RTree<LocatedAddress>,
+}
+
+impl AddressGeoFinder {
+    /// Load the published addresses and streets of the area, tile by tile.
+    /// Only the area is loaded, so that the memory does not depend on the size of the cluster.
+    pub fn from_rubber(rubber: &Rubber, area: &SearchArea) -> Result<Self, Error> {
+        // the tiles can overlap, the objects are deduplicated by id
+        let mut addrs = BTreeMap::new();
+        let mut streets = BTreeMap::new();
+        for tile in area.tiles() {
+            // the bounding box of elasticsearch goes from the top left corner
+            // to the bottom right one
+            let top_left = Coord::new(tile.lower()[0], tile.upper()[1]);
+            let bottom_right = Coord::new(tile.upper()[0], tile.lower()[1]);
+            let tile_addrs: Vec<Addr> = rubber
+                .get_objects_in_bbox(&get_main_type_index::<Addr>(), &top_left, &bottom_right)
+                .context("Could not retrieve the addresses from Elasticsearch")?;
+            let tile_streets: Vec<Street> = rubber
+                .get_objects_in_bbox(&get_main_type_index::<Street>(), &top_left, &bottom_right)
+                .context("Could not retrieve the streets from Elasticsearch")?;
+            let tile_lieux_dits: Vec<LieuDit> = rubber
+                .get_objects_in_bbox(&get_main_type_index::<LieuDit>(), &top_left, &bottom_right)
+                .context("Could not retrieve the lieux-dits from Elasticsearch")?;
+            addrs.extend(tile_addrs.into_iter().map(|addr| (addr.id.clone(), addr)));
+            streets.extend(
+                tile_streets
+                    .into_iter()
+                    .chain(tile_lieux_dits.into_iter().map(|lieu_dit| lieu_dit.0))
+                    .map(|street| (street.id.clone(), street)),
+            );
+        }
+        info!(
+            "{} addresses and {} streets retrieved from Elasticsearch",
+            addrs.len(),
+            streets.len()
+        );
+        Ok(Self::from_addrs_and_streets(
+            addrs.into_iter().map(|(_, addr)| addr).collect(),
+            streets.into_iter().map(|(_, street)| street).collect(),
+        ))
+    }
+
+    /// Load the addresses and streets of the area from a dump directory, as written by the
+    /// importers when their connection string is a `file://` url (cf `mimir::dump`).
+    pub fn from_dump(dir: &Path, area: &SearchArea) -> Result<Self, Error> {
+        let addrs: Vec<Addr> = mimir::dump::read_objects_filtered(
+            dir,
+            &format!("munin_{}", Addr::doc_type()),
+            |addr: &Addr| area.contains(&[coord_location(&addr.coord)]),
+        )
+        .with_context(|e| format!("Could not read the addresses from {:?}: {}", dir, e))?;
+        let mut streets: Vec<Street> = mimir::dump::read_objects_filtered(
+            dir,
+            &format!("munin_{}", Street::doc_type()),
+            |street: &Street| area.contains(&street_locations(street)),
+        )
+        .with_context(|e| format!("Could not read the streets from {:?}: {}", dir, e))?;
+        let lieux_dits: Vec<LieuDit> = mimir::dump::read_objects_filtered(
+            dir,
+            &format!("munin_{}", LieuDit::doc_type()),
+            |lieu_dit: &LieuDit| area.contains(&street_locations(&lieu_dit.0)),
+        )
+        .with_context(|e| format!("Could not read the lieux-dits from {:?}: {}", dir, e))?;
+        streets.extend(lieux_dits.into_iter().map(|lieu_dit| lieu_dit.0));
+        info!(
+            "{} addresses and {} streets read from {:?}",
+            addrs.len(),
+            streets.len(),
+            dir
+        );
+        Ok(Self::from_addrs_and_streets(addrs, streets))
+    }
+
+    /// Load the addresses and streets around the coords, from the dump directory if given,
+    /// otherwise from the dump of the rubber in dump mode, or from Elasticsearch
+    pub fn load_around<'a>(
+        rubber: &Rubber,
+        dump: Option<&Path>,
+        coords: impl IntoIterator<Item = &'a Coord>,
+    ) -> Result<Self, Error> {
+        let area = SearchArea::around(coords);
+        if area.is_empty() {
+            return Ok(std::iter::empty().collect());
+        }
+        match dump.or_else(|| rubber.dump_dir.as_deref()) {
+            Some(dir) => {
+                info!("Loading addresses from {:?}", dir);
+                Self::from_dump(dir, &area)
+            }
+            None => {
+                info!("Loading addresses from Elasticsearch");
+                Self::from_rubber(rubber, &area)
+            }
+        }
+    }
+
+    fn from_addrs_and_streets(addrs: Vec<Addr>, streets: Vec<Street>) -> Self {
+        addrs
+            .into_iter()
+            .map(Address::Addr)
+            .chain(streets.into_iter().map(Address::Street))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the nearest address or street of the coord, in 1km.
+    /// The distance to the coord is set in the returned place.
+    pub fn get(&self, coord: &Coord) -> Option<Place> {
+        let point = geo::Point::new(coord.lon(), coord.lat());
+        self.rtree
+            .locate_in_envelope_intersecting(&search_envelope(coord))
+            .map(|located| (located.location.distance_to(&point), located.address))
+            .filter(|(distance, _)| *distance <= MAX_DISTANCE_METERS)
+            .min_by(|(d1, _), (d2, _)| d1.partial_cmp(d2).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(distance, address)| {
+                let mut place = match &self.addresses[address] {
+                    Address::Street(street) => Place::Street(street.clone()),
+                    Address::Addr(addr) => Place::Addr(addr.clone()),
+                };
+                place.set_distance(distance as u32);
+                place
+            })
+    }
+}
+
+impl FromIterator<Address> for AddressGeoFinder {
+    fn from_iter<I: IntoIterator<Item = Address>>(iter: I) -> Self {
+        let mut addresses = vec![];
+        let mut located = vec![];
+        for mut address in iter {
+            located.extend(address_locations(&address).into_iter().map(|location| {
+                LocatedAddress {
+                    location,
+                    address: addresses.len(),
+                }
+            }));
+            // the geometry of the streets is not needed in the addresses of the objects
+            if let Address::Street(ref mut street) = address {
+                street.geometry = None;
+            }
+            addresses.push(address);
+        }
+        AddressGeoFinder {
+            addresses,
+            rtree: RTree::bulk_load(located),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mimir::I18nProperties;
+
+    fn addr(id: &str, lon: f64, lat: f64) -> Address {
+        Address::Addr(Addr {
+            id: id.to_string(),
+            name: String::new(),
+            house_number: "1".to_string(),
+            street: Street {
+                id: format!("street:{}", id),
+                name: "Rue du Test".to_string(),
+                label: String::new(),
+                administrative_regions: vec![],
+                names: I18nProperties::default(),
+                labels: I18nProperties::default(),
+                aliases: vec![],
+                weight: 0.,
+                zip_codes: vec![],
+                coord: Coord::new(lon, lat),
+                approx_coord: None,
+                geometry: None,
+                distance: None,
+                country_codes: vec![],
+                context: None,
+            },
+            label: String::new(),
+            coord: Coord::new(lon, lat),
+            approx_coord: None,
+            weight: 0.,
+            zip_codes: vec![],
+            distance: None,
+            country_codes: vec![],
+            context: None,
+        })
+    }
+
+    #[test]
+    fn nearest_address() {
+        let finder: AddressGeoFinder = vec![
+            addr("addr:far", 2.36, 48.85),
+            addr("addr:near", 2.3502, 48.85),
+            addr("addr:nearer", 2.35, 48.8501),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(finder.len(), 3);
+
+        let place = finder.get(&Coord::new(2.35, 48.85)).unwrap();
+        assert_eq!(place.id(), "addr:nearer");
+        assert_eq!(place.distance(), Some(11));
+
+        // nothing in 1km
+        assert!(finder.get(&Coord::new(2.40, 48.85)).is_none());
+    }
+
+    #[test]
+    fn nearest_street_by_geometry() {
+        let street = |id: &str, coords: Vec<(f64, f64)>| {
+            let mut street = match addr(id, coords[0].0, coords[0].1) {
+                Address::Addr(addr) => addr.street,
+                Address::Street(street) => street,
+            };
+            street.id = id.to_string();
+            street.set_geometry(geo::MultiLineString(vec![coords.into()]));
+            Address::Street(street)
+        };
+        let finder: AddressGeoFinder = vec![
+            // a long street whose coord is 3km away, but passing 10m from the coord
+            street("street:long", vec![(2.31, 48.8501), (2.39, 48.8501)]),
+            addr("addr:near", 2.3502, 48.85),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(finder.len(), 2);
+
+        let place = finder.get(&Coord::new(2.35, 48.85)).unwrap();
+        assert_eq!(place.id(), "street:long");
+        assert_eq!(place.distance(), Some(11));
+        // the geometry is not kept in the address
+        match place {
+            Place::Street(street) => assert!(street.geometry.is_none()),
+            _ => panic!("a street is expected"),
+        }
+    }
+
+    #[test]
+    fn search_area_by_tiles() {
+        let area = SearchArea::around(&[
+            Coord::new(2.35, 48.85),
+            Coord::new(2.351, 48.85),
+            Coord::new(5.37, 43.29),
+        ]);
+        // the area between the objects is not searched
+        assert_eq!(area.tiles().count(), 2);
+        assert!(area.contains(&[coord_location(&Coord::new(2.355, 48.85))]));
+        assert!(!area.contains(&[coord_location(&Coord::new(4., 46.))]));
+        assert!(SearchArea::around(&[]).is_empty());
+    }
+
+    #[test]
+    fn addresses_of_the_area_from_dump() {
+        use mimir::dump::{publish, write_documents, DumpMetadata};
+        use mimir::rubber::IndexVisibility;
+
+        let dir =
+            std::env::temp_dir().join(format!("address_geofinder_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = "munin_addr_fr_20200101_000000_000000";
+        let addrs = vec![addr("addr:a", 2.35, 48.85), addr("addr:far", 3.35, 48.85)]
+            .into_iter()
+            .filter_map(|address| match address {
+                Address::Addr(addr) => Some(addr),
+                Address::Street(_) => None,
+            });
+        write_documents(&dir, index, addrs).unwrap();
+        publish(
+            &dir,
+            &DumpMetadata {
+                index: index.to_string(),
+                dataset: "fr".to_string(),
+                doc_type: Addr::doc_type().to_string(),
+                is_geo_data: true,
+                visibility: IndexVisibility::Public,
+                provenance: None,
+            },
+        )
+        .unwrap();
+
+        let area = SearchArea::around(&[Coord::new(2.351, 48.85)]);
+        let loaded = AddressGeoFinder::from_dump(&dir, &area).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        // the address far from the area is not loaded
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(
+            loaded.get(&Coord::new(2.351, 48.85)).unwrap().id(),
+            "addr:a"
+        );
+    }
+}
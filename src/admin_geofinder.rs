@@ -36,7 +36,7 @@ use geo_types::{MultiPolygon, Point};
 use mimir::Admin;
 use rstar::{Envelope, PointDistance, RTree, RTreeObject, SelectionFunction, AABB};
 use slog_scope::{info, warn};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::iter::FromIterator;
 use std::sync::Arc;
 
@@ -262,6 +262,16 @@ impl AdminGeoFinder {
             admin
         })
     }
+
+    /// Return the languages of the admins' names, sorted and deduplicated.
+    pub fn langs(&self) -> Vec<String> {
+        let langs: BTreeSet<&str> = self
+            .admin_by_id
+            .values()
+            .flat_map(|admin| admin.names.0.iter().map(|p| p.key.as_str()))
+            .collect();
+        langs.into_iter().map(str::to_string).collect()
+    }
 }
 
 impl Default for AdminGeoFinder {
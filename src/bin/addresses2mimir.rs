@@ -0,0 +1,168 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use failure::ResultExt;
+use lazy_static::lazy_static;
+use mimir::rubber::{IndexSettings, Rubber};
+use mimirsbrunn::addr_mapping::{AddressMapping, GenericAddress};
+use mimirsbrunn::addr_reader::{import_addresses_with_mapping, open_files};
+use mimirsbrunn::rejects::RejectsArgs;
+use mimirsbrunn::utils::PublicationArgs;
+use slog_scope::{info, warn};
+use std::io::stdin;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+lazy_static! {
+    static ref DEFAULT_NB_THREADS: String = num_cpus::get().to_string();
+}
+
+#[derive(StructOpt, Debug)]
+struct Args {
+    /// CSV or TSV files, optionally gzipped. Can be either a directory or a file.
+    /// If this is left empty, addresses are read from standard input.
+    #[structopt(short = "i", long = "input", parse(from_os_str))]
+    input: Option<PathBuf>,
+    /// TOML file describing the delimiter, the columns and the ids of the addresses.
+    #[structopt(short = "m", long = "mapping", parse(from_os_str))]
+    mapping: PathBuf,
+    /// Float precision for coordinates used to define the `id` field of addresses,
+    /// when no id template is given in the mapping.
+    /// Set to 0 to use exact coordinates.
+    #[structopt(short = "p", long = "id-precision", default_value = "6")]
+    id_precision: usize,
+    /// Elasticsearch parameters.
+    #[structopt(
+        short = "c",
+        long = "connection-string",
+        default_value = "http://localhost:9200/munin"
+    )]
+    connection_string: String,
+    /// Name of the dataset.
+    #[structopt(short = "d", long = "dataset", default_value = "fr")]
+    dataset: String,
+    /// Number of threads to use
+    #[structopt(
+        short = "t",
+        long = "nb-threads",
+        default_value = &DEFAULT_NB_THREADS
+    )]
+    nb_threads: usize,
+    /// Number of threads to use to insert into Elasticsearch. Note that Elasticsearch is not able
+    /// to handle values that are too high.
+    #[structopt(short = "T", long = "nb-insert-threads", default_value = "1")]
+    nb_insert_threads: usize,
+    /// Number of shards for the es index
+    #[structopt(short = "s", long = "nb-shards", default_value = "5")]
+    nb_shards: usize,
+    /// Number of replicas for the es index
+    #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
+    nb_replicas: usize,
+    #[structopt(flatten)]
+    rejects: RejectsArgs,
+    #[structopt(flatten)]
+    publication: PublicationArgs,
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    info!("importing addresses into Mimir");
+
+    let mapping = AddressMapping::from_path(&args.mapping)?;
+
+    let mut rubber = Rubber::new(&args.connection_string)
+        .with_nb_insert_threads(args.nb_insert_threads)
+        .with_publication_settings(args.publication.settings(&args.input)?);
+
+    let index_settings = IndexSettings {
+        nb_shards: args.nb_shards,
+        nb_replicas: args.nb_replicas,
+    };
+
+    // Fetch and index admins for `into_addr`
+    let into_addr = {
+        let admins = rubber.get_all_admins().unwrap_or_else(|err| {
+            warn!(
+                "Administratives regions not found in es db for dataset {}. (error: {})",
+                &args.dataset, err
+            );
+            vec![]
+        });
+
+        let admins_geofinder = admins.into_iter().collect();
+        let id_precision = args.id_precision;
+
+        move |a: GenericAddress| a.into_addr(&admins_geofinder, id_precision)
+    };
+
+    let rejects = args.rejects.open()?;
+
+    if let Some(input_path) = args.input {
+        // Import from file(s)
+        let paths: Vec<PathBuf> = if input_path.is_dir() {
+            walkdir::WalkDir::new(&input_path)
+                .into_iter()
+                .map(|entry| entry.map(walkdir::DirEntry::into_path))
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|_| format!("Impossible to read the directory {:?}", input_path))?
+                .into_iter()
+                .filter(|p| p.is_file())
+                .collect()
+        } else {
+            vec![input_path]
+        };
+        import_addresses_with_mapping(
+            &mut rubber,
+            args.nb_threads,
+            index_settings,
+            &args.dataset,
+            &mapping,
+            open_files(paths),
+            into_addr,
+            &rejects,
+        )?;
+    } else {
+        // Import from stdin
+        import_addresses_with_mapping(
+            &mut rubber,
+            args.nb_threads,
+            index_settings,
+            &args.dataset,
+            &mapping,
+            std::iter::once(stdin()),
+            into_addr,
+            &rejects,
+        )?;
+    }
+    rejects.finish()
+}
+
+fn main() {
+    mimirsbrunn::utils::launch_run(run);
+}
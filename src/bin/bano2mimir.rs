@@ -30,16 +30,20 @@
 
 use failure::ensure;
 use lazy_static::lazy_static;
-use mimir::objects::Admin;
+use mimir::objects::{Admin, MimirObject};
 use mimir::rubber::{IndexSettings, Rubber};
-use mimirsbrunn::addr_reader::{import_addresses_from_files, import_addresses_from_streams};
+use mimirsbrunn::addr_reader::{
+    import_addresses, import_addresses_from_files, import_addresses_from_streams, open_files,
+};
 use mimirsbrunn::admin_geofinder::AdminGeoFinder;
 use mimirsbrunn::labels;
+use mimirsbrunn::rejects::{self, Rejects, RejectsArgs};
+use mimirsbrunn::utils::{normalize_house_number_id, PublicationArgs};
 use serde::{Deserialize, Serialize};
 use slog_scope::{info, warn};
 use std::collections::BTreeMap;
 use std::fs;
-use std::io::stdin;
+use std::io::{stdin, Read};
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -79,18 +83,32 @@ impl Bano {
         use_old_index_format: bool,
     ) -> Result<mimir::Addr, mimirsbrunn::Error> {
         let street_id = format!("street:{}", self.fantoir()?.to_string());
-        let mut admins = admins_geofinder.get(&geo::Coordinate {
-            x: self.lon,
-            y: self.lat,
-        });
-
-        // If we have an admin corresponding to the INSEE, we know
-        // that's the good one, thus we remove all the admins of its
-        // level found by the geofinder, and add our admin.
-        if let Some(admin) = admins_from_insee.get(self.insee()?) {
-            admins.retain(|a| a.level != admin.level);
-            admins.push(admin.clone());
-        }
+        let insee = self.insee()?.to_string();
+        self.into_addr_with(
+            &insee,
+            street_id,
+            vec![],
+            admins_from_insee,
+            admins_geofinder,
+            use_old_index_format,
+        )
+    }
+    fn into_addr_with(
+        self,
+        insee: &str,
+        street_id: String,
+        street_aliases: Vec<String>,
+        admins_from_insee: &AdminFromInsee,
+        admins_geofinder: &AdminGeoFinder,
+        use_old_index_format: bool,
+    ) -> Result<mimir::Addr, mimirsbrunn::Error> {
+        let admins = get_admins(
+            insee,
+            self.lon,
+            self.lat,
+            admins_from_insee,
+            admins_geofinder,
+        );
 
         let country_codes = vec!["fr".to_owned()];
 
@@ -126,11 +144,15 @@ impl Bano {
             id: street_id,
             name: self.street,
             label: street_label,
+            names: mimir::I18nProperties::default(),
+            labels: mimir::I18nProperties::default(),
+            aliases: street_aliases,
             administrative_regions: admins,
             weight,
             zip_codes: zip_codes.clone(),
             coord,
             approx_coord: None,
+            geometry: None,
             distance: None,
             country_codes: country_codes.clone(),
             context: None,
@@ -143,18 +165,7 @@ impl Bano {
                 if use_old_index_format {
                     String::new()
                 } else {
-                    format!(
-                        ":{}",
-                        self.nb
-                            .replace(" ", "")
-                            .replace("\t", "")
-                            .replace("\r", "")
-                            .replace("\n", "")
-                            .replace("/", "-")
-                            .replace(".", "-")
-                            .replace(":", "-")
-                            .replace(";", "-")
-                    )
+                    format!(":{}", normalize_house_number_id(&self.nb))
                 }
             ),
             name: addr_name,
@@ -172,6 +183,178 @@ impl Bano {
     }
 }
 
+/// Admins of a coordinate
+///
+/// If we have an admin corresponding to the INSEE, we know
+/// that's the good one, thus we remove all the admins of its
+/// level found by the geofinder, and add our admin.
+fn get_admins(
+    insee: &str,
+    lon: f64,
+    lat: f64,
+    admins_from_insee: &AdminFromInsee,
+    admins_geofinder: &AdminGeoFinder,
+) -> Vec<Arc<Admin>> {
+    let mut admins = admins_geofinder.get(&geo::Coordinate { x: lon, y: lat });
+    if let Some(admin) = admins_from_insee.get(insee) {
+        admins.retain(|a| a.level != admin.level);
+        admins.push(admin.clone());
+    }
+    admins
+}
+
+/// House number of the BAN for the lieux-dits, which are not addresses
+const BAN_LIEU_DIT_NUMBER: &str = "99999";
+
+/// A line of the BAN (Base Adresse Nationale) CSV
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Ban {
+    pub id: String,
+    #[serde(default)]
+    pub id_fantoir: String,
+    pub numero: String,
+    #[serde(default)]
+    pub rep: String,
+    pub nom_voie: String,
+    pub code_postal: String,
+    pub code_insee: String,
+    pub nom_commune: String,
+    pub lon: f64,
+    pub lat: f64,
+    /// Other name of the street
+    #[serde(default)]
+    pub alias: String,
+    /// Name of the lieu-dit of the address
+    #[serde(default)]
+    pub nom_ld: String,
+}
+
+impl Ban {
+    pub fn insee(&self) -> &str {
+        self.code_insee.trim_start_matches('0')
+    }
+    pub fn is_lieu_dit(&self) -> bool {
+        self.numero == BAN_LIEU_DIT_NUMBER
+    }
+    /// Name of the lieu-dit of the line, if any
+    pub fn lieu_dit(&self) -> Option<&str> {
+        if self.is_lieu_dit() {
+            Some(&self.nom_voie)
+        } else if !self.nom_ld.is_empty() {
+            Some(&self.nom_ld)
+        } else {
+            None
+        }
+    }
+    /// The house number is made of the number and of its repetition index (bis, ter...)
+    pub fn house_number(&self) -> String {
+        if self.rep.is_empty() {
+            self.numero.clone()
+        } else {
+            format!("{} {}", self.numero, self.rep)
+        }
+    }
+    fn street_id(&self) -> String {
+        let street_code = if self.id_fantoir.is_empty() {
+            self.id.splitn(3, '_').take(2).collect::<Vec<_>>().join("_")
+        } else {
+            self.id_fantoir.clone()
+        };
+        format!("street:{}", street_code)
+    }
+    pub fn into_addr(
+        self,
+        admins_from_insee: &AdminFromInsee,
+        admins_geofinder: &AdminGeoFinder,
+        use_old_index_format: bool,
+    ) -> Result<mimir::Addr, mimirsbrunn::Error> {
+        let insee = self.insee().to_string();
+        let street_id = self.street_id();
+        let street_aliases = if self.alias.is_empty() || self.alias == self.nom_voie {
+            vec![]
+        } else {
+            vec![self.alias.clone()]
+        };
+        let bano = Bano {
+            id: self.id.clone(),
+            nb: self.house_number(),
+            street: self.nom_voie,
+            zip: self.code_postal,
+            city: self.nom_commune,
+            src: "BAN".to_string(),
+            lat: self.lat,
+            lon: self.lon,
+        };
+        bano.into_addr_with(
+            &insee,
+            street_id,
+            street_aliases,
+            admins_from_insee,
+            admins_geofinder,
+            use_old_index_format,
+        )
+    }
+    /// Build the street-like place of the lieu-dit of the line
+    pub fn into_lieu_dit(
+        self,
+        admins_from_insee: &AdminFromInsee,
+        admins_geofinder: &AdminGeoFinder,
+    ) -> Option<mimir::Street> {
+        let name = self.lieu_dit()?.to_string();
+        let admins = get_admins(
+            self.insee(),
+            self.lon,
+            self.lat,
+            admins_from_insee,
+            admins_geofinder,
+        );
+        let country_codes = vec!["fr".to_owned()];
+        let city = build_admin_from_bano_city(&self.nom_commune);
+        let label = labels::format_street_label(
+            &name,
+            admins
+                .iter()
+                .filter(|a| a.is_city())
+                .map(|a| a.deref())
+                .chain(std::iter::once(&city)),
+            &country_codes,
+        );
+        let weight = admins
+            .iter()
+            .find(|a| a.level == 8)
+            .map_or(0., |a| a.weight);
+        let coord = mimir::Coord::new(self.lon, self.lat);
+        Some(mimir::Street {
+            id: lieu_dit_id(&self.code_insee, &name),
+            label,
+            name,
+            names: mimir::I18nProperties::default(),
+            labels: mimir::I18nProperties::default(),
+            aliases: vec![],
+            administrative_regions: admins,
+            weight,
+            zip_codes: vec![self.code_postal],
+            coord,
+            approx_coord: Some(coord.into()),
+            geometry: None,
+            distance: None,
+            country_codes,
+            context: None,
+        })
+    }
+}
+
+fn lieu_dit_id(code_insee: &str, name: &str) -> String {
+    format!(
+        "street:{}_ld_{}",
+        code_insee,
+        name.to_lowercase()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("_")
+    )
+}
+
 fn build_admin_from_bano_city(city: &str) -> Admin {
     mimir::Admin {
         name: city.to_string(),
@@ -217,19 +400,136 @@ struct Args {
     /// therefore, different addresses with the same position will disappear.
     #[structopt(long = "use-old-index-format")]
     use_old_index_format: bool,
+    /// The input is the CSV of the BAN (Base Adresse Nationale) instead of the BANO one.
+    /// The lieux-dits of the BAN are then also imported, and searched as streets.
+    #[structopt(long = "ban")]
+    ban: bool,
+    #[structopt(flatten)]
+    rejects: RejectsArgs,
+    #[structopt(flatten)]
+    publication: PublicationArgs,
+}
+
+/// Read the lines of BAN CSV files, separated by semicolons
+fn read_ban<'a>(
+    streams: impl IntoIterator<Item = impl Read + 'a> + 'a,
+    rejects: &'a Rejects,
+) -> impl Iterator<Item = Ban> + 'a {
+    streams.into_iter().flat_map(move |stream| {
+        let reader = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .from_reader(stream);
+        rejects::deserialize_records(reader, rejects.clone())
+    })
+}
+
+/// Import the addresses of the BAN, and its lieux-dits.
+/// The lieux-dits have their own doc type, so that they are not
+/// removed by the import of the streets of the dataset.
+fn import_ban(
+    rubber: &mut Rubber,
+    args: &Args,
+    index_settings: IndexSettings,
+    lines: impl Iterator<Item = Ban>,
+    rejects: &Rejects,
+) -> Result<(), mimirsbrunn::Error> {
+    let admins = rubber.get_all_admins().unwrap_or_else(|err| {
+        warn!(
+            "Administratives regions not found in es db for dataset {}. (error: {})",
+            &args.dataset, err
+        );
+        vec![]
+    });
+    let admins_geofinder: Arc<AdminGeoFinder> = Arc::new(admins.iter().cloned().collect());
+    let admins_by_insee: Arc<AdminFromInsee> = Arc::new(
+        admins
+            .into_iter()
+            .filter(|a| !a.insee.is_empty())
+            .map(|mut a| {
+                a.boundary = None; // to save some space we remove the admin boundary
+                (a.insee.clone(), Arc::new(a))
+            })
+            .collect(),
+    );
+
+    let into_addr = {
+        let admins_geofinder = admins_geofinder.clone();
+        let admins_by_insee = admins_by_insee.clone();
+        let use_old_index_format = args.use_old_index_format;
+        move |b: Ban| b.into_addr(&admins_by_insee, &admins_geofinder, use_old_index_format)
+    };
+
+    // the first line of a lieu-dit is kept, unless the lieu-dit has its own line
+    let mut lieux_dits = BTreeMap::<String, Ban>::new();
+    let addresses = lines.filter_map(|line| {
+        if let Some(name) = line.lieu_dit() {
+            let id = lieu_dit_id(&line.code_insee, name);
+            if line.is_lieu_dit() || !lieux_dits.contains_key(&id) {
+                lieux_dits.insert(id, line.clone());
+            }
+        }
+        if line.is_lieu_dit() {
+            None
+        } else {
+            Some(line)
+        }
+    });
+    import_addresses(
+        rubber,
+        args.nb_threads,
+        index_settings.clone(),
+        &args.dataset,
+        addresses,
+        into_addr,
+        rejects,
+    )?;
+
+    let lieux_dits = lieux_dits
+        .into_iter()
+        .filter_map(|(_, line)| line.into_lieu_dit(&admins_by_insee, &admins_geofinder))
+        .map(mimir::LieuDit);
+    let nb = rubber.public_index(&args.dataset, &index_settings, lieux_dits)?;
+    info!("importing lieux-dits: {} lieux-dits added.", nb);
+    Ok(())
 }
 
 fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
     info!("importing bano into Mimir");
 
-    let mut rubber =
-        Rubber::new(&args.connection_string).with_nb_insert_threads(args.nb_insert_threads);
+    // the checks not given for a doc type are meant for the addresses, not the lieux-dits
+    let publication_settings = args
+        .publication
+        .settings(&args.input)?
+        .scoped_to(mimir::Addr::doc_type());
+    let mut rubber = Rubber::new(&args.connection_string)
+        .with_nb_insert_threads(args.nb_insert_threads)
+        .with_publication_settings(publication_settings);
 
     let index_settings = IndexSettings {
         nb_shards: args.nb_shards,
         nb_replicas: args.nb_replicas,
     };
 
+    let rejects = args.rejects.open()?;
+
+    if args.ban {
+        if let Some(ref input_path) = args.input {
+            let paths: Vec<PathBuf> = if input_path.is_dir() {
+                fs::read_dir(&input_path)?
+                    .map(|p| p.map(|p| p.path()))
+                    .collect::<Result<_, _>>()?
+            } else {
+                vec![input_path.clone()]
+            };
+            let lines = read_ban(open_files(paths), &rejects);
+            import_ban(&mut rubber, &args, index_settings, lines, &rejects)?;
+        } else {
+            let lines = read_ban(std::iter::once(stdin()), &rejects);
+            import_ban(&mut rubber, &args, index_settings, lines, &rejects)?;
+        }
+        return rejects.finish();
+    }
+
     // Fetch and index admins for `into_addr`
     let into_addr = {
         let admins = rubber.get_all_admins().unwrap_or_else(|err| {
@@ -267,7 +567,8 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
                 &args.dataset,
                 paths.map(|p| p.unwrap().path()),
                 into_addr,
-            )
+                &rejects,
+            )?;
         } else {
             import_addresses_from_files(
                 &mut rubber,
@@ -277,7 +578,8 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
                 &args.dataset,
                 std::iter::once(input_path),
                 into_addr,
-            )
+                &rejects,
+            )?;
         }
     } else {
         // Import from stdin
@@ -289,9 +591,49 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
             &args.dataset,
             std::iter::once(stdin()),
             into_addr,
-        )
+            &rejects,
+        )?;
     }
+    rejects.finish()
 }
 fn main() {
     mimirsbrunn::utils::launch_run(run);
 }
+
+#[test]
+fn test_read_ban() {
+    let rejects = Rejects::default();
+    let lines: Vec<_> = read_ban(
+        open_files(vec![PathBuf::from("./tests/fixtures/sample-ban.csv")]),
+        &rejects,
+    )
+    .collect();
+    assert_eq!(lines.len(), 3);
+
+    let line = &lines[0];
+    assert_eq!(line.house_number(), "12 bis");
+    assert_eq!(line.insee(), "1001");
+    assert_eq!(line.street_id(), "street:01001_0005");
+    assert_eq!(line.lieu_dit(), Some("Les Bruyères"));
+
+    let addr = line
+        .clone()
+        .into_addr(&AdminFromInsee::new(), &AdminGeoFinder::default(), false)
+        .unwrap();
+    assert_eq!(addr.house_number, "12 bis");
+    assert_eq!(addr.id, "addr:4.9306;46.1572:12bis");
+    assert_eq!(addr.street.name, "Route de la Dombes");
+    assert_eq!(addr.street.aliases, vec!["RD 1083"]);
+    assert_eq!(addr.zip_codes, vec!["01400"]);
+
+    assert!(!lines[1].is_lieu_dit());
+    assert_eq!(lines[1].lieu_dit(), None);
+
+    let lieu_dit = lines[2].clone();
+    assert!(lieu_dit.is_lieu_dit());
+    let street = lieu_dit
+        .into_lieu_dit(&AdminFromInsee::new(), &AdminGeoFinder::default())
+        .unwrap();
+    assert_eq!(street.id, "street:01001_ld_les_bruyères");
+    assert_eq!(street.name, "Les Bruyères");
+}
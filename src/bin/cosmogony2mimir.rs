@@ -31,7 +31,7 @@
 use cosmogony::{Zone, ZoneIndex};
 use failure::Error;
 use mimir::objects::Admin;
-use mimir::rubber::{IndexSettings, Rubber};
+use mimir::rubber::{IndexSettings, PublicationSettings, Rubber};
 use mimirsbrunn::osm_reader::admin;
 use mimirsbrunn::osm_reader::osm_utils;
 use mimirsbrunn::utils;
@@ -92,6 +92,7 @@ impl IntoAdmin for Zone {
             .and_then(|id| zones_osm_id.get(&id))
             .map(|(id, insee)| format_id(id, insee.as_ref()));
         let codes = osm_utils::get_osm_codes_from_tags(&self.tags);
+        let aliases = osm_utils::get_aliases_from_tags(&self.tags, &self.name);
         let mut admin = Admin {
             id: zones_osm_id
                 .get(&self.id)
@@ -120,6 +121,7 @@ impl IntoAdmin for Zone {
                 .into_iter()
                 .filter(|(k, _)| langs.contains(&k))
                 .collect(),
+            aliases,
             distance: None,
             context: None,
             administrative_regions: Vec::new(),
@@ -152,8 +154,9 @@ fn send_to_es(
     cnx_string: &str,
     dataset: &str,
     index_settings: IndexSettings,
+    publication_settings: PublicationSettings,
 ) -> Result<(), Error> {
-    let mut rubber = Rubber::new(cnx_string);
+    let mut rubber = Rubber::new(cnx_string).with_publication_settings(publication_settings);
     rubber.initialize_templates()?;
     let nb_admins = rubber.public_index(dataset, &index_settings, admins)?;
     info!("{} admins added.", nb_admins);
@@ -216,6 +219,7 @@ fn index_cosmogony(args: Args) -> Result<(), Error> {
         &args.connection_string,
         &args.dataset,
         index_settings,
+        args.publication.settings(&[&args.input])?,
     )?;
 
     Ok(())
@@ -250,6 +254,8 @@ struct Args {
     /// instead of 'admin:osm:{osm_id}'
     #[structopt(long = "french-id-retrocompatibility")]
     french_id_retrocompatibility: bool,
+    #[structopt(flatten)]
+    publication: utils::PublicationArgs,
 }
 
 fn main() {
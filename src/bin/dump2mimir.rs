@@ -0,0 +1,132 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use failure::{bail, ResultExt};
+use mimir::dump;
+use mimir::rubber::{get_date_index_name, IndexSettings, Rubber};
+use slog_scope::{info, warn};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+// number of lines sent in a single bulk, each document is made of 2 lines
+const CHUNK_SIZE: usize = 2000;
+
+#[derive(StructOpt, Debug)]
+struct Args {
+    /// Directory of the dump, written by an importer with a `file://` connection string.
+    #[structopt(short = "i", long = "input", parse(from_os_str))]
+    input: PathBuf,
+    /// Elasticsearch parameters.
+    #[structopt(
+        short = "c",
+        long = "connection-string",
+        default_value = "http://localhost:9200/munin"
+    )]
+    connection_string: String,
+    /// Number of shards for the es index
+    #[structopt(short = "s", long = "nb-shards", default_value = "5")]
+    nb_shards: usize,
+    /// Number of replicas for the es index
+    #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
+    nb_replicas: usize,
+    #[structopt(flatten)]
+    publication: mimirsbrunn::utils::PublicationArgs,
+}
+
+fn load_index(
+    rubber: &mut Rubber,
+    input: &Path,
+    metadata: &dump::DumpMetadata,
+    index_settings: &IndexSettings,
+) -> Result<usize, failure::Error> {
+    let index_name = get_date_index_name(&metadata.dataset_index());
+    info!("loading {} into {}", metadata.index, index_name);
+    rubber.create_index(&index_name, index_settings)?;
+
+    let mut nb_docs = 0;
+    let mut lines = dump::read_lines(input, &metadata.index)?.peekable();
+    while lines.peek().is_some() {
+        let chunk = lines
+            .by_ref()
+            .take(CHUNK_SIZE)
+            .collect::<Result<Vec<_>, _>>()?;
+        nb_docs += rubber.bulk_index_raw(&index_name, &chunk)?;
+    }
+
+    // the index is published with the provenance of the import that has written the dump
+    rubber.publication_settings.provenance = metadata.provenance.clone();
+    rubber.publish_index_by_type(
+        &metadata.dataset,
+        &metadata.doc_type,
+        metadata.is_geo_data,
+        &index_name,
+        metadata.visibility,
+    )?;
+    Ok(nb_docs)
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    let mut rubber =
+        Rubber::new(&args.connection_string).with_publication_settings(args.publication.into());
+    if rubber.dump_dir.is_some() {
+        bail!("the dump has to be loaded into elasticsearch, not into another dump");
+    }
+    rubber.initialize_templates()?;
+
+    let index_settings = IndexSettings {
+        nb_shards: args.nb_shards,
+        nb_replicas: args.nb_replicas,
+    };
+
+    let all_metadata = dump::read_all_metadata(&args.input)?;
+    let published: BTreeSet<_> = all_metadata
+        .iter()
+        .map(|m| dump::dump_path(&args.input, &m.index))
+        .collect();
+    for entry in std::fs::read_dir(&args.input)? {
+        let path = entry?.path();
+        let is_dump = path.to_string_lossy().ends_with(".ndjson.gz");
+        if is_dump && !published.contains(&path) {
+            warn!("{:?} has not been published, it is not loaded", path);
+        }
+    }
+
+    for metadata in &all_metadata {
+        let nb_docs = load_index(&mut rubber, &args.input, metadata, &index_settings)
+            .with_context(|err| format!("Error while loading {}: {}", metadata.index, err))?;
+        info!("{} documents loaded from {}", nb_docs, metadata.index);
+    }
+    Ok(())
+}
+
+fn main() {
+    mimirsbrunn::utils::launch_run(run);
+}
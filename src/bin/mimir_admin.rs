@@ -0,0 +1,291 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use chrono::{DateTime, Utc};
+use failure::ensure;
+use mimir::rubber::{orphan_versions, Rubber};
+use mimir::MimirObject;
+use mimirsbrunn::stops::rebuild_global_stop_index;
+use slog_scope::info;
+use std::collections::BTreeMap;
+use structopt::StructOpt;
+
+/// Administration of the indexes and the aliases of mimir.
+#[derive(StructOpt, Debug)]
+struct Args {
+    /// Elasticsearch parameters.
+    #[structopt(
+        short = "c",
+        long = "connection-string",
+        default_value = "http://localhost:9200/"
+    )]
+    connection_string: String,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// List the datasets of each type, with the versions of their index and their number
+    /// of documents.
+    #[structopt(name = "list")]
+    List,
+    /// Show the tree of the aliases, from `munin` to the dated indexes.
+    #[structopt(name = "aliases")]
+    Aliases,
+    /// Delete all the versions of the index of a dataset, which removes it from all the aliases.
+    #[structopt(name = "delete-dataset")]
+    DeleteDataset {
+        /// Type of the documents of the index (admin, street, addr, poi, stop...).
+        /// By default, the dataset is deleted for all the types.
+        #[structopt(short = "t", long = "type")]
+        doc_type: Option<String>,
+        /// Name of the dataset.
+        #[structopt(short = "d", long = "dataset")]
+        dataset: String,
+    },
+    /// Show how an index has been built: the import tool, its arguments, the input files...
+    #[structopt(name = "provenance")]
+    Provenance {
+        /// Name of the index, or of an alias.
+        #[structopt(short = "i", long = "index")]
+        index: String,
+    },
+    /// Delete the dated indexes left by failed imports, ie the versions that have never
+    /// been published. Only the ones older than `--older-than` are deleted, as the recent
+    /// ones can be being filled by an import.
+    #[structopt(name = "remove-orphans")]
+    RemoveOrphans {
+        /// Only list the orphan indexes.
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        /// Minimum age, in hours, of the orphan indexes to delete.
+        #[structopt(long = "older-than", default_value = "24")]
+        older_than: i64,
+    },
+}
+
+/// The aliases built by `Rubber::publish_index`, with the indexes they point to
+#[derive(Default)]
+struct AliasTree(BTreeMap<String, AliasTree>);
+
+impl AliasTree {
+    fn insert(&mut self, path: &[String]) {
+        if let Some((first, rest)) = path.split_first() {
+            self.0.entry(first.clone()).or_default().insert(rest);
+        }
+    }
+
+    fn print(&self, depth: usize) {
+        for (name, children) in &self.0 {
+            println!("{}{}", "  ".repeat(depth), name);
+            children.print(depth + 1);
+        }
+    }
+}
+
+// the aliases are all held by the dated indexes, they are sorted from the most general one
+// (munin) to the one of the dataset (munin_<type>_<dataset>)
+fn alias_level(alias: &str) -> (usize, usize) {
+    match alias {
+        "munin" => (0, 0),
+        "munin_geo_data" => (1, 0),
+        _ => (2, alias.len()),
+    }
+}
+
+fn list(rubber: &Rubber) -> Result<(), failure::Error> {
+    let provenances = rubber.get_provenances("munin_*")?;
+    for ((doc_type, dataset), versions) in rubber.get_all_index_versions()? {
+        println!("{} {}", doc_type, dataset);
+        for version in versions {
+            let nb_docs = rubber.count(&version.name, None)?;
+            let status = if version.is_published {
+                " (published)"
+            } else {
+                ""
+            };
+            println!("  {} {} documents{}", version.name, nb_docs, status);
+            if let Some(provenance) = provenances.get(&version.name) {
+                println!(
+                    "    imported by {} {} on {}",
+                    provenance.tool,
+                    provenance.version,
+                    provenance
+                        .published_at
+                        .as_deref()
+                        .unwrap_or(&provenance.started_at)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn show_aliases(rubber: &Rubber) -> Result<(), failure::Error> {
+    let mut tree = AliasTree::default();
+    for (index, mut aliases) in rubber.get_all_aliased_index("munin")? {
+        if aliases.is_empty() {
+            continue;
+        }
+        aliases.sort_by_key(|alias| alias_level(alias));
+        aliases.push(index);
+        tree.insert(&aliases);
+    }
+    tree.print(0);
+    Ok(())
+}
+
+fn show_provenance(rubber: &Rubber, index: &str) -> Result<(), failure::Error> {
+    let provenances = rubber.get_provenances(index)?;
+    ensure!(!provenances.is_empty(), "no provenance for {}", index);
+    println!("{}", serde_json::to_string_pretty(&provenances)?);
+    Ok(())
+}
+
+fn delete_dataset(
+    rubber: &mut Rubber,
+    doc_type: Option<&str>,
+    dataset: &str,
+) -> Result<(), failure::Error> {
+    let doc_types: Vec<String> = match doc_type {
+        Some(doc_type) => vec![doc_type.to_string()],
+        None => rubber
+            .get_all_index_versions()?
+            .into_iter()
+            .filter(|((_, d), _)| d == dataset)
+            .map(|((t, _), _)| t)
+            .collect(),
+    };
+    ensure!(
+        !doc_types.is_empty(),
+        "no index for the dataset {}",
+        dataset
+    );
+    for doc_type in &doc_types {
+        let deleted = rubber.delete_dataset(doc_type, dataset)?;
+        info!("{} {} deleted: {:?}", doc_type, dataset, deleted);
+    }
+    // the stops of the dataset are also removed from the global stop index
+    if doc_types.iter().any(|t| t == mimir::Stop::doc_type()) {
+        rebuild_global_stop_index(rubber)?;
+        info!("global stop index rebuilt");
+    }
+    Ok(())
+}
+
+fn remove_orphans(
+    rubber: &mut Rubber,
+    dry_run: bool,
+    older_than: i64,
+) -> Result<(), failure::Error> {
+    let creation_dates = rubber.get_creation_dates("munin_*")?;
+    let created_before = chrono::Utc::now() - chrono::Duration::hours(older_than);
+    let orphans: Vec<String> = rubber
+        .get_all_index_versions()?
+        .values()
+        .flat_map(|versions| orphan_versions(versions))
+        .filter(|v| is_created_before(&creation_dates, &v.name, created_before))
+        .map(|v| v.name.clone())
+        .collect();
+    for index in orphans {
+        if dry_run {
+            println!("{}", index);
+        } else {
+            rubber.delete_index(&index)?;
+            info!("{} deleted", index);
+        }
+    }
+    Ok(())
+}
+
+// an index whose creation date is unknown is considered as recent
+fn is_created_before(
+    creation_dates: &BTreeMap<String, DateTime<Utc>>,
+    index: &str,
+    date: DateTime<Utc>,
+) -> bool {
+    creation_dates
+        .get(index)
+        .map_or(false, |created_at| *created_at < date)
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    let mut rubber = Rubber::new(&args.connection_string);
+    match args.command {
+        Command::List => list(&rubber),
+        Command::Aliases => show_aliases(&rubber),
+        Command::DeleteDataset { doc_type, dataset } => {
+            delete_dataset(&mut rubber, doc_type.as_deref(), &dataset)
+        }
+        Command::Provenance { index } => show_provenance(&rubber, &index),
+        Command::RemoveOrphans {
+            dry_run,
+            older_than,
+        } => remove_orphans(&mut rubber, dry_run, older_than),
+    }
+}
+
+fn main() {
+    mimirsbrunn::utils::launch_run(run);
+}
+
+#[test]
+fn test_alias_level() {
+    let mut aliases = vec![
+        "munin_addr_fr".to_string(),
+        "munin".to_string(),
+        "munin_addr".to_string(),
+        "munin_geo_data".to_string(),
+    ];
+    aliases.sort_by_key(|alias| alias_level(alias));
+    assert_eq!(
+        aliases,
+        vec!["munin", "munin_geo_data", "munin_addr", "munin_addr_fr"]
+    );
+}
+
+#[test]
+fn test_is_created_before() {
+    use chrono::TimeZone;
+    let mut creation_dates = BTreeMap::new();
+    creation_dates.insert(
+        "munin_addr_fr_1".to_string(),
+        Utc.ymd(2020, 10, 19).and_hms(10, 0, 0),
+    );
+    let date = Utc.ymd(2020, 10, 20).and_hms(10, 0, 0);
+    assert!(is_created_before(&creation_dates, "munin_addr_fr_1", date));
+    assert!(!is_created_before(
+        &creation_dates,
+        "munin_addr_fr_1",
+        Utc.ymd(2020, 10, 18).and_hms(10, 0, 0)
+    ));
+    assert!(!is_created_before(&creation_dates, "munin_addr_fr_2", date));
+}
@@ -0,0 +1,85 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use mimir::rubber::Rubber;
+use mimir::MimirObject;
+use mimirsbrunn::stops::rebuild_global_stop_index;
+use slog_scope::info;
+use structopt::StructOpt;
+
+/// Re-point the index of a type and a dataset to a previous version,
+/// the versions being kept by the import tools with `--keep-versions`.
+#[derive(StructOpt, Debug)]
+struct Args {
+    /// Elasticsearch parameters.
+    #[structopt(
+        short = "c",
+        long = "connection-string",
+        default_value = "http://localhost:9200/"
+    )]
+    connection_string: String,
+    /// Type of the documents of the index (admin, street, addr, poi, stop...).
+    #[structopt(short = "t", long = "type")]
+    doc_type: String,
+    /// Name of the dataset.
+    #[structopt(short = "d", long = "dataset")]
+    dataset: String,
+    /// Version (dated index) to publish, it must have been published before.
+    /// By default, the previously published version preceding the published one.
+    #[structopt(long = "to")]
+    to: Option<String>,
+    /// Only keep this number of previously published versions, the older ones being deleted.
+    #[structopt(long = "keep")]
+    keep: Option<usize>,
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    let mut rubber = Rubber::new(&args.connection_string);
+    let published = rubber.rollback_index(&args.doc_type, &args.dataset, args.to.as_deref())?;
+    info!(
+        "munin_{}_{} now points to {}",
+        args.doc_type, args.dataset, published
+    );
+    // the global stop index is rebuilt with the stops of the published version
+    if args.doc_type == mimir::Stop::doc_type() {
+        rebuild_global_stop_index(&mut rubber)?;
+        info!("global stop index rebuilt");
+    }
+
+    if let Some(keep) = args.keep {
+        let deleted = rubber.prune_index_versions(&args.doc_type, &args.dataset, keep)?;
+        info!("deleted indexes: {:?}", deleted);
+    }
+    Ok(())
+}
+
+fn main() {
+    mimirsbrunn::utils::launch_run(run);
+}
@@ -0,0 +1,95 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use failure::ResultExt;
+use mimir::rubber::IndexSettings;
+use mimirsbrunn::rejects::RejectsArgs;
+use mimirsbrunn::stops::*;
+use slog_scope::info;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// NeTEx file, or directory of NeTEx files.
+    #[structopt(short = "i", long = "input", parse(from_os_str))]
+    input: PathBuf,
+    /// Name of the dataset.
+    #[structopt(short = "d", long = "dataset", default_value = "fr")]
+    dataset: String,
+    /// Elasticsearch parameters.
+    #[structopt(
+        short = "c",
+        long = "connection-string",
+        default_value = "http://localhost:9200/munin"
+    )]
+    connection_string: String,
+    /// Number of shards for the es index
+    #[structopt(short = "s", long = "nb-shards", default_value = "1")]
+    nb_shards: usize,
+    /// Number of replicas for the es index
+    #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
+    nb_replicas: usize,
+    #[structopt(flatten)]
+    rejects: RejectsArgs,
+    #[structopt(flatten)]
+    publication: mimirsbrunn::utils::PublicationArgs,
+}
+
+fn main() {
+    mimirsbrunn::utils::launch_run(run);
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    info!("Launching netex2mimir...");
+
+    let rejects = args.rejects.open()?;
+    let stops = mimirsbrunn::netex::read_stops(&args.input, &rejects)?;
+
+    let index_settings = IndexSettings {
+        nb_shards: args.nb_shards,
+        nb_replicas: args.nb_replicas,
+    };
+
+    import_stops(
+        stops,
+        &args.connection_string,
+        &args.dataset,
+        index_settings,
+        args.publication.settings(&[&args.input])?,
+    )
+    .with_context(|err| {
+        format!(
+            "Error occurred when importing stops into {} on {}: {}",
+            args.dataset, args.connection_string, err
+        )
+    })?;
+    rejects.finish()
+}
@@ -32,13 +32,8 @@ use failure::ResultExt;
 use mimir::rubber::IndexSettings;
 use mimirsbrunn::stops::*;
 use slog_scope::{info, warn};
-use std::cmp::Ordering;
-use std::collections::{hash_map::DefaultHasher, HashMap};
-use std::hash::BuildHasherDefault;
 use std::path::PathBuf;
 use structopt::StructOpt;
-use transit_model::objects as navitia;
-use typed_index_collection::Idx;
 
 #[derive(Debug, StructOpt)]
 struct Args {
@@ -64,127 +59,12 @@ struct Args {
     /// Number of replicas for the es index
     #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
     nb_replicas: usize,
-}
-
-fn get_lines(idx: Idx<navitia::StopArea>, navitia: &transit_model::Model) -> Vec<mimir::Line> {
-    use mimir::FromTransitModel;
-    let mut lines: Vec<_> = navitia
-        .get_corresponding_from_idx(idx)
-        .into_iter()
-        .map(|l_idx| mimir::Line::from_transit_model(l_idx, navitia))
-        .collect();
-
-    // we want the lines to be sorted in a way where
-    // line-3 is before line-11, so be use a human_sort
-    lines.sort_by(|lhs, rhs| {
-        match (&lhs.sort_order, &rhs.sort_order) {
-            (None, Some(_)) => Ordering::Greater,
-            (Some(_), None) => Ordering::Less,
-            (Some(s), Some(o)) => s.cmp(o),
-            (None, None) => Ordering::Equal,
-        }
-        .then_with(|| match (&lhs.code, &rhs.code) {
-            (Some(l), Some(r)) => human_sort::compare(l, r),
-            _ => Ordering::Equal,
-        })
-        .then_with(|| human_sort::compare(&lhs.name, &rhs.name))
-    });
-    lines
-}
-
-fn to_mimir(
-    idx: Idx<navitia::StopArea>,
-    stop_area: &navitia::StopArea,
-    navitia: &transit_model::Model,
-) -> mimir::Stop {
-    let commercial_modes = navitia
-        .get_corresponding_from_idx(idx)
-        .into_iter()
-        .map(|cm_idx| mimir::CommercialMode {
-            id: mimir::objects::normalize_id(
-                "commercial_mode",
-                &navitia.commercial_modes[cm_idx].id,
-            ),
-            name: navitia.commercial_modes[cm_idx].name.clone(),
-        })
-        .collect();
-    let physical_modes = navitia
-        .get_corresponding_from_idx(idx)
-        .into_iter()
-        .map(|pm_idx| mimir::PhysicalMode {
-            id: mimir::objects::normalize_id("physical_mode", &navitia.physical_modes[pm_idx].id),
-            name: navitia.physical_modes[pm_idx].name.clone(),
-        })
-        .collect();
-    let comments = stop_area
-        .comment_links
-        .iter()
-        .filter_map(|comment_id| {
-            let res = navitia.comments.get(comment_id);
-            if res.is_none() {
-                warn!("Could not retrieve comments for id {}", comment_id);
-            }
-            res
-        })
-        .map(|comment| mimir::Comment {
-            name: comment.name.clone(),
-        })
-        .collect();
-    let feed_publishers = navitia
-        .get_corresponding_from_idx(idx)
-        .into_iter()
-        .map(|contrib_idx| mimir::FeedPublisher {
-            id: navitia.contributors[contrib_idx].id.clone(),
-            name: navitia.contributors[contrib_idx].name.clone(),
-            license: navitia.contributors[contrib_idx]
-                .license
-                .clone()
-                .unwrap_or_else(|| "".into()),
-            url: navitia.contributors[contrib_idx]
-                .website
-                .clone()
-                .unwrap_or_else(|| "".into()),
-        })
-        .collect();
-    let coord = mimir::Coord::new(stop_area.coord.lon, stop_area.coord.lat);
-
-    let lines = get_lines(idx, navitia);
-
-    mimir::Stop {
-        id: mimir::objects::normalize_id("stop_area", &stop_area.id),
-        label: stop_area.name.clone(),
-        name: stop_area.name.clone(),
-        coord,
-        approx_coord: Some(coord.into()),
-        commercial_modes,
-        physical_modes,
-        lines,
-        comments,
-        timezone: stop_area
-            .timezone
-            .clone()
-            .map(chrono_tz::Tz::name)
-            .map(str::to_owned)
-            .unwrap_or_default(),
-        codes: stop_area
-            .codes
-            .iter()
-            .map(|&(ref t, ref v)| mimir::Code {
-                name: t.clone(),
-                value: v.clone(),
-            })
-            .collect(),
-        properties: stop_area
-            .object_properties
-            .iter()
-            .map(|(ref k, ref v)| mimir::Property {
-                key: k.to_string(),
-                value: v.to_string(),
-            })
-            .collect(),
-        feed_publishers,
-        ..Default::default()
-    }
+    /// Also index the stop points and the entrances of the stop areas.
+    /// They are only searched when the `public_transport:stop_point` type is asked.
+    #[structopt(long = "stop-points")]
+    stop_points: bool,
+    #[structopt(flatten)]
+    publication: mimirsbrunn::utils::PublicationArgs,
 }
 
 fn main() {
@@ -200,34 +80,20 @@ fn run(args: Args) -> Result<(), transit_model::Error> {
 
     let navitia = transit_model::ntfs::read(&args.input)?;
 
-    let nb_stop_points: HashMap<String, u32, BuildHasherDefault<DefaultHasher>> = navitia
-        .stop_areas
-        .iter()
-        .map(|(idx, sa)| {
-            let id = mimir::objects::normalize_id("stop_area", &sa.id);
-            let nb_stop_points = navitia
-                .get_corresponding_from_idx::<_, navitia::StopPoint>(idx)
-                .len();
-            (id, nb_stop_points as u32)
-        })
-        .collect();
-    let mut stops: Vec<mimir::Stop> = navitia
-        .stop_areas
-        .iter()
-        .map(|(idx, sa)| to_mimir(idx, sa, &navitia))
-        .collect();
-    initialize_weights(stops.iter_mut(), &nb_stop_points);
+    let stops = stops_from_model(&navitia);
 
     let index_settings = IndexSettings {
         nb_shards: args.nb_shards,
         nb_replicas: args.nb_replicas,
     };
 
+    let publication_settings = args.publication.settings(&[&args.input])?;
     import_stops(
         stops,
         &args.connection_string,
         &args.dataset,
-        index_settings,
+        index_settings.clone(),
+        publication_settings.clone(),
     )
     .with_context(|err| {
         format!(
@@ -235,6 +101,22 @@ fn run(args: Args) -> Result<(), transit_model::Error> {
             args.dataset, args.connection_string, err
         )
     })?;
+
+    if args.stop_points {
+        import_stop_points(
+            stop_points_from_model(&navitia),
+            &args.connection_string,
+            &args.dataset,
+            index_settings,
+            publication_settings,
+        )
+        .with_context(|err| {
+            format!(
+                "Error occurred when importing stop points into {} on {}: {}",
+                args.dataset, args.connection_string, err
+            )
+        })?;
+    }
     Ok(())
 }
 
@@ -247,6 +129,8 @@ fn test_bad_connection_string() {
         city_level: None,
         nb_replicas: 1,
         nb_shards: 1,
+        stop_points: false,
+        publication: Default::default(),
     };
     let causes = run(args)
         .unwrap_err()
@@ -271,6 +155,8 @@ fn test_bad_file() {
         city_level: None,
         nb_replicas: 1,
         nb_shards: 1,
+        stop_points: false,
+        publication: Default::default(),
     };
     let causes = run(args)
         .unwrap_err()
@@ -30,14 +30,15 @@
 
 use lazy_static::lazy_static;
 use mimir::rubber::{IndexSettings, Rubber};
-use mimirsbrunn::addr_reader::{import_addresses_from_files, import_addresses_from_streams};
+use mimirsbrunn::addr_reader::{import_addresses, open_files};
 use mimirsbrunn::admin_geofinder::AdminGeoFinder;
+use mimirsbrunn::rejects::{self, RejectReason, Rejection, Rejects, RejectsArgs};
 use mimirsbrunn::{labels, utils};
 use serde::{Deserialize, Serialize};
 use slog_scope::{info, warn};
-use std::io::stdin;
+use std::io::{stdin, BufRead, BufReader, Read};
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 lazy_static! {
@@ -59,6 +60,130 @@ pub struct OpenAddress {
     pub lon: f64,
 }
 
+/// Properties of a feature of the GeoJSON-line output of OpenAddresses
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct OpenAddressProperties {
+    hash: Option<String>,
+    number: Option<String>,
+    street: Option<String>,
+    unit: Option<String>,
+    city: Option<String>,
+    district: Option<String>,
+    region: Option<String>,
+    postcode: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PointGeometry {
+    coordinates: Vec<f64>,
+}
+
+/// A feature of the GeoJSON-line output of OpenAddresses
+#[derive(Deserialize)]
+struct OpenAddressFeature {
+    #[serde(default)]
+    properties: OpenAddressProperties,
+    geometry: Option<PointGeometry>,
+}
+
+impl OpenAddressFeature {
+    fn into_open_address(self) -> Result<OpenAddress, mimirsbrunn::Error> {
+        let (lon, lat) = match self.geometry.as_ref().map(|g| g.coordinates.as_slice()) {
+            Some([lon, lat, ..]) => (*lon, *lat),
+            _ => {
+                return Err(Rejection::new(
+                    RejectReason::InvalidCoord,
+                    "address without point geometry",
+                )
+                .into())
+            }
+        };
+        let p = self.properties;
+        Ok(OpenAddress {
+            id: p.hash.unwrap_or_default(),
+            street: p.street.unwrap_or_default(),
+            postcode: p.postcode.unwrap_or_default(),
+            district: p.district.unwrap_or_default(),
+            region: p.region.unwrap_or_default(),
+            city: p.city.unwrap_or_default(),
+            number: p.number.unwrap_or_default(),
+            unit: p.unit.unwrap_or_default(),
+            lat,
+            lon,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Csv,
+    GeoJson,
+}
+
+/// Format of a file, from its extension, the `.gz` one being skipped
+fn format_from_extension(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_str()?;
+    let name = name.trim_end_matches(".gz");
+    if name.ends_with(".csv") {
+        Some(Format::Csv)
+    } else if name.ends_with(".geojson") || name.ends_with(".geojsonl") || name.ends_with(".json") {
+        Some(Format::GeoJson)
+    } else {
+        None
+    }
+}
+
+/// Read the addresses of a stream, in the CSV or the GeoJSON-line format.
+/// If the format is not given, it is guessed from the content: a GeoJSON starts with a '{'.
+fn read_open_addresses(
+    stream: impl Read + 'static,
+    format: Option<Format>,
+    rejects: Rejects,
+) -> Box<dyn Iterator<Item = OpenAddress>> {
+    let mut reader = BufReader::new(stream);
+    let format = format.unwrap_or_else(|| {
+        let starts_with_brace = reader
+            .fill_buf()
+            .map(|buf| buf.iter().find(|c| !c.is_ascii_whitespace()) == Some(&b'{'))
+            .unwrap_or(false);
+        if starts_with_brace {
+            Format::GeoJson
+        } else {
+            Format::Csv
+        }
+    });
+    match format {
+        Format::Csv => Box::new(rejects::deserialize_records(
+            csv::ReaderBuilder::new()
+                .has_headers(true)
+                .from_reader(reader),
+            rejects,
+        )),
+        Format::GeoJson => Box::new(
+            reader
+                .lines()
+                .filter_map(|line| {
+                    line.map_err(|e| warn!("Impossible to read line, error: {}", e))
+                        .ok()
+                })
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(move |line| {
+                    serde_json::from_str::<OpenAddressFeature>(&line)
+                        .map_err(failure::Error::from)
+                        .and_then(OpenAddressFeature::into_open_address)
+                        .map_err(|e| {
+                            warn!("Impossible to read line, error: {}", e);
+                            let record = serde_json::from_str::<serde_json::Value>(&line)
+                                .unwrap_or_else(|_| serde_json::Value::String(line.clone()));
+                            rejects.reject_error(RejectReason::InvalidRecord, &record, &e)
+                        })
+                        .ok()
+                }),
+        ),
+    }
+}
+
 impl OpenAddress {
     pub fn into_addr(
         self,
@@ -94,11 +219,15 @@ impl OpenAddress {
             id: street_id,
             name: self.street,
             label: street_label,
+            names: mimir::I18nProperties::default(),
+            labels: mimir::I18nProperties::default(),
+            aliases: vec![],
             administrative_regions: admins,
             weight,
             zip_codes: zip_codes.clone(),
             coord,
             approx_coord: None,
+            geometry: None,
             distance: None,
             country_codes: country_codes.clone(),
             context: None,
@@ -108,18 +237,7 @@ impl OpenAddress {
             if use_old_index_format {
                 String::new()
             } else {
-                format!(
-                    ":{}",
-                    self.number
-                        .replace(" ", "")
-                        .replace("\t", "")
-                        .replace("\r", "")
-                        .replace("\n", "")
-                        .replace("/", "-")
-                        .replace(".", "-")
-                        .replace(":", "-")
-                        .replace(";", "-")
-                )
+                format!(":{}", utils::normalize_house_number_id(&self.number))
             }
         };
 
@@ -156,7 +274,8 @@ impl OpenAddress {
 
 #[derive(StructOpt, Debug)]
 struct Args {
-    /// OpenAddresses files. Can be either a directory or a file.
+    /// OpenAddresses files, in the legacy CSV format or in the GeoJSON-line one, optionally
+    /// gzipped. Can be either a directory or a file.
     /// If this is left empty, addresses are read from standard input.
     #[structopt(short = "i", long = "input", parse(from_os_str))]
     input: Option<PathBuf>,
@@ -198,6 +317,10 @@ struct Args {
     /// therefore, different addresses with the same position will disappear.
     #[structopt(long = "use-old-index-format")]
     use_old_index_format: bool,
+    #[structopt(flatten)]
+    rejects: RejectsArgs,
+    #[structopt(flatten)]
+    publication: utils::PublicationArgs,
 }
 
 fn run(args: Args) -> Result<(), failure::Error> {
@@ -207,8 +330,9 @@ fn run(args: Args) -> Result<(), failure::Error> {
         warn!("city-level option is deprecated, it now has no effect.");
     }
 
-    let mut rubber =
-        Rubber::new(&args.connection_string).with_nb_insert_threads(args.nb_insert_threads);
+    let mut rubber = Rubber::new(&args.connection_string)
+        .with_nb_insert_threads(args.nb_insert_threads)
+        .with_publication_settings(args.publication.settings(&args.input)?);
 
     let index_settings = IndexSettings {
         nb_shards: args.nb_shards,
@@ -232,59 +356,103 @@ fn run(args: Args) -> Result<(), failure::Error> {
         move |a: OpenAddress| a.into_addr(&admins_geofinder, use_old_index_format, id_precision)
     };
 
+    let rejects = args.rejects.open()?;
+
     if let Some(input_path) = args.input {
         // Import from file(s)
-        if input_path.is_dir() {
-            let paths = walkdir::WalkDir::new(&input_path);
-            let path_iter = paths
+        let paths: Vec<PathBuf> = if input_path.is_dir() {
+            walkdir::WalkDir::new(&input_path)
                 .into_iter()
                 .map(|p| p.unwrap().into_path())
                 .filter(|p| {
-                    let f = p
-                        .file_name()
-                        .and_then(|name| name.to_str())
-                        .map(|name| name.ends_with(".csv") || name.ends_with(".csv.gz"))
-                        .unwrap_or(false);
+                    let f = format_from_extension(p).is_some();
                     if !f {
-                        info!("skipping file {} as it is not a csv", p.display());
+                        info!(
+                            "skipping file {} as it is neither a csv nor a geojson",
+                            p.display()
+                        );
                     }
                     f
-                });
-
-            import_addresses_from_files(
-                &mut rubber,
-                true,
-                args.nb_threads,
-                index_settings,
-                &args.dataset,
-                path_iter,
-                into_addr,
-            )
+                })
+                .collect()
         } else {
-            import_addresses_from_files(
-                &mut rubber,
-                true,
-                args.nb_threads,
-                index_settings,
-                &args.dataset,
-                std::iter::once(input_path),
-                into_addr,
-            )
-        }
+            vec![input_path]
+        };
+        let formats: Vec<_> = paths.iter().map(|p| format_from_extension(p)).collect();
+        let addresses = open_files(paths)
+            .zip(formats)
+            .flat_map(|(stream, format)| read_open_addresses(stream, format, rejects.clone()));
+
+        import_addresses(
+            &mut rubber,
+            args.nb_threads,
+            index_settings,
+            &args.dataset,
+            addresses,
+            into_addr,
+            &rejects,
+        )?;
     } else {
         // Import from stdin
-        import_addresses_from_streams(
+        import_addresses(
             &mut rubber,
-            true,
             args.nb_threads,
             index_settings,
             &args.dataset,
-            std::iter::once(stdin()),
+            read_open_addresses(stdin(), None, rejects.clone()),
             into_addr,
-        )
+            &rejects,
+        )?;
     }
+    rejects.finish()
 }
 
 fn main() {
     mimirsbrunn::utils::launch_run(run);
 }
+
+#[test]
+fn test_read_geojson_line() {
+    let addresses: Vec<_> = open_files(vec![PathBuf::from("./tests/fixtures/sample-oa.geojson")])
+        .flat_map(|stream| read_open_addresses(stream, None, Rejects::default()))
+        .collect();
+    assert_eq!(addresses.len(), 2);
+    let address = &addresses[0];
+    assert_eq!(address.id, "7dd34e4c6cc4cdcd");
+    assert_eq!(address.number, "72");
+    assert_eq!(address.street, "Otto-Braun-Straße");
+    assert_eq!(address.city, "Berlin");
+    assert_eq!(address.postcode, "10178");
+    assert_eq!(address.unit, "");
+    assert_float_eq::assert_f64_near!(address.lon, 13.4193129);
+    assert_float_eq::assert_f64_near!(address.lat, 52.5235445);
+}
+
+#[test]
+fn test_format_detection() {
+    assert_eq!(
+        format_from_extension(Path::new("de/berlin.geojson.gz")),
+        Some(Format::GeoJson)
+    );
+    assert_eq!(
+        format_from_extension(Path::new("de/berlin.csv")),
+        Some(Format::Csv)
+    );
+    assert_eq!(format_from_extension(Path::new("de/berlin.txt")), None);
+
+    let rejects_path = std::env::temp_dir().join(format!("oa_rejects_{}", std::process::id()));
+    let rejects = Rejects::new(Some(&rejects_path)).unwrap();
+    let csv = read_open_addresses(
+        std::fs::File::open("./tests/fixtures/sample-oa.csv").unwrap(),
+        None,
+        rejects.clone(),
+    );
+    // the badly formatted line is skipped, and written in the rejects
+    assert_eq!(csv.count(), 12);
+    rejects.finish().unwrap();
+    let report = std::fs::read_to_string(&rejects_path).unwrap();
+    assert_eq!(report.lines().count(), 1);
+    assert!(report.contains(r#""reason":"invalid_record""#));
+    std::fs::remove_file(&rejects_path).unwrap();
+    std::fs::remove_file(rejects::summary_path(&rejects_path)).unwrap();
+}
@@ -28,12 +28,16 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 
-use failure::ResultExt;
+use failure::{ensure, ResultExt};
 use mimir::rubber::{IndexSettings, Rubber};
+use mimirsbrunn::address_geofinder::AddressGeoFinder;
 use mimirsbrunn::admin_geofinder::AdminGeoFinder;
+use mimirsbrunn::osm_reader::address::addresses;
 use mimirsbrunn::osm_reader::admin::read_administrative_regions;
 use mimirsbrunn::osm_reader::make_osm_reader;
-use mimirsbrunn::osm_reader::poi::{add_address, compute_poi_weight, pois, PoiConfig};
+use mimirsbrunn::osm_reader::poi::{
+    add_address, add_address_from_finder, compute_poi_weight, pois, PoiConfig,
+};
 use mimirsbrunn::osm_reader::street::{compute_street_weight, streets};
 use mimirsbrunn::settings::osm2mimir::{Args, Settings};
 use slog_scope::{debug, info};
@@ -41,12 +45,21 @@ use slog_scope::{debug, info};
 fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
     let input = args.input.clone(); // we save the input, because args will be consumed by settings.
     validate_args(&args)?;
+    let rejects = args.rejects.open()?;
+    let publication_settings = args.publication.settings(&[&input])?;
     let settings = Settings::new(args)?;
+    if let Some(poi) = &settings.poi {
+        ensure!(
+            poi.in_memory_reverse || poi.addresses_dump.is_none(),
+            "the addresses dump is only used with --in-memory-reverse"
+        );
+    }
 
     let mut osm_reader = make_osm_reader(&input)?;
     debug!("creation of indexes");
     let mut rubber = Rubber::new(&settings.elasticsearch.connection_string)
-        .with_nb_insert_threads(settings.elasticsearch.insert_thread_count);
+        .with_nb_insert_threads(settings.elasticsearch.insert_thread_count)
+        .with_publication_settings(publication_settings);
     rubber.initialize_templates()?;
 
     let settings = &settings;
@@ -72,6 +85,13 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
 
     let admins_geofinder = admins.into_iter().collect::<AdminGeoFinder>();
 
+    let langs = if settings.langs.is_empty() {
+        admins_geofinder.langs()
+    } else {
+        settings.langs.clone()
+    };
+    info!("using the languages {:?} for the names and labels", langs);
+
     if settings
         .street
         .as_ref()
@@ -79,7 +99,7 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
         .unwrap_or_else(|| false)
     {
         info!("Extracting streets from osm");
-        let mut streets = streets(&mut osm_reader, &admins_geofinder, &settings)?;
+        let mut streets = streets(&mut osm_reader, &admins_geofinder, &settings, &langs)?;
 
         info!("computing street weight");
         compute_street_weight(&mut streets);
@@ -103,6 +123,35 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
             })?;
         info!("Nb of indexed street: {}", nb_streets);
     }
+    if let Some(address_settings) = settings.address.as_ref().filter(|address| address.import) {
+        // the osm addresses get their own dataset, so that they don't replace the addresses
+        // imported by bano2mimir or openaddresses2mimir in munin_addr_<dataset>
+        let address_dataset = address_settings
+            .dataset
+            .clone()
+            .unwrap_or_else(|| format!("{}_osm", settings.dataset));
+        info!("Extracting addresses from osm");
+        let addresses = addresses(&mut osm_reader, &admins_geofinder, &settings, &rejects)?;
+
+        let address_index_settings = IndexSettings {
+            nb_shards: settings.elasticsearch.addresses_shards,
+            nb_replicas: settings.elasticsearch.addresses_replicas,
+        };
+        info!("importing addresses into Mimir");
+        let nb_addresses = rubber
+            .public_index(
+                &address_dataset,
+                &address_index_settings,
+                addresses.into_iter(),
+            )
+            .with_context(|err| {
+                format!(
+                    "Error occurred when requesting address number in {}: {}",
+                    address_dataset, err
+                )
+            })?;
+        info!("Nb of indexed addresses: {}", nb_addresses);
+    }
     if settings
         .admin
         .as_ref()
@@ -139,15 +188,43 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
             .as_ref()
             .and_then(|poi| poi.config.clone())
             .unwrap_or_else(PoiConfig::default);
+        config
+            .check()
+            .map_err(|e| failure::format_err!("Invalid poi configuration: {}", e))?;
 
         info!("Extracting pois from osm");
-        let mut pois = pois(&mut osm_reader, &config, &admins_geofinder);
+        let mut pois = pois(
+            &mut osm_reader,
+            &config,
+            &admins_geofinder,
+            &langs,
+            &rejects,
+        );
 
         info!("computing poi weight");
         compute_poi_weight(&mut pois);
 
-        info!("Adding address in poi");
-        add_address(&mut pois, &mut rubber);
+        let poi_settings = settings.poi.as_ref().expect("poi settings");
+        // in dump mode, the addresses can only be searched in the dumped ones
+        let pois = if poi_settings.in_memory_reverse || rubber.dump_dir.is_some() {
+            // only the addresses around the pois without address are needed
+            let addresses_geofinder = AddressGeoFinder::load_around(
+                &rubber,
+                poi_settings.addresses_dump.as_deref(),
+                pois.iter()
+                    .filter(|poi| poi.address.is_none())
+                    .map(|poi| &poi.coord),
+            )?;
+            info!("Adding address in poi");
+            add_address_from_finder(
+                pois,
+                addresses_geofinder,
+                settings.elasticsearch.reverse_thread_count,
+            )
+        } else {
+            info!("Adding address in poi");
+            add_address(pois, &rubber, settings.elasticsearch.reverse_thread_count)
+        };
 
         let poi_index_settings = IndexSettings {
             nb_shards: settings.elasticsearch.pois_shards,
@@ -160,7 +237,7 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
 
         info!("Nb of indexed pois: {}", nb_pois);
     }
-    Ok(())
+    rejects.finish()
 }
 
 // We need to allow for unused variables, because currently all the checks on
@@ -32,10 +32,15 @@ use slog_scope::{error, info};
 
 use failure::format_err;
 use lazy_static::lazy_static;
-use mimir::objects::{Coord, I18nProperties, Poi, PoiType, Property};
-use mimir::rubber::{IndexSettings, IndexVisibility, Rubber, TypedIndex};
-use mimirsbrunn::{admin_geofinder::AdminGeoFinder, labels, utils};
+use mimir::objects::{Coord, I18nProperties, Place, Poi, PoiType, Property};
+use mimir::rubber::{IndexSettings, IndexVisibility, PublicationSettings, Rubber, TypedIndex};
+use mimirsbrunn::geojson_poi::{is_geojson, PoiMapping, SourcePoi};
+use mimirsbrunn::rejects::{RejectReason, Rejection, Rejects, RejectsArgs};
+use mimirsbrunn::{
+    address_geofinder::AddressGeoFinder, admin_geofinder::AdminGeoFinder, labels, utils,
+};
 use navitia_poi_model::{Model as NavitiaModel, Poi as NavitiaPoi, PoiType as NavitiaPoiType};
+use par_map::ParMap;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
@@ -45,33 +50,80 @@ lazy_static! {
     static ref DEFAULT_NB_THREADS: String = num_cpus::get().to_string();
 }
 
-// This function takes a Poi from the navitia model, ie from the CSV deserialization, and returns
-// a Poi from the mimir model, with all the contextual information added.
-fn into_mimir_poi(
+// Where the addresses of the POIs are searched
+enum AddressFinder {
+    Elasticsearch(Rubber),
+    InMemory(AddressGeoFinder),
+}
+
+impl AddressFinder {
+    // Nearest address or street of the coord
+    fn get(&self, coord: &Coord) -> Option<Place> {
+        match self {
+            AddressFinder::Elasticsearch(rubber) => rubber
+                .reverse(coord, None) // No timeout
+                .ok()
+                .and_then(|addrs| addrs.into_iter().next()), // Take the first place
+            AddressFinder::InMemory(addresses_geofinder) => addresses_geofinder.get(coord),
+        }
+    }
+}
+
+// The fields of a Poi from the navitia model written in the rejects
+#[derive(serde::Serialize)]
+struct NavitiaPoiRecord<'a> {
+    id: &'a str,
+    name: &'a str,
+    poi_type_id: &'a str,
+    coord: Coord,
+}
+
+// This function takes a Poi from the navitia model, ie from the CSV deserialization.
+fn from_navitia_poi(
     poi: NavitiaPoi,
     poi_types: &HashMap<String, NavitiaPoiType>,
-    rubber: &mut Rubber,
-    admins_geofinder: &AdminGeoFinder,
-) -> Result<Poi, mimirsbrunn::Error> {
+) -> Result<SourcePoi, mimirsbrunn::Error> {
     let poi_type = poi_types
         .get(&poi.poi_type_id)
-        .ok_or_else(|| format_err!("could not find Poi Type '{}'", poi.poi_type_id))
+        .ok_or_else(|| {
+            Rejection::new(
+                RejectReason::UnknownPoiType,
+                format!("could not find Poi Type '{}'", poi.poi_type_id),
+            )
+        })
         .map(PoiType::from)?;
 
-    let coord = Coord::from(&poi.coord);
+    Ok(SourcePoi {
+        id: poi.id,
+        name: poi.name,
+        coord: Coord::from(&poi.coord),
+        poi_type,
+        properties: poi.properties.into_iter().map(Property::from).collect(),
+    })
+}
+
+// This function takes a Poi read from the input file and returns
+// a Poi from the mimir model, with all the contextual information added.
+fn into_mimir_poi(
+    poi: SourcePoi,
+    address_finder: &AddressFinder,
+    admins_geofinder: &AdminGeoFinder,
+) -> Result<Poi, mimirsbrunn::Error> {
+    let coord = poi.coord;
 
-    let place = rubber
-        .get_address(&coord) // No timeout
-        .ok()
-        .and_then(|addrs| addrs.into_iter().next()); // Take the first place
+    let place = address_finder.get(&coord);
 
     let addr = place.as_ref().and_then(|place| place.address());
 
     // We the the admins from the address, or, if we don't have any, from the geofinder.
-    let admins = place.map_or_else(|| admins_geofinder.get(&poi.coord), |addr| addr.admins());
+    let admins = place.map_or_else(|| admins_geofinder.get(&coord), |addr| addr.admins());
 
     if admins.is_empty() {
-        return Err(format_err!("Could not find admins for POI {}", &poi.id));
+        return Err(Rejection::new(
+            RejectReason::NoAdmin,
+            format!("Could not find admins for POI {}", &poi.id),
+        )
+        .into());
     }
 
     // The weight is that of the city, or 0.0 if there is no such admin.
@@ -87,6 +139,13 @@ fn into_mimir_poi(
     let label =
         labels::format_poi_label(&poi.name, admins.iter().map(|a| a.deref()), &country_codes);
 
+    let aliases = utils::get_aliases(
+        &poi.name,
+        poi.properties
+            .iter()
+            .map(|p| (p.key.as_str(), p.value.as_str())),
+    );
+
     let poi = Poi {
         id: mimir::objects::normalize_id("poi", &poi.id),
         label,
@@ -96,12 +155,13 @@ fn into_mimir_poi(
         administrative_regions: admins,
         weight,
         zip_codes: vec![],
-        poi_type,
-        properties: poi.properties.into_iter().map(Property::from).collect(),
+        poi_type: poi.poi_type,
+        properties: poi.properties,
         address: addr,
         country_codes,
         names: I18nProperties::default(),
         labels: I18nProperties::default(),
+        aliases,
         distance: None,
         context: None,
     };
@@ -109,27 +169,70 @@ fn into_mimir_poi(
     Ok(poi)
 }
 
+fn read_pois(
+    file: &Path,
+    mapping: Option<&Path>,
+    rejects: &Rejects,
+) -> Result<Vec<SourcePoi>, mimirsbrunn::Error> {
+    if is_geojson(file) {
+        let mapping = mapping
+            .ok_or_else(|| format_err!("a mapping is needed to read the GeoJSON {:?}", file))?;
+        PoiMapping::from_path(mapping)?.read_pois(file, rejects)
+    } else {
+        let model = NavitiaModel::try_from_path(file)?;
+        let poi_types = model.poi_types;
+        Ok(model
+            .pois
+            .into_iter()
+            .filter_map(|(id, poi)| {
+                let record = rejects.record_of(&NavitiaPoiRecord {
+                    id: &poi.id,
+                    name: &poi.name,
+                    poi_type_id: &poi.poi_type_id,
+                    coord: Coord::from(&poi.coord),
+                });
+                from_navitia_poi(poi, &poi_types)
+                    .map_err(|err| {
+                        info!("Could not read POI '{}': {}", id, err);
+                        rejects.reject_error(RejectReason::InvalidRecord, &record, &err)
+                    })
+                    .ok()
+            })
+            .collect())
+    }
+}
+
 fn import_pois(
     rubber: &mut Rubber,
     index: &TypedIndex<Poi>,
     admins_geofinder: AdminGeoFinder,
-    file: &Path,
+    source_pois: Vec<SourcePoi>,
+    address_finder: AddressFinder,
+    rejects: &Rejects,
+    nb_threads: usize,
 ) -> Result<(), mimirsbrunn::Error>
 where
 {
     info!("Add data in elasticsearch db.");
 
-    let model = NavitiaModel::try_from_path(file)?;
-    let poi_types = model.poi_types;
-
+    // The POIs are enriched in parallel, the address finder and the admins being read-only.
     // Note: We're ignoring those POIs that fail to be enriched.
-    let pois: Vec<_> = model
-        .pois
+    let record_rejects = rejects.clone();
+    let pois: Vec<_> = source_pois
         .into_iter()
-        .filter_map(|(id, poi)| {
-            into_mimir_poi(poi, &poi_types, rubber, &admins_geofinder)
-                .map_err(|err| info!("Could not extract information for POI '{}': {}", id, err))
-                .ok()
+        .with_nb_threads(nb_threads)
+        .par_map(move |poi| {
+            let id = poi.id.clone();
+            let record = record_rejects.record_of(&poi);
+            let poi = into_mimir_poi(poi, &address_finder, &admins_geofinder);
+            (id, record, poi)
+        })
+        .filter_map(|(id, record, poi)| {
+            poi.map_err(|err| {
+                info!("Could not extract information for POI '{}': {}", id, err);
+                rejects.reject_error(RejectReason::InvalidData, &record, &err)
+            })
+            .ok()
         })
         .collect(); // TODO Can we get rid of collect, and chain with the following rubber...?
 
@@ -144,17 +247,24 @@ where
 
 /// This function initializes the ES context: It creates an index for this dataset,
 /// and then import the POIs in it.
+#[allow(clippy::too_many_arguments)]
 fn index_poi(
     cnx_string: &str,
     dataset: &str,
     file: &Path,
+    mapping: Option<&Path>,
     visibility: IndexVisibility,
     nb_shards: usize,
     nb_replicas: usize,
+    in_memory_reverse: bool,
+    addresses_dump: Option<&Path>,
+    rejects: &Rejects,
+    publication_settings: PublicationSettings,
+    nb_threads: usize,
 ) -> Result<(), mimirsbrunn::Error>
 where
 {
-    let mut rubber = Rubber::new(cnx_string);
+    let mut rubber = Rubber::new(cnx_string).with_publication_settings(publication_settings);
     rubber.initialize_templates()?;
 
     let settings = IndexSettings {
@@ -170,7 +280,28 @@ where
     })?;
     let admins_geofinder = admins.into_iter().collect();
 
-    import_pois(&mut rubber, &index, admins_geofinder, file)?;
+    let source_pois = read_pois(file, mapping, rejects)?;
+
+    // in dump mode, the addresses can only be searched in the dumped ones
+    let address_finder = if in_memory_reverse || rubber.dump_dir.is_some() {
+        AddressFinder::InMemory(AddressGeoFinder::load_around(
+            &rubber,
+            addresses_dump,
+            source_pois.iter().map(|poi| &poi.coord),
+        )?)
+    } else {
+        AddressFinder::Elasticsearch(rubber.clone())
+    };
+
+    import_pois(
+        &mut rubber,
+        &index,
+        admins_geofinder,
+        source_pois,
+        address_finder,
+        rejects,
+        nb_threads,
+    )?;
 
     rubber
         .publish_index(dataset, index, visibility)
@@ -179,10 +310,16 @@ where
 
 #[derive(StructOpt, Debug)]
 struct Args {
-    /// POI file
+    /// POI file, either a navitia POI file, a GeoJSON FeatureCollection (`.geojson`, `.json`)
+    /// or a line-delimited GeoJSON (`.ndjson`, `.geojsonl`, `.geojsons`, `.jsonl`)
     #[structopt(short = "i", long = "input", parse(from_os_str))]
     input: PathBuf,
 
+    /// TOML mapping picking the id, name, poi type and properties of the GeoJSON features.
+    /// Mandatory for the GeoJSON inputs.
+    #[structopt(short = "m", long = "mapping", parse(from_os_str))]
+    mapping: Option<PathBuf>,
+
     /// Elasticsearch parameters.
     #[structopt(
         short = "c",
@@ -215,6 +352,27 @@ struct Args {
     /// Number of replicas for the es index
     #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
     nb_replicas: usize,
+
+    /// Search the addresses of the POIs in memory instead of querying Elasticsearch for each POI.
+    /// The addresses and streets around the POIs are loaded from Elasticsearch,
+    /// or from the addresses dump.
+    #[structopt(long = "in-memory-reverse")]
+    in_memory_reverse: bool,
+
+    /// Dump directory (written by an importer with a `file://` connection string) from which
+    /// the addresses and streets are loaded for the in memory search of the POIs addresses.
+    #[structopt(
+        long = "addresses-dump",
+        parse(from_os_str),
+        requires = "in_memory_reverse"
+    )]
+    addresses_dump: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    rejects: RejectsArgs,
+
+    #[structopt(flatten)]
+    publication: utils::PublicationArgs,
 }
 
 fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
@@ -224,14 +382,23 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
         IndexVisibility::Public
     };
 
+    let rejects = args.rejects.open()?;
+
     index_poi(
         &args.connection_string,
         &args.dataset,
         &args.input,
+        args.mapping.as_deref(),
         visibility,
         args.nb_shards,
         args.nb_replicas,
-    )
+        args.in_memory_reverse,
+        args.addresses_dump.as_deref(),
+        &rejects,
+        args.publication.settings(&[&args.input])?,
+        args.nb_threads,
+    )?;
+    rejects.finish()
 }
 fn main() {
     mimirsbrunn::utils::launch_run(run);
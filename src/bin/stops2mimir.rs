@@ -30,11 +30,12 @@
 
 use failure::ResultExt;
 use mimir::rubber::IndexSettings;
+use mimirsbrunn::rejects::{self, RejectReason, Rejects, RejectsArgs};
 use mimirsbrunn::stops::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use slog_scope::{info, warn};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 const MAX_LAT: f64 = 90f64;
@@ -45,7 +46,8 @@ const MIN_LON: f64 = -180f64;
 
 #[derive(Debug, StructOpt)]
 struct Args {
-    /// NTFS stops.txt file.
+    /// GTFS directory or zip file.
+    /// A single stops.txt file can also be given, the stops then having no lines nor modes.
     #[structopt(short = "i", long = "input", parse(from_os_str))]
     input: PathBuf,
     /// Name of the dataset.
@@ -67,6 +69,14 @@ struct Args {
     /// Number of replicas for the es index
     #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
     nb_replicas: usize,
+    /// Also index the stop points and the entrances of the stop areas.
+    /// They are only searched when the `public_transport:stop_point` type is asked.
+    #[structopt(long = "stop-points")]
+    stop_points: bool,
+    #[structopt(flatten)]
+    rejects: RejectsArgs,
+    #[structopt(flatten)]
+    publication: mimirsbrunn::utils::PublicationArgs,
 }
 
 #[derive(Deserialize, Debug)]
@@ -75,11 +85,13 @@ enum StopConversionErr {
     InvisibleStop,
     ///The stop in the line is not a StopArea
     NotStopArea,
+    ///The stop in the line is neither a StopPoint nor an entrance
+    NotStopPoint,
     ///Values of one or more attributes are not valid
     InvalidStop(String),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct GtfsStop {
     stop_id: String,
     stop_lat: f64,
@@ -101,22 +113,27 @@ impl GtfsStop {
             _ => (),
         }
     }
+    fn has_valid_coord(&self) -> bool {
+        self.stop_lat > MIN_LAT
+            && self.stop_lat < MAX_LAT
+            && self.stop_lon > MIN_LON
+            && self.stop_lon < MAX_LON
+    }
+    fn invalid_coord_err(&self) -> StopConversionErr {
+        StopConversionErr::InvalidStop(format!(
+            "Invalid lon {:?} or lat {:?} for stop {:?}",
+            self.stop_lon, self.stop_lat, self.stop_name
+        ))
+    }
     // to be moved when TryInto is stablilized
     fn try_into(self) -> Result<mimir::Stop, StopConversionErr> {
         if self.location_type != Some(1) {
             Err(StopConversionErr::NotStopArea)
         } else if self.visible == Some(0) {
             Err(StopConversionErr::InvisibleStop)
-        } else if self.stop_lat <= MIN_LAT
-            || self.stop_lat >= MAX_LAT
-            || self.stop_lon <= MIN_LON
-            || self.stop_lon >= MAX_LON
-        {
+        } else if !self.has_valid_coord() {
             //Here we return an error message
-            Err(StopConversionErr::InvalidStop(format!(
-                "Invalid lon {:?} or lat {:?} for stop {:?}",
-                self.stop_lon, self.stop_lat, self.stop_name
-            )))
+            Err(self.invalid_coord_err())
         } else {
             let coord = mimir::Coord::new(self.stop_lon, self.stop_lat);
             Ok(mimir::Stop {
@@ -129,50 +146,191 @@ impl GtfsStop {
             })
         }
     }
-    fn try_into_with_warn(self) -> Option<mimir::Stop> {
+    /// Convert a stop point or an entrance, `stop_area_names` giving the name
+    /// of the parent stop areas by id
+    fn try_into_stop_point(
+        self,
+        stop_area_names: &HashMap<String, String>,
+    ) -> Result<mimir::StopPoint, StopConversionErr> {
+        let stop_type = match self.location_type {
+            None | Some(0) => mimir::StopPointType::StopPoint,
+            Some(2) => mimir::StopPointType::Entrance,
+            _ => return Err(StopConversionErr::NotStopPoint),
+        };
+        if self.visible == Some(0) {
+            return Err(StopConversionErr::InvisibleStop);
+        }
+        if !self.has_valid_coord() {
+            return Err(self.invalid_coord_err());
+        }
+        let parent = self
+            .parent_station
+            .as_ref()
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                let id = format!("stop_area:{}", id);
+                mimir::ParentStopArea {
+                    name: stop_area_names.get(&id).cloned().unwrap_or_default(),
+                    id,
+                }
+            });
+        let coord = mimir::Coord::new(self.stop_lon, self.stop_lat);
+        Ok(mimir::StopPoint {
+            id: format!("stop_point:{}", self.stop_id), // prefix to match navitia's id
+            coord,
+            approx_coord: Some(coord.into()),
+            label: self.stop_name.clone(),
+            name: self.stop_name,
+            stop_type,
+            parent,
+            ..Default::default()
+        })
+    }
+    fn try_into_with_warn(self, rejects: &Rejects) -> Option<mimir::Stop> {
+        let record = rejects.record_of(&self);
         match self.try_into() {
             Ok(s) => Some(s),
             Err(StopConversionErr::InvisibleStop) => None,
             Err(StopConversionErr::NotStopArea) => None,
+            Err(StopConversionErr::NotStopPoint) => None,
             Err(StopConversionErr::InvalidStop(msg)) => {
                 warn!("skip csv line: {}", msg);
+                rejects.reject(RejectReason::InvalidCoord, &record, msg);
                 None
             }
         }
     }
 }
 
-fn run(args: Args) -> Result<(), failure::Error> {
-    info!("Launching stops2mimir...");
-    if args.city_level.is_some() {
-        warn!("city-level option is deprecated, it now has no effect.");
-    }
-
-    let mut rdr = csv::Reader::from_path(&args.input)?;
+/// Read the stops of a single stops.txt file
+fn read_stops_file(path: &Path, rejects: &Rejects) -> Result<Vec<mimir::Stop>, failure::Error> {
+    let rdr = csv::Reader::from_path(path)?;
     let mut nb_stop_points = HashMap::new();
-    let mut stops: Vec<mimir::Stop> = rdr
-        .deserialize()
-        .filter_map(|rc| rc.map_err(|e| warn!("skip csv line: {}", e)).ok())
+    let mut stops: Vec<mimir::Stop> = rejects::deserialize_records(rdr, rejects.clone())
         .filter_map(|stop: GtfsStop| {
             stop.incr_stop_point(&mut nb_stop_points);
-            stop.try_into_with_warn()
+            stop.try_into_with_warn(rejects)
         })
         .collect();
     initialize_weights(stops.iter_mut(), &nb_stop_points);
+    Ok(stops)
+}
+
+/// Read the stop points and the entrances of a single stops.txt file.
+/// The lines that can't be read are already reported with the stop areas.
+fn read_stop_points_file(
+    path: &Path,
+    rejects: &Rejects,
+) -> Result<Vec<mimir::StopPoint>, failure::Error> {
+    let mut rdr = csv::Reader::from_path(path)?;
+    let gtfs_stops: Vec<GtfsStop> = rdr
+        .deserialize()
+        .filter_map(|rc| rc.map_err(|e| warn!("skip csv line: {}", e)).ok())
+        .collect();
+    let stop_area_names: HashMap<String, String> = gtfs_stops
+        .iter()
+        .filter(|stop| stop.location_type == Some(1))
+        .map(|stop| {
+            (
+                format!("stop_area:{}", stop.stop_id),
+                stop.stop_name.clone(),
+            )
+        })
+        .collect();
+    Ok(gtfs_stops
+        .into_iter()
+        .filter_map(|stop| {
+            let record = rejects.record_of(&stop);
+            match stop.try_into_stop_point(&stop_area_names) {
+                Ok(stop_point) => Some(stop_point),
+                Err(StopConversionErr::InvalidStop(msg)) => {
+                    warn!("skip csv line: {}", msg);
+                    rejects.reject(RejectReason::InvalidCoord, &record, msg);
+                    None
+                }
+                Err(_) => None,
+            }
+        })
+        .collect())
+}
+
+/// Read a whole GTFS, to get the stops with their lines, modes and feed publishers,
+/// as it is done for a NTFS.
+/// The feed publisher of the stops is named after the dataset.
+fn read_gtfs(path: &Path, dataset: &str) -> Result<transit_model::Model, failure::Error> {
+    let configuration = transit_model::gtfs::Configuration {
+        contributor: transit_model::objects::Contributor {
+            id: dataset.to_string(),
+            name: dataset.to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let is_zip = path.extension().map_or(false, |ext| ext == "zip");
+    let navitia = if is_zip {
+        transit_model::gtfs::read_from_zip(path, configuration)
+    } else {
+        transit_model::gtfs::read_from_path(path, configuration)
+    }
+    .with_context(|e| format!("Could not read the GTFS {:?}: {}", path, e))?;
+    Ok(navitia)
+}
+
+fn is_stops_file(path: &Path) -> bool {
+    path.is_file() && path.extension().map_or(false, |ext| ext == "txt")
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    info!("Launching stops2mimir...");
+    if args.city_level.is_some() {
+        warn!("city-level option is deprecated, it now has no effect.");
+    }
+
+    let rejects = args.rejects.open()?;
+
+    let (stops, stop_points) = if is_stops_file(&args.input) {
+        let stop_points = if args.stop_points {
+            read_stop_points_file(&args.input, &rejects)?
+        } else {
+            vec![]
+        };
+        (read_stops_file(&args.input, &rejects)?, stop_points)
+    } else {
+        let navitia = read_gtfs(&args.input, &args.dataset)?;
+        let stop_points = if args.stop_points {
+            stop_points_from_model(&navitia)
+        } else {
+            vec![]
+        };
+        (stops_from_model(&navitia), stop_points)
+    };
 
     let index_settings = IndexSettings {
         nb_shards: args.nb_shards,
         nb_replicas: args.nb_replicas,
     };
 
+    let publication_settings = args.publication.settings(&[&args.input])?;
     import_stops(
         stops,
         &args.connection_string,
         &args.dataset,
-        index_settings,
+        index_settings.clone(),
+        publication_settings.clone(),
     )
     .context("Error while importing stops")?;
-    Ok(())
+
+    if args.stop_points {
+        import_stop_points(
+            stop_points,
+            &args.connection_string,
+            &args.dataset,
+            index_settings,
+            publication_settings,
+        )
+        .context("Error while importing stop points")?;
+    }
+    rejects.finish()
 }
 
 fn main() {
@@ -190,7 +348,7 @@ fn test_load_stops() {
         .filter_map(Result::ok)
         .filter_map(|stop: GtfsStop| {
             stop.incr_stop_point(&mut nb_stop_points);
-            stop.try_into_with_warn()
+            stop.try_into_with_warn(&Rejects::default())
         })
         .collect();
     let ids: Vec<_> = stops.iter().map(|s| s.id.clone()).sorted().collect();
@@ -222,3 +380,78 @@ fn test_load_stops() {
         ]
     );
 }
+
+#[test]
+fn test_load_gtfs() {
+    let navitia = read_gtfs(Path::new("./tests/fixtures/gtfs"), "dataset1").unwrap();
+    let stops = stops_from_model(&navitia);
+    let main_station = stops
+        .iter()
+        .find(|s| s.id == "stop_area:SA:main_station")
+        .unwrap();
+    let second_station = stops
+        .iter()
+        .find(|s| s.id == "stop_area:SA:second_station")
+        .unwrap();
+    assert_eq!(stops.len(), 2);
+    assert_float_eq::assert_f64_near!(main_station.weight, 1.);
+    assert_float_eq::assert_f64_near!(second_station.weight, 0.5);
+    for stop in &stops {
+        assert_eq!(stop.lines.len(), 1);
+        assert_eq!(stop.lines[0].code, Some("1".to_string()));
+        assert_eq!(stop.physical_modes.len(), 1);
+        assert_eq!(stop.commercial_modes.len(), 1);
+        assert_eq!(
+            stop.feed_publishers
+                .iter()
+                .map(|f| f.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["dataset1"]
+        );
+    }
+}
+
+#[test]
+fn test_load_stop_points() {
+    use itertools::Itertools;
+    let stop_points =
+        read_stop_points_file(Path::new("./tests/fixtures/stops.txt"), &Rejects::default())
+            .unwrap();
+    let ids: Vec<_> = stop_points.iter().map(|s| s.id.as_str()).sorted().collect();
+    assert_eq!(
+        ids,
+        vec![
+            "stop_point:SP:main_station",
+            "stop_point:SP:second_station",
+            "stop_point:SP:weight_1_station_1",
+            "stop_point:SP:weight_3_station_1",
+            "stop_point:SP:weight_3_station_2",
+            "stop_point:SP:weight_3_station_3",
+        ]
+    );
+    let main_station = stop_points
+        .iter()
+        .find(|s| s.id == "stop_point:SP:main_station")
+        .unwrap();
+    let parent = main_station.parent.as_ref().unwrap();
+    assert_eq!(parent.id, "stop_area:SA:main_station");
+    assert_eq!(parent.name, "République");
+    assert_eq!(main_station.stop_type, mimir::StopPointType::StopPoint);
+}
+
+#[test]
+fn test_load_gtfs_stop_points() {
+    let navitia = read_gtfs(Path::new("./tests/fixtures/gtfs"), "dataset1").unwrap();
+    let stop_points = stop_points_from_model(&navitia);
+    assert_eq!(stop_points.len(), 3);
+    let stop_point = stop_points
+        .iter()
+        .find(|s| s.id == "stop_point:SP:main_station:1")
+        .unwrap();
+    assert_eq!(
+        stop_point.parent.as_ref().map(|p| p.id.as_str()),
+        Some("stop_area:SA:main_station")
+    );
+    assert_eq!(stop_point.lines.len(), 1);
+    assert_float_eq::assert_f64_near!(stop_point.weight, 1.);
+}
@@ -0,0 +1,322 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Reading of POIs from a GeoJSON FeatureCollection, or from line-delimited GeoJSON features.
+//! The properties of the features are picked by a TOML mapping:
+//!
+//! ```toml
+//! # property giving the id of the poi, the id of the feature is used if not given
+//! id = "ref"
+//! name = "name"
+//! poi_type = "category"
+//! # properties kept as mimir::Property, all the properties are kept if not given
+//! properties = ["opening_hours", "website"]
+//!
+//! # name of the poi types, the value of the `poi_type` property is used if not given
+//! [poi_types]
+//! restaurant = "Restaurant"
+//! ```
+
+use crate::rejects::{RejectReason, Rejection, Rejects};
+use crate::Error;
+use failure::{bail, ResultExt};
+use geo::centroid::Centroid;
+use mimir::objects::{Coord, PoiType, Property};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use slog_scope::warn;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PoiMapping {
+    pub id: Option<String>,
+    pub name: String,
+    pub poi_type: String,
+    pub properties: Option<Vec<String>>,
+    #[serde(default)]
+    pub poi_types: BTreeMap<String, String>,
+}
+
+impl PoiMapping {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|_| format!("Impossible to read the mapping {:?}", path))?;
+        Ok(toml::from_str(&content).with_context(|_| format!("Invalid mapping {:?}", path))?)
+    }
+
+    fn is_kept(&self, key: &str) -> bool {
+        match self.properties {
+            Some(ref properties) => properties.iter().any(|p| p == key),
+            None => key != self.name && key != self.poi_type && Some(key) != self.id.as_deref(),
+        }
+    }
+
+    /// Build a poi from a GeoJSON feature
+    pub fn read_feature(&self, feature: &Value) -> Result<SourcePoi, Error> {
+        let empty = Map::new();
+        let properties = feature
+            .get("properties")
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        let get = |key: &str| properties.get(key).and_then(value_to_string);
+
+        let id = match self.id {
+            Some(ref key) => get(key),
+            None => feature.get("id").and_then(value_to_string),
+        }
+        .ok_or_else(|| Rejection::new(RejectReason::MissingId, "feature without id"))?;
+        let name = get(&self.name).ok_or_else(|| {
+            Rejection::new(RejectReason::MissingName, format!("poi {} has no name", id))
+        })?;
+        let poi_type = get(&self.poi_type)
+            .map(|t| PoiType {
+                id: format!("poi_type:{}", t),
+                name: self.poi_types.get(&t).cloned().unwrap_or_else(|| t.clone()),
+            })
+            .ok_or_else(|| {
+                Rejection::new(
+                    RejectReason::UnknownPoiType,
+                    format!("poi {} has no type", id),
+                )
+            })?;
+        let coord = read_coord(feature.get("geometry")).ok_or_else(|| {
+            Rejection::new(
+                RejectReason::InvalidCoord,
+                format!("poi {} has no valid geometry", id),
+            )
+        })?;
+        let properties = properties
+            .iter()
+            .filter(|(k, _)| self.is_kept(k))
+            .filter_map(|(k, v)| {
+                value_to_string(v).map(|value| Property {
+                    key: k.clone(),
+                    value,
+                })
+            })
+            .collect();
+
+        Ok(SourcePoi {
+            id,
+            name,
+            coord,
+            poi_type,
+            properties,
+        })
+    }
+
+    /// Read the pois of a file, either a FeatureCollection (`.geojson` or `.json`),
+    /// or a file with a feature per line (`.ndjson`, `.geojsonl`, `.geojsons`).
+    /// The features that can't be read are skipped, and written in the rejects.
+    pub fn read_pois(&self, path: &Path, rejects: &Rejects) -> Result<Vec<SourcePoi>, Error> {
+        let file = File::open(path).with_context(|_| format!("Impossible to open {:?}", path))?;
+        let features: Vec<Value> = if is_line_delimited(path) {
+            BufReader::new(file)
+                .lines()
+                .filter_map(|line| {
+                    line.map_err(|e| warn!("Impossible to read line, error: {}", e))
+                        .ok()
+                })
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(|line| {
+                    serde_json::from_str(line.trim_start_matches('\x1e'))
+                        .map_err(|e| {
+                            warn!("Impossible to read feature, error: {}", e);
+                            rejects.reject(RejectReason::InvalidRecord, &line, e)
+                        })
+                        .ok()
+                })
+                .collect()
+        } else {
+            let mut value: Value = serde_json::from_reader(BufReader::new(file))
+                .with_context(|_| format!("Invalid GeoJSON {:?}", path))?;
+            match value.get("type").and_then(Value::as_str) {
+                Some("FeatureCollection") => match value.get_mut("features").map(Value::take) {
+                    Some(Value::Array(features)) => features,
+                    _ => bail!("FeatureCollection without features in {:?}", path),
+                },
+                Some("Feature") => vec![value],
+                _ => bail!("{:?} is neither a Feature nor a FeatureCollection", path),
+            }
+        };
+        Ok(features
+            .iter()
+            .filter_map(|feature| {
+                self.read_feature(feature)
+                    .map_err(|e| {
+                        warn!("Skipping feature: {}", e);
+                        rejects.reject_error(RejectReason::InvalidRecord, feature, &e)
+                    })
+                    .ok()
+            })
+            .collect())
+    }
+}
+
+/// A poi read from a source file, before being attached to its admins and address
+#[derive(Debug, Clone, Serialize)]
+pub struct SourcePoi {
+    pub id: String,
+    pub name: String,
+    pub coord: Coord,
+    pub poi_type: PoiType,
+    pub properties: Vec<Property>,
+}
+
+fn is_line_delimited(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            ext == "ndjson" || ext == "geojsonl" || ext == "geojsons" || ext == "jsonl"
+        })
+}
+
+/// Tells if the file is a GeoJSON one, from its extension
+pub fn is_geojson(path: &Path) -> bool {
+    is_line_delimited(path)
+        || path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| ext == "geojson" || ext == "json")
+}
+
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+// The coord of a feature: its point, or the centroid of its other geometries
+fn read_coord(geometry: Option<&Value>) -> Option<Coord> {
+    let geometry: geojson::Geometry = serde_json::from_value(geometry?.clone()).ok()?;
+    let geometry: geo_types::Geometry<f64> = geometry.value.try_into().ok()?;
+    geometry
+        .centroid()
+        .map(|centroid| Coord::new(centroid.x(), centroid.y()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping() -> PoiMapping {
+        PoiMapping::from_path(Path::new("./tests/fixtures/geojson_poi/mapping.toml")).unwrap()
+    }
+
+    #[test]
+    fn test_read_feature_collection() {
+        let pois = mapping()
+            .read_pois(
+                Path::new("./tests/fixtures/geojson_poi/pois.geojson"),
+                &Rejects::default(),
+            )
+            .unwrap();
+        // the poi without name is skipped
+        assert_eq!(pois.len(), 2);
+        let poi = &pois[0];
+        assert_eq!(poi.id, "42");
+        assert_eq!(poi.name, "Chez Paul");
+        assert_eq!(poi.poi_type.id, "poi_type:restaurant");
+        assert_eq!(poi.poi_type.name, "Restaurant");
+        assert_float_eq::assert_f64_near!(poi.coord.lon(), 2.3522);
+        assert_float_eq::assert_f64_near!(poi.coord.lat(), 48.8566);
+        assert_eq!(
+            poi.properties
+                .iter()
+                .map(|p| (p.key.as_str(), p.value.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("capacity", "40"), ("website", "https://chezpaul.fr")]
+        );
+        assert_eq!(pois[1].poi_type.name, "Parking");
+    }
+
+    #[test]
+    fn test_read_line_delimited() {
+        let pois = mapping()
+            .read_pois(
+                Path::new("./tests/fixtures/geojson_poi/pois.ndjson"),
+                &Rejects::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            pois.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(),
+            vec!["42", "43"]
+        );
+    }
+
+    #[test]
+    fn test_keep_all_properties() {
+        let mut mapping = mapping();
+        mapping.properties = None;
+        let feature = serde_json::json!({
+            "type": "Feature",
+            "geometry": {"type": "Point", "coordinates": [2.35, 48.85]},
+            "properties": {"ref": 1, "name": "Bob", "category": "bar", "wifi": true}
+        });
+        let poi = mapping.read_feature(&feature).unwrap();
+        assert_eq!(poi.properties.len(), 1);
+        assert_eq!(poi.properties[0].key, "wifi");
+        assert_eq!(poi.properties[0].value, "true");
+    }
+
+    #[test]
+    fn test_centroid_of_polygon() {
+        let feature = serde_json::json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Polygon",
+                "coordinates": [[[2.35, 48.85], [2.37, 48.85], [2.37, 48.87], [2.35, 48.87], [2.35, 48.85]]]
+            },
+            "properties": {"ref": 1, "name": "Bob", "category": "bar"}
+        });
+        let poi = mapping().read_feature(&feature).unwrap();
+        assert!((poi.coord.lon() - 2.36).abs() < 1e-9);
+        assert!((poi.coord.lat() - 48.86).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rejection_reason() {
+        let feature = serde_json::json!({
+            "type": "Feature",
+            "geometry": null,
+            "properties": {"ref": 1, "name": "Bob", "category": "bar"}
+        });
+        let err = mapping().read_feature(&feature).unwrap_err();
+        let rejection = err.downcast_ref::<Rejection>().unwrap();
+        assert_eq!(rejection.reason, RejectReason::InvalidCoord);
+    }
+}
@@ -87,12 +87,13 @@ pub fn format_addr_name_and_label<'a>(
     )
 }
 
-/// create some international label for a poi
-/// One label is created for each lang in the `langs` parameter
-pub fn format_international_poi_label<'a>(
-    poi_names: &mimir::I18nProperties,
-    default_poi_name: &str,
-    default_poi_label: &str,
+// create some international label for a place
+// One label is created for each lang in the `langs` parameter,
+// unless it is the same as the default label
+fn format_international_label<'a>(
+    names: &mimir::I18nProperties,
+    default_name: &str,
+    default_label: &str,
     admins: impl Iterator<Item = &'a mimir::Admin> + Clone,
     country_codes: &[String],
     langs: &[String],
@@ -100,16 +101,15 @@ pub fn format_international_poi_label<'a>(
     let labels = langs
         .iter()
         .filter_map(|ref lang| {
-            let local_poi_name = poi_names.get(lang).unwrap_or(default_poi_name);
-            let i18n_poi_label =
-                format_i18n_label(local_poi_name, admins.clone(), country_codes, lang);
+            let local_name = names.get(lang).unwrap_or(default_name);
+            let i18n_label = format_i18n_label(local_name, admins.clone(), country_codes, lang);
 
-            if i18n_poi_label == default_poi_label {
+            if i18n_label == default_label {
                 None
             } else {
                 Some(mimir::Property {
                     key: (*lang).to_string(),
-                    value: i18n_poi_label,
+                    value: i18n_label,
                 })
             }
         })
@@ -117,6 +117,46 @@ pub fn format_international_poi_label<'a>(
     mimir::I18nProperties(labels)
 }
 
+/// create some international label for a poi
+/// One label is created for each lang in the `langs` parameter
+pub fn format_international_poi_label<'a>(
+    poi_names: &mimir::I18nProperties,
+    default_poi_name: &str,
+    default_poi_label: &str,
+    admins: impl Iterator<Item = &'a mimir::Admin> + Clone,
+    country_codes: &[String],
+    langs: &[String],
+) -> mimir::I18nProperties {
+    format_international_label(
+        poi_names,
+        default_poi_name,
+        default_poi_label,
+        admins,
+        country_codes,
+        langs,
+    )
+}
+
+/// create some international label for a street
+/// One label is created for each lang in the `langs` parameter
+pub fn format_international_street_label<'a>(
+    street_names: &mimir::I18nProperties,
+    default_street_name: &str,
+    default_street_label: &str,
+    admins: impl Iterator<Item = &'a mimir::Admin> + Clone,
+    country_codes: &[String],
+    langs: &[String],
+) -> mimir::I18nProperties {
+    format_international_label(
+        street_names,
+        default_street_name,
+        default_street_label,
+        admins,
+        country_codes,
+        langs,
+    )
+}
+
 fn default_name(house_number: &str, street: &str) -> String {
     //default formating is "{street} {hn}" as it's the most common format (but not correct for france)
     format!("{street} {hn}", street = street, hn = house_number)
@@ -362,4 +402,19 @@ mod test {
             make_i18_prop(&[("ja", "Rembrandthuis (アムステルダム)"),])
         );
     }
+
+    #[test]
+    fn nl_street_in_french() {
+        let street_names = make_i18_prop(&[("fr", "Place du Dam")]);
+        let label = format_international_street_label(
+            &street_names,
+            "Dam",
+            "Dam (Amsterdam)",
+            get_nl_admins().iter(),
+            &["nl".to_owned()],
+            &["fr".to_owned(), "nl".to_owned()],
+        );
+        // there is no dutch label since it is the same as the default one
+        assert_eq!(label, make_i18_prop(&[("fr", "Place du Dam (Amsterdam)")]));
+    }
 }
@@ -28,10 +28,15 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 
+pub mod addr_mapping;
 pub mod addr_reader;
+pub mod address_geofinder;
 pub mod admin_geofinder;
+pub mod geojson_poi;
 pub mod labels;
+pub mod netex;
 pub mod osm_reader;
+pub mod rejects;
 pub mod settings;
 pub mod stops;
 pub mod utils;
@@ -0,0 +1,521 @@
+// Copyright © 2021, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Read the stops of NeTEx files.
+//!
+//! The StopPlaces become stops, weighted by their number of quays like the stop areas of a NTFS.
+//! Their lines are found through the journey patterns of the ServiceFrames and the
+//! passenger stop assignments of their scheduled stop points.
+
+use crate::rejects::{RejectReason, Rejects};
+use crate::stops::{initialize_weights, sort_lines};
+use crate::Error;
+use failure::{format_err, ResultExt};
+use minidom::Element;
+use slog_scope::{info, warn};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use transit_model::objects::Rgb;
+
+struct Quay {
+    id: String,
+    coord: Option<mimir::Coord>,
+}
+
+struct StopPlace {
+    id: String,
+    name: String,
+    alternative_names: Vec<String>,
+    coord: Option<mimir::Coord>,
+    modes: Vec<String>,
+    quays: Vec<Quay>,
+}
+
+struct Line {
+    id: String,
+    name: String,
+    code: Option<String>,
+    mode: Option<String>,
+    color: Option<Rgb>,
+    text_color: Option<Rgb>,
+}
+
+struct JourneyPattern {
+    route: Option<String>,
+    line: Option<String>,
+    scheduled_stop_points: Vec<String>,
+}
+
+/// The objects of the NeTEx files needed to build the stops
+#[derive(Default)]
+struct Netex {
+    stop_places: Vec<StopPlace>,
+    /// quays defined outside of their stop place, with the id of their stop place
+    quays: Vec<(Quay, String)>,
+    lines: HashMap<String, Line>,
+    /// line of each route
+    route_lines: HashMap<String, String>,
+    journey_patterns: Vec<JourneyPattern>,
+    /// quay (or stop place) of each scheduled stop point
+    stop_assignments: HashMap<String, String>,
+    rejects: Rejects,
+}
+
+fn child<'a>(elem: &'a Element, name: &str) -> Option<&'a Element> {
+    elem.children().find(|c| c.name() == name)
+}
+
+fn child_text(elem: &Element, name: &str) -> Option<String> {
+    child(elem, name)
+        .map(|c| c.text().trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+fn child_ref(elem: &Element, name: &str) -> Option<String> {
+    child(elem, name)
+        .and_then(|c| c.attr("ref"))
+        .map(str::to_string)
+}
+
+fn parse_centroid(elem: &Element) -> Option<mimir::Coord> {
+    let location = child(child(elem, "Centroid")?, "Location")?;
+    let lon = child_text(location, "Longitude")?.parse().ok()?;
+    let lat = child_text(location, "Latitude")?.parse().ok()?;
+    Some(mimir::Coord::new(lon, lat)).filter(mimir::Coord::is_valid)
+}
+
+fn parse_quay(elem: &Element) -> Option<Quay> {
+    Some(Quay {
+        id: elem.attr("id")?.to_string(),
+        coord: parse_centroid(elem),
+    })
+}
+
+fn parse_stop_place(elem: &Element) -> Option<StopPlace> {
+    let id = elem.attr("id")?.to_string();
+    let name = child_text(elem, "Name")?;
+    let alternative_names = child(elem, "alternativeNames")
+        .into_iter()
+        .flat_map(Element::children)
+        .filter_map(|alt| child_text(alt, "Name"))
+        .collect();
+    let modes = child_text(elem, "TransportMode")
+        .into_iter()
+        .chain(
+            child_text(elem, "OtherTransportModes")
+                .iter()
+                .flat_map(|modes| modes.split_whitespace().map(str::to_string)),
+        )
+        .collect();
+    let quays = child(elem, "quays")
+        .into_iter()
+        .flat_map(Element::children)
+        .filter_map(parse_quay)
+        .collect();
+    Some(StopPlace {
+        id,
+        name,
+        alternative_names,
+        coord: parse_centroid(elem),
+        modes,
+        quays,
+    })
+}
+
+fn parse_line(elem: &Element) -> Option<Line> {
+    let presentation = child(elem, "Presentation");
+    let color = |name| {
+        presentation
+            .and_then(|p| child_text(p, name))
+            .and_then(|c| c.parse().ok())
+    };
+    Some(Line {
+        id: elem.attr("id")?.to_string(),
+        name: child_text(elem, "Name")?,
+        code: child_text(elem, "PublicCode").or_else(|| child_text(elem, "ShortName")),
+        mode: child_text(elem, "TransportMode"),
+        color: color("Colour"),
+        text_color: color("TextColour"),
+    })
+}
+
+fn parse_journey_pattern(elem: &Element) -> JourneyPattern {
+    JourneyPattern {
+        route: child_ref(elem, "RouteRef"),
+        line: child_ref(elem, "LineRef"),
+        scheduled_stop_points: child(elem, "pointsInSequence")
+            .into_iter()
+            .flat_map(Element::children)
+            .filter_map(|point| child_ref(point, "ScheduledStopPointRef"))
+            .collect(),
+    }
+}
+
+impl Netex {
+    /// Read the objects of an element and of its descendants.
+    /// The frames can be nested in various ways, so all the elements are visited.
+    fn read_element(&mut self, elem: &Element) {
+        match elem.name() {
+            "StopPlace" => match parse_stop_place(elem) {
+                Some(stop_place) => self.stop_places.push(stop_place),
+                None => {
+                    warn!("StopPlace {:?} ignored: no name", elem.attr("id"));
+                    self.rejects.reject(
+                        RejectReason::MissingName,
+                        &serde_json::json!({ "id": elem.attr("id") }),
+                        "StopPlace without name",
+                    );
+                }
+            },
+            "Quay" => {
+                let parent =
+                    child_ref(elem, "ParentZoneRef").or_else(|| child_ref(elem, "ParentSiteRef"));
+                if let (Some(quay), Some(parent)) = (parse_quay(elem), parent) {
+                    self.quays.push((quay, parent));
+                }
+            }
+            "Line" | "FlexibleLine" => {
+                if let Some(line) = parse_line(elem) {
+                    self.lines.insert(line.id.clone(), line);
+                }
+            }
+            "Route" => {
+                if let (Some(id), Some(line)) = (elem.attr("id"), child_ref(elem, "LineRef")) {
+                    self.route_lines.insert(id.to_string(), line);
+                }
+            }
+            "JourneyPattern" | "ServiceJourneyPattern" => {
+                self.journey_patterns.push(parse_journey_pattern(elem))
+            }
+            "PassengerStopAssignment" => {
+                let point = child_ref(elem, "ScheduledStopPointRef");
+                let stop = child_ref(elem, "QuayRef").or_else(|| child_ref(elem, "StopPlaceRef"));
+                if let (Some(point), Some(stop)) = (point, stop) {
+                    self.stop_assignments.insert(point, stop);
+                }
+            }
+            _ => elem.children().for_each(|c| self.read_element(c)),
+        }
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<(), Error> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|e| format!("Could not read {:?}: {}", path, e))?;
+        let root: Element = content
+            .parse()
+            .map_err(|e| format_err!("Invalid NeTEx file {:?}: {}", path, e))?;
+        self.read_element(&root);
+        Ok(())
+    }
+
+    /// Ids of the lines stopping at each stop place
+    fn lines_by_stop_place(&self) -> HashMap<&str, BTreeSet<&str>> {
+        let stop_place_of_quay: HashMap<&str, &str> = self
+            .stop_places
+            .iter()
+            .flat_map(|sp| {
+                sp.quays
+                    .iter()
+                    .map(move |q| (q.id.as_str(), sp.id.as_str()))
+            })
+            .chain(
+                self.quays
+                    .iter()
+                    .map(|(q, sp)| (q.id.as_str(), sp.as_str())),
+            )
+            .collect();
+
+        let mut lines = HashMap::<_, BTreeSet<_>>::new();
+        for pattern in &self.journey_patterns {
+            let line = pattern
+                .line
+                .as_ref()
+                .or_else(|| self.route_lines.get(pattern.route.as_ref()?));
+            let line = match line {
+                Some(line) => line.as_str(),
+                None => continue,
+            };
+            for point in &pattern.scheduled_stop_points {
+                if let Some(stop) = self.stop_assignments.get(point) {
+                    let stop_place = stop_place_of_quay
+                        .get(stop.as_str())
+                        .copied()
+                        .unwrap_or_else(|| stop.as_str());
+                    lines.entry(stop_place).or_default().insert(line);
+                }
+            }
+        }
+        lines
+    }
+
+    fn into_stops(self) -> Vec<mimir::Stop> {
+        let lines_by_stop_place = self.lines_by_stop_place();
+        let mut quays_by_stop_place = HashMap::<_, Vec<&Quay>>::new();
+        for (quay, stop_place) in &self.quays {
+            quays_by_stop_place
+                .entry(stop_place.as_str())
+                .or_default()
+                .push(quay);
+        }
+
+        let mut nb_stop_points = HashMap::new();
+        let mut stops: Vec<mimir::Stop> = self
+            .stop_places
+            .iter()
+            .filter_map(|sp| {
+                let quays: Vec<&Quay> = sp
+                    .quays
+                    .iter()
+                    .chain(
+                        quays_by_stop_place
+                            .remove(sp.id.as_str())
+                            .unwrap_or_default(),
+                    )
+                    .collect();
+                let lines: Vec<&Line> = lines_by_stop_place
+                    .get(sp.id.as_str())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|id| self.lines.get(*id))
+                    .collect();
+                let stop = to_mimir(sp, &quays, &lines, &self.rejects)?;
+                nb_stop_points.insert(stop.id.clone(), quays.len() as u32);
+                Some(stop)
+            })
+            .collect();
+        initialize_weights(stops.iter_mut(), &nb_stop_points);
+        stops
+    }
+}
+
+/// Physical (and commercial) mode of a NeTEx transport mode, named as in a NTFS
+fn physical_mode(mode: &str) -> Option<&'static str> {
+    Some(match mode {
+        "air" => "Air",
+        "bus" | "trolleyBus" => "Bus",
+        "coach" => "Coach",
+        "ferry" | "water" => "Ferry",
+        "funicular" => "Funicular",
+        "cableway" | "lift" => "SuspendedCableCar",
+        "metro" => "Metro",
+        "rail" => "Train",
+        "tram" => "Tramway",
+        "taxi" => "Taxi",
+        _ => return None,
+    })
+}
+
+fn to_physical_mode(mode: &str) -> Option<mimir::PhysicalMode> {
+    physical_mode(mode).map(|name| mimir::PhysicalMode {
+        id: mimir::objects::normalize_id("physical_mode", name),
+        name: name.to_string(),
+    })
+}
+
+fn to_commercial_mode(mode: &str) -> Option<mimir::CommercialMode> {
+    physical_mode(mode).map(|name| mimir::CommercialMode {
+        id: mimir::objects::normalize_id("commercial_mode", name),
+        name: name.to_string(),
+    })
+}
+
+fn to_mimir_line(line: &Line) -> mimir::Line {
+    let mode = line.mode.as_deref();
+    mimir::Line {
+        id: mimir::objects::normalize_id("line", &line.id),
+        name: line.name.clone(),
+        code: line.code.clone(),
+        color: line.color.clone(),
+        text_color: line.text_color.clone(),
+        commercial_mode: mode.and_then(to_commercial_mode),
+        network: None,
+        physical_modes: mode.and_then(to_physical_mode).into_iter().collect(),
+        sort_order: None,
+    }
+}
+
+fn to_mimir(
+    stop_place: &StopPlace,
+    quays: &[&Quay],
+    lines: &[&Line],
+    rejects: &Rejects,
+) -> Option<mimir::Stop> {
+    let coord = stop_place.coord.or_else(|| {
+        let coords: Vec<_> = quays.iter().filter_map(|q| q.coord).collect();
+        if coords.is_empty() {
+            return None;
+        }
+        let nb = coords.len() as f64;
+        Some(mimir::Coord::new(
+            coords.iter().map(mimir::Coord::lon).sum::<f64>() / nb,
+            coords.iter().map(mimir::Coord::lat).sum::<f64>() / nb,
+        ))
+    });
+    let coord = match coord {
+        Some(coord) => coord,
+        None => {
+            warn!("StopPlace {} ignored: no coordinates", stop_place.id);
+            rejects.reject(
+                RejectReason::InvalidCoord,
+                &serde_json::json!({ "id": stop_place.id, "name": stop_place.name }),
+                "StopPlace without coordinates, nor quays with coordinates",
+            );
+            return None;
+        }
+    };
+
+    let modes: BTreeSet<&str> = stop_place
+        .modes
+        .iter()
+        .map(String::as_str)
+        .chain(lines.iter().filter_map(|l| l.mode.as_deref()))
+        .collect();
+
+    let mut mimir_lines: Vec<_> = lines.iter().map(|l| to_mimir_line(l)).collect();
+    sort_lines(&mut mimir_lines);
+
+    let mut aliases: Vec<String> = Vec::new();
+    for alias in &stop_place.alternative_names {
+        if *alias != stop_place.name && !aliases.contains(alias) {
+            aliases.push(alias.clone());
+        }
+    }
+
+    Some(mimir::Stop {
+        id: mimir::objects::normalize_id("stop_area", &stop_place.id),
+        label: stop_place.name.clone(),
+        name: stop_place.name.clone(),
+        coord,
+        approx_coord: Some(coord.into()),
+        commercial_modes: modes
+            .iter()
+            .filter_map(|m| to_commercial_mode(m))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        physical_modes: modes
+            .iter()
+            .filter_map(|m| to_physical_mode(m))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        lines: mimir_lines,
+        aliases,
+        ..Default::default()
+    })
+}
+
+/// The NeTEx files of a path: the path itself if it is a file,
+/// or all the xml files of the directory.
+fn netex_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .map(walkdir::DirEntry::into_path)
+        .filter(|p| p.extension().map_or(false, |ext| ext == "xml"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Read the stops of a NeTEx file, or of a directory of NeTEx files.
+/// The objects can be spread over several files, the stop places in one
+/// and the lines in others for instance.
+/// The stop places without name or coordinates are written in the rejects.
+pub fn read_stops(path: &Path, rejects: &Rejects) -> Result<Vec<mimir::Stop>, Error> {
+    let mut netex = Netex {
+        rejects: rejects.clone(),
+        ..Default::default()
+    };
+    for file in netex_files(path) {
+        info!("reading {:?}", file);
+        netex.read_file(&file)?;
+    }
+    info!(
+        "{} stop places and {} lines read",
+        netex.stop_places.len(),
+        netex.lines.len()
+    );
+    Ok(netex.into_stops())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_netex_stops() {
+        let mut stops =
+            read_stops(Path::new("./tests/fixtures/netex"), &Rejects::default()).unwrap();
+        stops.sort_by(|a, b| a.id.cmp(&b.id));
+        let ids: Vec<_> = stops.iter().map(|s| s.id.as_str()).collect();
+        // the stop place without coordinates is ignored
+        assert_eq!(
+            ids,
+            vec![
+                "stop_area:TEST:StopPlace:bastille",
+                "stop_area:TEST:StopPlace:gare_de_lyon"
+            ]
+        );
+
+        let bastille = &stops[0];
+        assert_float_eq::assert_f64_near!(bastille.coord.lon(), 2.369);
+        assert_float_eq::assert_f64_near!(bastille.coord.lat(), 48.853);
+        assert_float_eq::assert_f64_near!(bastille.weight, 0.5);
+        assert_eq!(bastille.lines.len(), 1);
+
+        let gare_de_lyon = &stops[1];
+        assert_eq!(gare_de_lyon.name, "Gare de Lyon");
+        assert_eq!(gare_de_lyon.aliases, vec!["Paris Gare de Lyon".to_string()]);
+        assert_float_eq::assert_f64_near!(gare_de_lyon.weight, 1.);
+        assert_eq!(
+            gare_de_lyon
+                .physical_modes
+                .iter()
+                .map(|m| m.id.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "physical_mode:Bus",
+                "physical_mode:Metro",
+                "physical_mode:Train"
+            ]
+        );
+        let line = &gare_de_lyon.lines[0];
+        assert_eq!(line.id, "line:TEST:Line:29");
+        assert_eq!(line.code, Some("29".to_string()));
+        assert_eq!(
+            line.commercial_mode.as_ref().map(|m| m.name.as_str()),
+            Some("Bus")
+        );
+    }
+}
@@ -0,0 +1,484 @@
+// Copyright © 2020, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use super::osm_store::{Getter, ObjWrapper};
+use super::osm_utils::{get_way_coord, osm_record};
+use super::street::Kind;
+use super::OsmPbfReader;
+use crate::admin_geofinder::AdminGeoFinder;
+use crate::rejects::{RejectReason, Rejects};
+use crate::{labels, settings, utils, Error};
+use failure::ResultExt;
+use osmpbfreader::{OsmId, OsmObj, StoreObjs, Tags};
+use slog_scope::{info, warn};
+use std::collections::HashSet;
+use std::ops::Deref;
+use std::sync::Arc;
+
+// Interpolations spanning more house numbers than that are most likely broken
+const MAX_INTERPOLATED_HOUSE_NUMBERS: u32 = 1000;
+
+fn get_tag<'a>(tags: &'a Tags, key: &str) -> Option<&'a str> {
+    tags.get(key).map(|v| v.trim()).filter(|v| !v.is_empty())
+}
+
+fn has_address_tags(tags: &Tags) -> bool {
+    get_tag(tags, "addr:housenumber").is_some() && get_tag(tags, "addr:street").is_some()
+}
+
+fn is_interpolation(tags: &Tags) -> bool {
+    get_tag(tags, "addr:interpolation").is_some()
+}
+
+fn is_associated_street(tags: &Tags) -> bool {
+    tags.get("type").map_or(false, |v| v == "associatedStreet")
+}
+
+/// Step between the house numbers of an `addr:interpolation` way.
+/// The alphabetic interpolations are not handled.
+fn interpolation_step(interpolation: &str) -> Option<u32> {
+    match interpolation {
+        "odd" | "even" => Some(2),
+        "all" => Some(1),
+        step => step.parse().ok().filter(|step| *step > 0),
+    }
+}
+
+/// House numbers strictly between `start` and `end`, with the given step.
+fn expand_interpolation(start: u32, end: u32, step: u32) -> Vec<u32> {
+    if step == 0 || start == end {
+        return vec![];
+    }
+    let (low, high) = (start.min(end), start.max(end));
+    if (high - low) / step > MAX_INTERPOLATED_HOUSE_NUMBERS {
+        return vec![];
+    }
+    let numbers = (1..)
+        .map(|i| i * step)
+        .take_while(|offset| *offset < high - low)
+        .map(|offset| {
+            if start < end {
+                start + offset
+            } else {
+                start - offset
+            }
+        });
+    numbers.collect()
+}
+
+/// Coordinate at `fraction` (between 0 and 1) of the length of the line.
+fn interpolate_coord(line: &[mimir::Coord], fraction: f64) -> Option<mimir::Coord> {
+    let segment_length = |a: &mimir::Coord, b: &mimir::Coord| (b.x - a.x).hypot(b.y - a.y);
+    let total_length: f64 = line.windows(2).map(|s| segment_length(&s[0], &s[1])).sum();
+    if total_length == 0. {
+        return line.first().cloned();
+    }
+    let mut remaining = fraction.max(0.).min(1.) * total_length;
+    for segment in line.windows(2) {
+        let (a, b) = (&segment[0], &segment[1]);
+        let length = segment_length(a, b);
+        if remaining <= length && length > 0. {
+            let ratio = remaining / length;
+            return Some(mimir::Coord::new(
+                a.x + (b.x - a.x) * ratio,
+                a.y + (b.y - a.y) * ratio,
+            ));
+        }
+        remaining -= length;
+    }
+    line.last().cloned()
+}
+
+fn build_addr(
+    id: String,
+    street_id: String,
+    house_number: &str,
+    street_name: &str,
+    postcode: Option<&str>,
+    coord: mimir::Coord,
+    admins: Vec<Arc<mimir::Admin>>,
+) -> mimir::Addr {
+    let country_codes = utils::find_country_codes(admins.iter().map(|a| a.deref()));
+    let weight = admins.iter().find(|a| a.is_city()).map_or(0., |a| a.weight);
+    let zip_codes = match postcode {
+        Some(postcode) => vec![postcode.to_string()],
+        None => utils::get_zip_codes_from_admins(&admins),
+    };
+    let street_label = labels::format_street_label(
+        street_name,
+        admins.iter().map(|a| a.deref()),
+        &country_codes,
+    );
+    let (addr_name, addr_label) = labels::format_addr_name_and_label(
+        house_number,
+        street_name,
+        admins.iter().map(|a| a.deref()),
+        &country_codes,
+    );
+    let street = mimir::Street {
+        id: street_id,
+        name: street_name.to_string(),
+        label: street_label,
+        names: mimir::I18nProperties::default(),
+        labels: mimir::I18nProperties::default(),
+        aliases: vec![],
+        administrative_regions: admins,
+        weight,
+        zip_codes: zip_codes.clone(),
+        coord,
+        approx_coord: None,
+        geometry: None,
+        distance: None,
+        country_codes: country_codes.clone(),
+        context: None,
+    };
+    mimir::Addr {
+        id,
+        name: addr_name,
+        label: addr_label,
+        house_number: house_number.to_string(),
+        street,
+        coord,
+        approx_coord: Some(coord.into()),
+        weight,
+        zip_codes,
+        distance: None,
+        country_codes,
+        context: None,
+    }
+}
+
+fn get_obj_coord<T: StoreObjs + Getter>(obj_map: &T, obj: &OsmObj) -> Option<mimir::Coord> {
+    let coord = match obj {
+        OsmObj::Node(node) => mimir::Coord::new(node.lon(), node.lat()),
+        OsmObj::Way(way) => get_way_coord(obj_map, way),
+        OsmObj::Relation(_) => return None,
+    };
+    Some(coord).filter(|c| !c.is_default())
+}
+
+fn format_addr_id(id: OsmId) -> String {
+    let kind = match id {
+        OsmId::Node(_) => "node",
+        OsmId::Way(_) => "way",
+        OsmId::Relation(_) => "relation",
+    };
+    format!("addr:osm:{}:{}", kind, id.inner_id())
+}
+
+/// Build the address of an object (like a poi) from its `addr:*` tags,
+/// located at `coord` and in the `admins`
+pub fn addr_from_tags(
+    obj: &OsmObj,
+    coord: mimir::Coord,
+    admins: Vec<Arc<mimir::Admin>>,
+) -> Option<mimir::Addr> {
+    let tags = obj.tags();
+    let house_number = get_tag(tags, "addr:housenumber")?;
+    let street_name = get_tag(tags, "addr:street")?;
+    let id = format_addr_id(obj.id());
+    let street_id = format!("street:{}", id);
+    Some(build_addr(
+        id,
+        street_id,
+        house_number,
+        street_name,
+        get_tag(tags, "addr:postcode"),
+        coord,
+        admins,
+    ))
+}
+
+/// Build the address of a node or a building, `street` gives the street name and id
+/// to use if the object has no `addr:street` tag
+fn parse_addr<T: StoreObjs + Getter>(
+    obj_map: &T,
+    obj: &OsmObj,
+    street: Option<(&str, &str)>,
+    admins_geofinder: &AdminGeoFinder,
+    rejects: &Rejects,
+) -> Option<mimir::Addr> {
+    let tags = obj.tags();
+    let house_number = get_tag(tags, "addr:housenumber")?;
+    let id = format_addr_id(obj.id());
+    let (street_name, street_id) = match (get_tag(tags, "addr:street"), street) {
+        (Some(name), Some((rel_name, rel_street_id))) if name == rel_name => {
+            (name, rel_street_id.to_string())
+        }
+        (Some(name), _) => (name, format!("street:{}", id)),
+        (None, Some((rel_name, rel_street_id))) => (rel_name, rel_street_id.to_string()),
+        (None, None) => {
+            rejects.reject(
+                RejectReason::MissingStreet,
+                &osm_record(obj.id(), tags),
+                format!("the address {} has no street", id),
+            );
+            return None;
+        }
+    };
+    let coord = match get_obj_coord(obj_map, obj) {
+        Some(coord) => coord,
+        None => {
+            info!(
+                "The address {} is rejected, cause: could not compute coordinates.",
+                id
+            );
+            rejects.reject(
+                RejectReason::InvalidCoord,
+                &osm_record(obj.id(), tags),
+                format!("could not compute the coordinates of the address {}", id),
+            );
+            return None;
+        }
+    };
+    Some(build_addr(
+        id,
+        street_id,
+        house_number,
+        street_name,
+        get_tag(tags, "addr:postcode"),
+        coord,
+        admins_geofinder.get(&coord),
+    ))
+}
+
+/// Expand an `addr:interpolation` way into its intermediate house numbers.
+/// The ends of the interpolation are regular addresses, they are not part of the output.
+fn parse_interpolation<T: StoreObjs + Getter>(
+    obj_map: &T,
+    way: &osmpbfreader::objects::Way,
+    admins_geofinder: &AdminGeoFinder,
+    rejects: &Rejects,
+) -> Vec<mimir::Addr> {
+    let step = match get_tag(&way.tags, "addr:interpolation").and_then(interpolation_step) {
+        Some(step) => step,
+        None => return vec![],
+    };
+    let nodes: Vec<_> = way
+        .nodes
+        .iter()
+        .filter_map(|id| obj_map.get(&(*id).into()))
+        .filter_map(|obj| obj.node().cloned())
+        .collect();
+    let line: Vec<_> = nodes
+        .iter()
+        .map(|node| mimir::Coord::new(node.lon(), node.lat()))
+        .collect();
+    let street_name = get_tag(&way.tags, "addr:street")
+        .or_else(|| nodes.iter().find_map(|n| get_tag(&n.tags, "addr:street")));
+    let street_name = match street_name {
+        Some(name) => name,
+        None => {
+            warn!("The interpolation {:?} has no street", way.id);
+            rejects.reject(
+                RejectReason::MissingStreet,
+                &osm_record(way.id.into(), &way.tags),
+                "the interpolation has no street",
+            );
+            return vec![];
+        }
+    };
+    let postcode = get_tag(&way.tags, "addr:postcode")
+        .or_else(|| nodes.iter().find_map(|n| get_tag(&n.tags, "addr:postcode")));
+
+    // the nodes of the way with a numeric house number delimit the interpolated segments
+    let numbered_nodes: Vec<(usize, u32)> = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, node)| {
+            get_tag(&node.tags, "addr:housenumber")
+                .and_then(|hn| hn.parse().ok())
+                .map(|hn| (i, hn))
+        })
+        .collect();
+
+    numbered_nodes
+        .windows(2)
+        .flat_map(|bounds| {
+            let ((start_idx, start), (end_idx, end)) = (bounds[0], bounds[1]);
+            let segment = &line[start_idx..=end_idx];
+            expand_interpolation(start, end, step)
+                .into_iter()
+                .filter_map(move |number| {
+                    let fraction = f64::from(number.max(start) - number.min(start))
+                        / f64::from(end.max(start) - end.min(start));
+                    interpolate_coord(segment, fraction).map(|coord| (number, coord))
+                })
+        })
+        .map(|(number, coord)| {
+            let id = format!("addr:osm:interpolation:{}:{}", way.id.0, number);
+            build_addr(
+                id,
+                format!("street:osm:interpolation:{}", way.id.0),
+                &number.to_string(),
+                street_name,
+                postcode,
+                coord,
+                admins_geofinder.get(&coord),
+            )
+        })
+        .collect()
+}
+
+pub fn addresses(
+    pbf: &mut OsmPbfReader,
+    admins_geofinder: &AdminGeoFinder,
+    settings: &settings::osm2mimir::Settings,
+    rejects: &Rejects,
+) -> Result<Vec<mimir::Addr>, Error> {
+    let with_interpolation = settings
+        .address
+        .as_ref()
+        .map_or(false, |address| address.interpolation);
+
+    let is_valid_obj = |obj: &OsmObj| -> bool {
+        match *obj {
+            OsmObj::Node(ref node) => has_address_tags(&node.tags),
+            OsmObj::Way(ref way) => {
+                has_address_tags(&way.tags) || (with_interpolation && is_interpolation(&way.tags))
+            }
+            OsmObj::Relation(ref rel) => is_associated_street(&rel.tags),
+        }
+    };
+
+    info!("reading pbf...");
+    #[cfg(feature = "db-storage")]
+    let mut objs_map = ObjWrapper::new(&settings.database)?;
+    #[cfg(not(feature = "db-storage"))]
+    let mut objs_map = ObjWrapper::new()?;
+
+    pbf.get_objs_and_deps_store(is_valid_obj, &mut objs_map)
+        .context("Error occurred when reading pbf")?;
+    info!("reading pbf done.");
+
+    let mut addr_list = Vec::new();
+
+    // The houses of an associatedStreet relation may not have any addr:street tag,
+    // the name of the street is then given by the relation.
+    // We keep track of these houses so that they are not imported twice.
+    let mut houses_in_relation = HashSet::new();
+    // the houses that can't be parsed with their relation are parsed again below,
+    // they are only rejected there
+    let no_rejects = Rejects::default();
+
+    objs_map.for_each_filter(Kind::Relation, |obj| {
+        let rel = obj.relation().expect("invalid relation filter");
+        let street_id = format!("street:osm:relation:{}", rel.id.0);
+        let street_name = get_tag(&rel.tags, "name").map(str::to_string).or_else(|| {
+            rel.refs
+                .iter()
+                .filter(|r| r.member.is_way() && r.role == "street")
+                .filter_map(|r| objs_map.get(&r.member))
+                .find_map(|obj| get_tag(obj.tags(), "name").map(str::to_string))
+        });
+
+        for house in rel.refs.iter().filter(|r| r.role == "house") {
+            if houses_in_relation.contains(&house.member) {
+                continue;
+            }
+            let house_obj = match objs_map.get(&house.member) {
+                Some(house_obj) => house_obj,
+                None => continue,
+            };
+            let street = street_name
+                .as_deref()
+                .map(|name| (name, street_id.as_str()));
+            if let Some(addr) =
+                parse_addr(&objs_map, &house_obj, street, admins_geofinder, &no_rejects)
+            {
+                houses_in_relation.insert(house.member);
+                addr_list.push(addr);
+            }
+        }
+    });
+
+    for kind in &[Kind::Node, Kind::Way] {
+        objs_map.for_each_filter(*kind, |obj| {
+            if houses_in_relation.contains(&obj.id()) {
+                return;
+            }
+            if has_address_tags(obj.tags()) {
+                addr_list.extend(parse_addr(&objs_map, &obj, None, admins_geofinder, rejects));
+            } else if let Some(way) = obj.way().filter(|way| is_interpolation(&way.tags)) {
+                if with_interpolation {
+                    addr_list.extend(parse_interpolation(
+                        &objs_map,
+                        way,
+                        admins_geofinder,
+                        rejects,
+                    ));
+                }
+            }
+        });
+    }
+
+    Ok(addr_list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolation_steps() {
+        assert_eq!(interpolation_step("odd"), Some(2));
+        assert_eq!(interpolation_step("even"), Some(2));
+        assert_eq!(interpolation_step("all"), Some(1));
+        assert_eq!(interpolation_step("5"), Some(5));
+        assert_eq!(interpolation_step("0"), None);
+        assert_eq!(interpolation_step("alphabetic"), None);
+    }
+
+    #[test]
+    fn expand_interpolations() {
+        assert_eq!(expand_interpolation(1, 9, 2), vec![3, 5, 7]);
+        assert_eq!(expand_interpolation(10, 4, 2), vec![8, 6]);
+        assert_eq!(expand_interpolation(1, 4, 1), vec![2, 3]);
+        assert_eq!(expand_interpolation(2, 4, 2), Vec::<u32>::new());
+        assert_eq!(expand_interpolation(4, 4, 2), Vec::<u32>::new());
+        assert_eq!(expand_interpolation(1, 100_000, 1), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn interpolate_coords() {
+        let line = vec![
+            mimir::Coord::new(0., 0.),
+            mimir::Coord::new(2., 0.),
+            mimir::Coord::new(2., 2.),
+        ];
+        let coord = interpolate_coord(&line, 0.25).unwrap();
+        assert_eq!((coord.x, coord.y), (1., 0.));
+        let coord = interpolate_coord(&line, 0.75).unwrap();
+        assert_eq!((coord.x, coord.y), (2., 1.));
+        let coord = interpolate_coord(&line, 1.).unwrap();
+        assert_eq!((coord.x, coord.y), (2., 2.));
+        assert!(interpolate_coord(&[], 0.5).is_none());
+    }
+}
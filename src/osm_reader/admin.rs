@@ -28,7 +28,7 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 use super::OsmPbfReader;
-use crate::osm_reader::osm_utils::{get_osm_codes_from_tags, make_centroid};
+use crate::osm_reader::osm_utils::{get_aliases_from_tags, get_osm_codes_from_tags, make_centroid};
 use crate::utils;
 use cosmogony::ZoneType;
 use geo::bounding_rect::BoundingRect;
@@ -175,6 +175,7 @@ pub fn read_administrative_regions(
                 codes,
                 names: mimir::I18nProperties::default(),
                 labels: mimir::I18nProperties::default(),
+                aliases: get_aliases_from_tags(&relation.tags, name),
                 distance: None,
                 context: None,
                 administrative_regions: Vec::new(),
@@ -2,6 +2,7 @@ use crate::Error;
 use std::fs::File;
 use std::path::Path;
 
+pub mod address;
 pub mod admin;
 pub mod osm_store;
 pub mod osm_utils;
@@ -30,7 +30,7 @@
 
 use super::osm_store::Getter;
 use geo::centroid::Centroid;
-use geo::MultiPolygon;
+use geo::{Coordinate, LineString, MultiLineString, MultiPolygon};
 use osmpbfreader::StoreObjs;
 
 pub fn get_way_coord<T: StoreObjs + Getter>(
@@ -55,6 +55,74 @@ pub fn get_way_coord<T: StoreObjs + Getter>(
         .unwrap_or_else(mimir::Coord::default)
 }
 
+/// Returns the line of the way, or None if less than 2 of its nodes are known.
+pub fn get_way_line_string<T: StoreObjs + Getter>(
+    obj_map: &T,
+    way: &osmpbfreader::objects::Way,
+) -> Option<LineString<f64>> {
+    let coords: Vec<_> = way
+        .nodes
+        .iter()
+        .filter_map(|node_id| obj_map.get(&(*node_id).into()))
+        .filter_map(|obj| {
+            obj.node().map(|node| Coordinate {
+                x: node.lon(),
+                y: node.lat(),
+            })
+        })
+        .collect();
+    if coords.len() < 2 {
+        None
+    } else {
+        Some(LineString(coords))
+    }
+}
+
+/// Merge the lines sharing an end, so that a street divided in several ways
+/// is made of as few lines as possible.
+/// Returns None if there is no line.
+pub fn merge_lines(lines: Vec<LineString<f64>>) -> Option<MultiLineString<f64>> {
+    let mut pending = lines;
+    let mut merged = Vec::new();
+
+    while let Some(mut line) = pending.pop() {
+        loop {
+            let first = line.0[0];
+            let last = line.0[line.0.len() - 1];
+            let connected = pending.iter().position(|other| {
+                let (other_first, other_last) = (other.0[0], other.0[other.0.len() - 1]);
+                other_first == last
+                    || other_last == last
+                    || other_first == first
+                    || other_last == first
+            });
+            let mut other = match connected {
+                Some(pos) => pending.swap_remove(pos).0,
+                None => break,
+            };
+            if other[0] == last {
+                line.0.extend(other.drain(1..));
+            } else if other[other.len() - 1] == last {
+                other.reverse();
+                line.0.extend(other.drain(1..));
+            } else {
+                if other[0] == first {
+                    other.reverse();
+                }
+                other.extend(line.0.drain(1..));
+                line = LineString(other);
+            }
+        }
+        merged.push(line);
+    }
+
+    if merged.is_empty() {
+        None
+    } else {
+        Some(MultiLineString(merged))
+    }
+}
+
 pub fn make_centroid(boundary: &Option<MultiPolygon<f64>>) -> mimir::Coord {
     let coord = boundary
         .as_ref()
@@ -81,6 +149,10 @@ pub fn get_osm_codes_from_tags(tags: &osmpbfreader::Tags) -> Vec<mimir::Code> {
         .collect()
 }
 
+pub fn get_aliases_from_tags(tags: &osmpbfreader::Tags, name: &str) -> Vec<String> {
+    crate::utils::get_aliases(name, tags.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+}
+
 pub fn get_names_from_tags(tags: &osmpbfreader::Tags, langs: &[String]) -> mimir::I18nProperties {
     const NAME_TAG_PREFIX: &str = "name:";
 
@@ -95,3 +167,47 @@ pub fn get_names_from_tags(tags: &osmpbfreader::Tags, langs: &[String]) -> mimir
         .collect();
     mimir::I18nProperties(properties)
 }
+
+/// The id and the tags of an osm object, as written in the rejects
+pub fn osm_record(id: osmpbfreader::OsmId, tags: &osmpbfreader::Tags) -> serde_json::Value {
+    let (osm_type, osm_id) = match id {
+        osmpbfreader::OsmId::Node(id) => ("node", id.0),
+        osmpbfreader::OsmId::Way(id) => ("way", id.0),
+        osmpbfreader::OsmId::Relation(id) => ("relation", id.0),
+    };
+    let tags: serde_json::Map<_, _> = tags
+        .iter()
+        .map(|(k, v)| (k.to_string(), serde_json::Value::String(v.to_string())))
+        .collect();
+    serde_json::json!({"osm_type": osm_type, "osm_id": osm_id, "tags": tags})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(coords: &[(f64, f64)]) -> LineString<f64> {
+        coords.to_vec().into()
+    }
+
+    #[test]
+    fn merge_connected_lines() {
+        let merged = merge_lines(vec![
+            line(&[(0., 0.), (1., 0.)]),
+            line(&[(2., 0.), (1., 0.)]),
+            line(&[(5., 5.), (6., 6.)]),
+            line(&[(0., 1.), (0., 0.)]),
+        ])
+        .unwrap();
+        assert_eq!(merged.0.len(), 2);
+        assert!(merged.0.contains(&line(&[(5., 5.), (6., 6.)])));
+        assert!(merged
+            .0
+            .contains(&line(&[(0., 1.), (0., 0.), (1., 0.), (2., 0.)])));
+    }
+
+    #[test]
+    fn merge_no_lines() {
+        assert_eq!(merge_lines(vec![]), None);
+    }
+}
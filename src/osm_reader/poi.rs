@@ -28,24 +28,105 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 
+use super::address::addr_from_tags;
+use super::osm_utils::get_aliases_from_tags;
+use super::osm_utils::get_names_from_tags;
 use super::osm_utils::get_way_coord;
 use super::osm_utils::make_centroid;
+use super::osm_utils::osm_record;
 use super::OsmPbfReader;
+use crate::address_geofinder::AddressGeoFinder;
 use crate::admin_geofinder::AdminGeoFinder;
+use crate::rejects::{RejectReason, Rejects};
 use crate::{labels, settings::osm2mimir::Settings, utils};
 use mimir::{rubber, Poi, PoiType};
 use osm_boundaries_utils::build_boundary;
-use serde::{Deserialize, Serialize};
+use par_map::ParMap;
+use regex::Regex;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use slog_scope::{info, warn};
 use std::collections::BTreeMap;
 use std::error::Error;
 use std::io;
 use std::ops::Deref;
 
+/// A regular expression on the value of an osm tag
+#[derive(Debug, Clone)]
+pub struct TagRegex(pub Regex);
+
+impl Serialize for TagRegex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TagRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(TagRegex)
+            .map_err(|e| D::Error::custom(format!("invalid regex {:?}: {}", pattern, e)))
+    }
+}
+
+/// Filter on an osm tag.
+///
+/// * `{"key": "amenity", "value": "cafe"}` matches the objects with the tag `amenity=cafe`
+/// * `{"key": "shop", "value": "*"}` matches the objects with a `shop` tag, whatever its value
+/// * `{"key": "amenity", "value": "cafe|bar"}` matches `amenity=cafe` or `amenity=bar`
+/// * `{"key": "cuisine", "regex": "^(pizza|burger)"}` matches the values with the regex
+/// * `"negate": true` inverts the filter, `{"key": "access", "value": "private", "negate": true}`
+///   matches the objects without `access=private` (including the ones without `access` tag).
+///   A rule needs at least one filter that is not negated.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OsmTagsFilter {
     pub key: String,
-    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<TagRegex>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub negate: bool,
+}
+
+impl OsmTagsFilter {
+    pub fn is_match(&self, tags: &osmpbfreader::Tags) -> bool {
+        let is_match = match (tags.get(self.key.as_str()), &self.value, &self.regex) {
+            (None, _, _) => false,
+            (Some(v), _, Some(regex)) => regex.0.is_match(v),
+            (Some(v), Some(value), None) => value == "*" || value.split('|').any(|alt| alt == v),
+            (Some(_), None, None) => false,
+        };
+        is_match != self.negate
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.key.is_empty() {
+            return Err("empty key in osm tags filter".to_string());
+        }
+        match (&self.value, &self.regex) {
+            (Some(_), Some(_)) => Err(format!(
+                "osm tags filter on {:?} has both a value and a regex",
+                self.key
+            )),
+            (None, None) => Err(format!(
+                "osm tags filter on {:?} has neither a value nor a regex",
+                self.key
+            )),
+            (Some(value), None) if value != "*" => {
+                if value.split('|').any(|alt| alt.is_empty() || alt == "*") {
+                    Err(format!(
+                        "invalid value {:?} in osm tags filter on {:?}",
+                        value, self.key
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -53,6 +134,30 @@ pub struct Rule {
     pub osm_tags_filters: Vec<OsmTagsFilter>,
     #[serde(rename = "type")]
     pub poi_type_id: String,
+    /// When several rules match an object, the one with the highest priority is used,
+    /// and the first one amongst those with the same priority.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl Rule {
+    pub fn is_match(&self, tags: &osmpbfreader::Tags) -> bool {
+        self.osm_tags_filters.iter().all(|f| f.is_match(tags))
+    }
+
+    fn check(&self) -> Result<(), String> {
+        for filter in &self.osm_tags_filters {
+            filter.check()?;
+        }
+        // a rule with only negated filters would match almost all the osm objects
+        if self.osm_tags_filters.iter().all(|filter| filter.negate) {
+            return Err(format!(
+                "rule of {:?} has no osm tags filter that is not negated",
+                self.poi_type_id
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -89,10 +194,10 @@ impl PoiConfig {
     pub fn get_poi_type(&self, tags: &osmpbfreader::Tags) -> Option<&PoiType> {
         self.rules
             .iter()
-            .find(|rule| {
-                rule.osm_tags_filters
-                    .iter()
-                    .all(|f| tags.get(f.key.as_str()).map_or(false, |v| v == &f.value))
+            .filter(|rule| rule.is_match(tags))
+            .fold(None, |best: Option<&Rule>, rule| match best {
+                Some(best) if best.priority >= rule.priority => Some(best),
+                _ => Some(rule),
             })
             .and_then(|rule| {
                 self.poi_types
@@ -114,6 +219,7 @@ impl PoiConfig {
                     format!("poi_type_id {:?} in a rule not declared", rule.poi_type_id).into(),
                 );
             }
+            rule.check()?;
         }
         Ok(())
     }
@@ -133,6 +239,8 @@ fn parse_poi(
     obj_map: &BTreeMap<osmpbfreader::OsmId, osmpbfreader::OsmObj>,
     matcher: &PoiConfig,
     admins_geofinder: &AdminGeoFinder,
+    langs: &[String],
+    rejects: &Rejects,
 ) -> Option<mimir::Poi> {
     let poi_type = match matcher.get_poi_type(osmobj.tags()) {
         Some(poi_type) => poi_type,
@@ -141,6 +249,11 @@ fn parse_poi(
                 "The poi {:?} has no tags even if it passes the filters",
                 osmobj.id()
             );
+            rejects.reject(
+                RejectReason::UnknownPoiType,
+                &osm_record(osmobj.id(), osmobj.tags()),
+                "no poi type matches the tags of the poi",
+            );
             return None;
         }
     };
@@ -168,6 +281,11 @@ fn parse_poi(
             "The poi {} is rejected, cause: could not compute coordinates.",
             id
         );
+        rejects.reject(
+            RejectReason::InvalidCoord,
+            &osm_record(osmobj.id(), osmobj.tags()),
+            format!("could not compute the coordinates of the poi {}", id),
+        );
         return None;
     }
 
@@ -177,10 +295,21 @@ fn parse_poi(
         _ => utils::get_zip_codes_from_admins(&adms),
     };
     let country_codes = utils::find_country_codes(adms.iter().map(|a| a.deref()));
+    let address = addr_from_tags(osmobj, coord, adms.clone()).map(mimir::Address::Addr);
+    let label = labels::format_poi_label(name, adms.iter().map(|a| a.deref()), &country_codes);
+    let names = get_names_from_tags(osmobj.tags(), langs);
+    let labels = labels::format_international_poi_label(
+        &names,
+        name,
+        &label,
+        adms.iter().map(|a| a.deref()),
+        &country_codes,
+        langs,
+    );
     Some(mimir::Poi {
         id,
         name: name.to_string(),
-        label: labels::format_poi_label(name, adms.iter().map(|a| a.deref()), &country_codes),
+        label,
         coord,
         approx_coord: Some(coord.into()),
         zip_codes,
@@ -188,9 +317,10 @@ fn parse_poi(
         weight: 0.,
         poi_type: poi_type.clone(),
         properties: make_properties(osmobj.tags()),
-        address: None,
-        names: mimir::I18nProperties::default(),
-        labels: mimir::I18nProperties::default(),
+        address,
+        names,
+        labels,
+        aliases: get_aliases_from_tags(osmobj.tags(), name),
         distance: None,
         country_codes,
         context: None,
@@ -205,12 +335,14 @@ pub fn pois(
     pbf: &mut OsmPbfReader,
     matcher: &PoiConfig,
     admins_geofinder: &AdminGeoFinder,
+    langs: &[String],
+    rejects: &Rejects,
 ) -> Vec<Poi> {
     let objects = pbf.get_objs_and_deps(|o| matcher.is_poi(o.tags())).unwrap();
     objects
         .iter()
         .filter(|&(_, obj)| matcher.is_poi(obj.tags()))
-        .filter_map(|(_, obj)| parse_poi(obj, &objects, matcher, admins_geofinder))
+        .filter_map(|(_, obj)| parse_poi(obj, &objects, matcher, admins_geofinder, langs, rejects))
         .collect()
 }
 
@@ -225,17 +357,52 @@ pub fn compute_poi_weight(pois_vec: &mut [Poi]) {
     }
 }
 
-pub fn add_address(pois_vec: &mut [Poi], rubber: &mut rubber::Rubber) {
-    for poi in pois_vec {
-        poi.address = rubber
-            .get_address(&poi.coord)
-            .ok()
-            .and_then(|addrs| addrs.into_iter().next())
-            .map(|addr| addr.address().unwrap());
-        if poi.address.is_none() {
-            warn!("The poi {:?} {:?} doesn't have address", poi.id, poi.name);
-        }
-    }
+/// Search in Elasticsearch the address of the pois that did not get one from their `addr:*` tags.
+/// The reverse queries are done in parallel on `nb_threads` threads.
+pub fn add_address(pois_vec: Vec<Poi>, rubber: &rubber::Rubber, nb_threads: usize) -> Vec<Poi> {
+    let rubber = rubber.clone();
+    pois_vec
+        .into_iter()
+        .with_nb_threads(nb_threads)
+        .par_map(move |mut poi| {
+            if poi.address.is_none() {
+                poi.address = rubber
+                    .reverse(&poi.coord, None)
+                    .ok()
+                    .and_then(|addrs| addrs.into_iter().next())
+                    .and_then(|addr| addr.address());
+            }
+            if poi.address.is_none() {
+                warn!("The poi {:?} {:?} doesn't have address", poi.id, poi.name);
+            }
+            poi
+        })
+        .collect()
+}
+
+/// Search the address of the pois that did not get one from their `addr:*` tags
+/// in the addresses loaded in memory.
+/// The searches are done in parallel on `nb_threads` threads.
+pub fn add_address_from_finder(
+    pois_vec: Vec<Poi>,
+    addresses_geofinder: AddressGeoFinder,
+    nb_threads: usize,
+) -> Vec<Poi> {
+    pois_vec
+        .into_iter()
+        .with_nb_threads(nb_threads)
+        .par_map(move |mut poi| {
+            if poi.address.is_none() {
+                poi.address = addresses_geofinder
+                    .get(&poi.coord)
+                    .and_then(|addr| addr.address());
+                if poi.address.is_none() {
+                    warn!("The poi {:?} {:?} doesn't have address", poi.id, poi.name);
+                }
+            }
+            poi
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -417,4 +584,124 @@ mod tests {
             ],))
         );
     }
+    #[test]
+    fn check_rich_filters() {
+        let json = r#"{
+            "types": [
+                {"id": "poi_type:shop", "name": "Shop"},
+                {"id": "poi_type:food", "name": "Food"},
+                {"id": "poi_type:parking", "name": "Parking"},
+                {"id": "poi_type:fast_food", "name": "Fast food"}
+            ],
+            "rules": [
+                {
+                    "osm_tags_filters": [{"key": "shop", "value": "*"}],
+                    "type": "poi_type:shop"
+                },
+                {
+                    "osm_tags_filters": [{"key": "amenity", "value": "cafe|bar|restaurant"}],
+                    "type": "poi_type:food"
+                },
+                {
+                    "osm_tags_filters": [
+                        {"key": "amenity", "value": "parking"},
+                        {"key": "access", "value": "private", "negate": true}
+                    ],
+                    "type": "poi_type:parking"
+                },
+                {
+                    "osm_tags_filters": [{"key": "cuisine", "regex": "^(burger|pizza)"}],
+                    "type": "poi_type:fast_food",
+                    "priority": 1
+                }
+            ]
+        }"#;
+        let c = from_str(json).unwrap();
+        assert_eq!(
+            Some("poi_type:shop"),
+            c.get_poi_id(&tags(&[("shop", "bakery")]))
+        );
+        assert_eq!(
+            Some("poi_type:food"),
+            c.get_poi_id(&tags(&[("amenity", "bar")]))
+        );
+        assert_eq!(None, c.get_poi_id(&tags(&[("amenity", "barber")])));
+        assert_eq!(
+            Some("poi_type:parking"),
+            c.get_poi_id(&tags(&[("amenity", "parking")]))
+        );
+        assert_eq!(
+            Some("poi_type:parking"),
+            c.get_poi_id(&tags(&[("amenity", "parking"), ("access", "yes")]))
+        );
+        assert_eq!(
+            None,
+            c.get_poi_id(&tags(&[("amenity", "parking"), ("access", "private")]))
+        );
+        // the rule with the highest priority wins, even if it is not the first one
+        assert_eq!(
+            Some("poi_type:fast_food"),
+            c.get_poi_id(&tags(&[("amenity", "restaurant"), ("cuisine", "burger")]))
+        );
+        assert_eq!(
+            Some("poi_type:food"),
+            c.get_poi_id(&tags(&[("amenity", "restaurant"), ("cuisine", "french")]))
+        );
+    }
+    #[test]
+    fn check_invalid_filters() {
+        let config = |filter: &str| {
+            format!(
+                r#"{{
+                "types": [{{"id": "poi_type:foo", "name": "Foo"}}],
+                "rules": [{{"osm_tags_filters": [{}], "type": "poi_type:foo"}}]
+            }}"#,
+                filter
+            )
+        };
+        from_str(&config(r#"{"key": "foo", "value": "bar"}"#)).unwrap();
+        from_str(&config(r#"{"key": "foo", "value": "*"}"#)).unwrap();
+        from_str(&config(r#"{"key": "foo"}"#)).unwrap_err();
+        from_str(&config(r#"{"key": "", "value": "bar"}"#)).unwrap_err();
+        from_str(&config(r#"{"key": "foo", "value": "bar|"}"#)).unwrap_err();
+        from_str(&config(r#"{"key": "foo", "value": "bar|*"}"#)).unwrap_err();
+        from_str(&config(r#"{"key": "foo", "regex": "(bar"}"#)).unwrap_err();
+        from_str(&config(r#"{"key": "foo", "value": "bar", "regex": "bar"}"#)).unwrap_err();
+        from_str(&config(r#"{"key": "foo", "value": "bar", "negate": true}"#)).unwrap_err();
+        from_str(&config(
+            r#"{"key": "foo", "value": "bar", "negate": true},
+            {"key": "bar", "value": "*"}"#,
+        ))
+        .unwrap();
+        from_str(&config("")).unwrap_err();
+    }
+    #[test]
+    fn check_toml_filters() {
+        let c: PoiConfig = toml::from_str(
+            r#"
+            [[types]]
+              id = "poi_type:shop"
+              name = "Shop"
+            [[rules]]
+              type = "poi_type:shop"
+              priority = 2
+              [[rules.osm_tags_filters]]
+                key = "shop"
+                value = "*"
+              [[rules.osm_tags_filters]]
+                key = "name"
+                regex = "^Super"
+            "#,
+        )
+        .unwrap();
+        c.check().unwrap();
+        assert_eq!(
+            Some("poi_type:shop"),
+            c.get_poi_id(&tags(&[("shop", "supermarket"), ("name", "Supermarché")]))
+        );
+        assert_eq!(
+            None,
+            c.get_poi_id(&tags(&[("shop", "supermarket"), ("name", "Épicerie")]))
+        );
+    }
 }
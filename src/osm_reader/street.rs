@@ -33,7 +33,9 @@
     clippy::never_loop,
     clippy::option_map_unit_fn
 )]
-use super::osm_utils::get_way_coord;
+use super::osm_utils::{
+    get_aliases_from_tags, get_names_from_tags, get_way_coord, get_way_line_string, merge_lines,
+};
 use super::OsmPbfReader;
 use crate::admin_geofinder::AdminGeoFinder;
 use crate::{labels, settings, utils, Error};
@@ -41,7 +43,7 @@ use cosmogony::ZoneType;
 use failure::ResultExt;
 use osmpbfreader::{OsmId, StoreObjs};
 use slog_scope::info;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ops::Deref;
 use std::sync::Arc;
 
@@ -59,6 +61,7 @@ pub fn streets(
     pbf: &mut OsmPbfReader,
     admins_geofinder: &AdminGeoFinder,
     settings: &settings::osm2mimir::Settings,
+    langs: &[String],
 ) -> Result<Vec<mimir::Street>, Error> {
     let invalid_highways = settings
         .street
@@ -112,43 +115,79 @@ pub fn streets(
     info!("reading pbf done.");
 
     // Builder for street object
-    let build_street =
-        |id: String, name: String, coord: mimir::Coord, admins: Vec<Arc<mimir::Admin>>| {
-            let admins_iter = admins.iter().map(Deref::deref);
-            let country_codes = utils::find_country_codes(admins_iter.clone());
-            mimir::Street {
-                id,
-                label: labels::format_street_label(&name, admins_iter, &country_codes),
-                name,
-                weight: 0.,
-                zip_codes: utils::get_zip_codes_from_admins(&admins),
-                administrative_regions: admins,
-                coord,
-                approx_coord: Some(coord.into()),
-                distance: None,
-                country_codes,
-                context: None,
-            }
+    let build_street = |id: String,
+                        name: String,
+                        names: mimir::I18nProperties,
+                        aliases: Vec<String>,
+                        coord: mimir::Coord,
+                        geometry: Option<geo_types::MultiLineString<f64>>,
+                        admins: Vec<Arc<mimir::Admin>>| {
+        let admins_iter = admins.iter().map(Deref::deref);
+        let country_codes = utils::find_country_codes(admins_iter.clone());
+        let label = labels::format_street_label(&name, admins_iter.clone(), &country_codes);
+        let labels = labels::format_international_street_label(
+            &names,
+            &name,
+            &label,
+            admins_iter,
+            &country_codes,
+            langs,
+        );
+        let mut street = mimir::Street {
+            id,
+            label,
+            name,
+            names,
+            labels,
+            aliases,
+            weight: 0.,
+            zip_codes: utils::get_zip_codes_from_admins(&admins),
+            administrative_regions: admins,
+            coord,
+            approx_coord: Some(coord.into()),
+            geometry: None,
+            distance: None,
+            country_codes,
+            context: None,
         };
+        if let Some(geometry) = geometry {
+            street.set_geometry(geometry);
+        }
+        street
+    };
 
     // Return an iterator giving documents that will be inserted for a given
     // street: one for each hierarchy of admins.
-    let build_streets_for_admins =
-        move |name: String, id, kind, mut all_admins: Vec<Vec<_>>, coord| {
-            let single_output = all_admins.len() <= 1;
-            all_admins.sort_unstable(); // sort admins to make id deterministic
-            all_admins.into_iter().enumerate().map(move |(i, admins)| {
-                let doc_id = {
-                    if single_output {
-                        format!("street:osm:{}:{}", kind, id)
-                    } else {
-                        format!("street:osm:{}:{}-{}", kind, id, i)
-                    }
-                };
+    let build_streets_for_admins = move |name: String,
+                                         names: mimir::I18nProperties,
+                                         aliases: Vec<String>,
+                                         id,
+                                         kind,
+                                         mut all_admins: Vec<Vec<_>>,
+                                         coord,
+                                         geometry: Option<_>| {
+        let single_output = all_admins.len() <= 1;
+        all_admins.sort_unstable(); // sort admins to make id deterministic
+        all_admins.into_iter().enumerate().map(move |(i, admins)| {
+            let doc_id = {
+                if single_output {
+                    format!("street:osm:{}:{}", kind, id)
+                } else {
+                    format!("street:osm:{}:{}-{}", kind, id, i)
+                }
+            };
 
-                build_street(doc_id, name.clone(), coord, admins)
-            })
-        };
+            build_street(
+                doc_id,
+                name.clone(),
+                names.clone(),
+                aliases.clone(),
+                coord,
+                geometry.clone(),
+                admins,
+            )
+        })
+    };
 
     // List of outputed streets
     let mut street_list = Vec::new();
@@ -174,6 +213,18 @@ pub fn streets(
                 .filter(OsmId::is_way),
         );
 
+        // The geometry of the street is made of all its ways
+        let geometry = merge_lines(
+            rel.refs
+                .iter()
+                .filter(|ref_obj| ref_obj.member.is_way() && &ref_obj.role == "street")
+                .filter_map(|ref_obj| {
+                    let obj = objs_map.get(&ref_obj.member)?;
+                    get_way_line_string(&objs_map, obj.way()?)
+                })
+                .collect(),
+        );
+
         let rel_street = rel
             .refs
             .iter()
@@ -183,13 +234,21 @@ pub fn streets(
                 let way = obj.way()?;
                 let coord = get_way_coord(&objs_map, &way);
                 let name = rel_name.or_else(|| way.tags.get("name"))?;
+                let tags = if rel_name.is_some() {
+                    &rel.tags
+                } else {
+                    &way.tags
+                };
 
                 Some(build_streets_for_admins(
                     name.to_string(),
+                    get_names_from_tags(tags, langs),
+                    get_aliases_from_tags(tags, name),
                     rel.id.0,
                     "relation",
                     get_street_admin(admins_geofinder, &objs_map, &way),
                     coord,
+                    geometry.clone(),
                 ))
             })
             .next();
@@ -202,6 +261,8 @@ pub fn streets(
     // We merge all the ways with same `way_name` and `admin list of level(=city_level)`
     // We use a Map to keep track of the way of smallest Id for a given pair of "name + cities list"
     let mut name_admin_map = BTreeMap::new();
+    // All the ways merged for a given pair of "name + cities list", to build the geometry
+    let mut name_admin_ways = HashMap::<_, Vec<OsmId>>::new();
 
     objs_map.for_each_filter(Kind::Way, |obj| {
         let osmid = obj.id();
@@ -219,6 +280,10 @@ pub fn streets(
                     .find(|admin| admin.is_city())
                     .map(|city| city.id.to_string())
                 {
+                    name_admin_ways
+                        .entry((name.to_string(), city.clone()))
+                        .or_default()
+                        .push(osmid);
                     name_admin_map
                         .entry((name.to_string(), city))
                         .and_modify(|(stored_id, stored_admins)| {
@@ -237,12 +302,17 @@ pub fn streets(
     // This step ensure that documents have distinguishable IDs if they are
     // added for the same street but different admins.
     let mut all_admins_for_street = HashMap::new();
+    let mut all_ways_for_street = HashMap::<_, BTreeSet<OsmId>>::new();
 
-    for (_, (min_id, admins)) in name_admin_map {
+    for (key, (min_id, admins)) in name_admin_map {
         all_admins_for_street
             .entry(min_id)
             .or_insert_with(Vec::new)
             .push(admins);
+        all_ways_for_street
+            .entry(min_id)
+            .or_default()
+            .extend(name_admin_ways.remove(&key).unwrap_or_default());
     }
 
     street_list.extend(
@@ -252,12 +322,29 @@ pub fn streets(
                 let obj = objs_map.get(&id)?;
                 let way = obj.way()?;
 
+                let name = way.tags.get("name")?;
+
+                let geometry = merge_lines(
+                    all_ways_for_street
+                        .get(&id)
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|way_id| {
+                            let obj = objs_map.get(way_id)?;
+                            get_way_line_string(&objs_map, obj.way()?)
+                        })
+                        .collect(),
+                );
+
                 Some(build_streets_for_admins(
-                    way.tags.get("name")?.to_string(),
+                    name.to_string(),
+                    get_names_from_tags(&way.tags, langs),
+                    get_aliases_from_tags(&way.tags, name),
                     way.id.0,
                     "way",
                     all_admins,
                     get_way_coord(&objs_map, way),
+                    geometry,
                 ))
             })
             .flatten(),
@@ -0,0 +1,330 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Report of the input records rejected by the importers.
+//!
+//! Each rejected record is written as a JSON line with a reason code, and a summary
+//! giving the number of rejected records per reason is written next to the report,
+//! in `<report>.summary.json`.
+
+use crate::Error;
+use failure::{Fail, ResultExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use slog_scope::{error, info, warn};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use structopt::StructOpt;
+
+/// Reason of the rejection of an input record
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    /// The record can't be read or doesn't have the expected fields
+    InvalidRecord,
+    /// The record has no coordinates, or invalid ones
+    InvalidCoord,
+    /// The record has no id
+    MissingId,
+    /// The record has no name
+    MissingName,
+    /// The address has no street
+    MissingStreet,
+    /// The type of the poi is missing or unknown
+    UnknownPoiType,
+    /// No administrative region has been found for the record
+    NoAdmin,
+    /// The record has been read, but can't be converted into a mimir object
+    InvalidData,
+}
+
+/// An error giving the reason of a rejection,
+/// to be used by the conversions whose errors can have several reasons
+#[derive(Fail, Debug)]
+#[fail(display = "{}", message)]
+pub struct Rejection {
+    pub reason: RejectReason,
+    pub message: String,
+}
+
+impl Rejection {
+    pub fn new(reason: RejectReason, message: impl Into<String>) -> Self {
+        Rejection {
+            reason,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RejectedRecord<'a, R: ?Sized> {
+    reason: RejectReason,
+    message: String,
+    record: &'a R,
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    total: usize,
+    reasons: &'a BTreeMap<RejectReason, usize>,
+}
+
+struct Report {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    counts: BTreeMap<RejectReason, usize>,
+}
+
+/// Argument of the report of the rejected records, to be flattened in the arguments
+/// of the import tools
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct RejectsArgs {
+    /// File where the rejected input records are written, with the reason of their rejection.
+    /// A summary of the rejections is also written in `<file>.summary.json`.
+    #[structopt(long = "rejects", parse(from_os_str))]
+    pub path: Option<PathBuf>,
+}
+
+impl RejectsArgs {
+    /// Create the report, if a file is given
+    pub fn open(&self) -> Result<Rejects, Error> {
+        Rejects::new(self.path.as_deref())
+    }
+}
+
+/// Report of the rejected records, doing nothing if no file is given.
+/// It can be cloned to be shared between threads.
+#[derive(Clone, Default)]
+pub struct Rejects {
+    report: Option<Arc<Mutex<Report>>>,
+}
+
+impl Rejects {
+    pub fn new(path: Option<&Path>) -> Result<Self, Error> {
+        let report = match path {
+            Some(path) => {
+                let file = File::create(path)
+                    .with_context(|_| format!("Impossible to create the rejects {:?}", path))?;
+                Some(Arc::new(Mutex::new(Report {
+                    path: path.to_path_buf(),
+                    writer: BufWriter::new(file),
+                    counts: BTreeMap::new(),
+                })))
+            }
+            None => None,
+        };
+        Ok(Rejects { report })
+    }
+
+    /// The record as JSON, kept to be written in the report if it is rejected later.
+    /// Nothing is kept if there is no report.
+    pub fn record_of<R: Serialize + ?Sized>(&self, record: &R) -> Option<Value> {
+        self.report
+            .as_ref()
+            .and_then(|_| serde_json::to_value(record).ok())
+    }
+
+    /// Write a rejected record in the report
+    pub fn reject<R: Serialize + ?Sized>(
+        &self,
+        reason: RejectReason,
+        record: &R,
+        message: impl Display,
+    ) {
+        let report = match self.report {
+            Some(ref report) => report,
+            None => return,
+        };
+        let mut report = report.lock().unwrap();
+        *report.counts.entry(reason).or_insert(0) += 1;
+        let line = RejectedRecord {
+            reason,
+            message: message.to_string(),
+            record,
+        };
+        let res = serde_json::to_writer(&mut report.writer, &line)
+            .map_err(Error::from)
+            .and_then(|_| report.writer.write_all(b"\n").map_err(Error::from));
+        if let Err(err) = res {
+            error!("Impossible to write the rejected record: {}", err);
+        }
+    }
+
+    /// Write a record rejected because of an error, the reason being the one of the
+    /// `Rejection` if the error is one, or `default_reason` otherwise
+    pub fn reject_error<R: Serialize + ?Sized>(
+        &self,
+        default_reason: RejectReason,
+        record: &R,
+        err: &Error,
+    ) {
+        let reason = err
+            .downcast_ref::<Rejection>()
+            .map_or(default_reason, |rejection| rejection.reason);
+        self.reject(reason, record, err);
+    }
+
+    /// Flush the report and write its summary
+    pub fn finish(&self) -> Result<(), Error> {
+        let report = match self.report {
+            Some(ref report) => report,
+            None => return Ok(()),
+        };
+        let mut report = report.lock().unwrap();
+        report.writer.flush()?;
+
+        let summary = Summary {
+            total: report.counts.values().sum(),
+            reasons: &report.counts,
+        };
+        info!("{} records rejected, see {:?}", summary.total, report.path);
+        for (reason, count) in &report.counts {
+            info!("{:>10} {:?}", count, reason);
+        }
+        let summary_path = summary_path(&report.path);
+        serde_json::to_writer_pretty(File::create(&summary_path)?, &summary)
+            .with_context(|_| format!("Impossible to write the summary {:?}", summary_path))?;
+        Ok(())
+    }
+}
+
+/// Path of the summary of a report
+pub fn summary_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".summary.json");
+    path.with_file_name(name)
+}
+
+/// Deserialize the records of a csv reader,
+/// the ones that can't be deserialized being written in the rejects
+pub fn deserialize_records<R, T>(
+    mut reader: csv::Reader<R>,
+    rejects: Rejects,
+) -> impl Iterator<Item = T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    let headers = if reader.has_headers() {
+        reader
+            .headers()
+            .map_err(|e| rejects.reject(RejectReason::InvalidRecord, &Value::Null, e))
+            .ok()
+            .cloned()
+    } else {
+        None
+    };
+    reader
+        .into_records()
+        .filter_map(move |record| match record {
+            Ok(record) => record
+                .deserialize(headers.as_ref())
+                .map_err(|e| {
+                    warn!("Impossible to read line, error: {}", e);
+                    let fields: Vec<_> = record.iter().collect();
+                    rejects.reject(RejectReason::InvalidRecord, &fields, e)
+                })
+                .ok(),
+            Err(e) => {
+                warn!("Impossible to read line, error: {}", e);
+                rejects.reject(RejectReason::InvalidRecord, &Value::Null, e);
+                None
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Record {
+        id: String,
+        lat: f64,
+    }
+
+    #[test]
+    fn test_rejects_report() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("rejects_test_{}.jsonl", std::process::id()));
+        let rejects = Rejects::new(Some(&path)).unwrap();
+
+        let csv = "id,lat\na,1.5\nb,not_a_float\nc,2.5\n";
+        let records: Vec<Record> =
+            deserialize_records(csv::Reader::from_reader(csv.as_bytes()), rejects.clone())
+                .collect();
+        assert_eq!(records.len(), 2);
+
+        rejects.reject_error(
+            RejectReason::InvalidData,
+            "c",
+            &Rejection::new(RejectReason::NoAdmin, "no admin for c").into(),
+        );
+        rejects.reject_error(
+            RejectReason::InvalidData,
+            "d",
+            &failure::format_err!("impossible"),
+        );
+        rejects.finish().unwrap();
+
+        assert_eq!(rejects.record_of("e"), Some(Value::from("e")));
+        assert_eq!(Rejects::default().record_of("e"), None);
+
+        let lines: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["reason"], "invalid_record");
+        assert_eq!(lines[0]["record"], serde_json::json!(["b", "not_a_float"]));
+        assert_eq!(lines[1]["reason"], "no_admin");
+        assert_eq!(lines[1]["message"], "no admin for c");
+        assert_eq!(lines[2]["reason"], "invalid_data");
+
+        let summary_path = summary_path(&path);
+        let summary: Value = serde_json::from_reader(File::open(&summary_path).unwrap()).unwrap();
+        assert_eq!(
+            summary,
+            serde_json::json!({
+                "total": 3,
+                "reasons": {"invalid_data": 1, "invalid_record": 1, "no_admin": 1}
+            })
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&summary_path).unwrap();
+    }
+}
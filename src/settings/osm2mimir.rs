@@ -8,6 +8,7 @@ use std::path::PathBuf;
 use structopt::StructOpt;
 
 use crate::osm_reader::poi;
+use crate::rejects::RejectsArgs;
 use crate::Error;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -22,6 +23,15 @@ pub struct Street {
     pub exclusion: StreetExclusion,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct Address {
+    pub import: bool,
+    /// Expand the `addr:interpolation` ways into individual house numbers
+    pub interpolation: bool,
+    /// Dataset of the addresses index, `<dataset>_osm` if not given
+    pub dataset: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Admin {
     pub import: bool,
@@ -33,6 +43,11 @@ pub struct Admin {
 pub struct Poi {
     pub import: bool,
     pub config: Option<poi::PoiConfig>,
+    /// Search the addresses of the pois in memory, instead of querying Elasticsearch for each poi
+    pub in_memory_reverse: bool,
+    /// Dump directory from which the addresses are loaded for the in memory search,
+    /// if not given, the addresses are loaded from Elasticsearch
+    pub addresses_dump: Option<PathBuf>,
 }
 
 #[cfg(feature = "db-storage")]
@@ -46,12 +61,16 @@ pub struct Database {
 pub struct Elasticsearch {
     pub connection_string: String,
     pub insert_thread_count: usize,
+    /// Number of threads used to search the addresses of the pois
+    pub reverse_thread_count: usize,
     pub streets_shards: usize,
     pub streets_replicas: usize,
     pub admins_shards: usize,
     pub admins_replicas: usize,
     pub pois_shards: usize,
     pub pois_replicas: usize,
+    pub addresses_shards: usize,
+    pub addresses_replicas: usize,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -62,7 +81,12 @@ pub struct Settings {
     pub elasticsearch: Elasticsearch,
     pub street: Option<Street>,
     pub poi: Option<Poi>,
+    pub address: Option<Address>,
     pub admin: Option<Admin>,
+    /// Languages of the names and labels of the pois and streets.
+    /// If empty, the languages of the admins are used.
+    #[serde(default)]
+    pub langs: Vec<String>,
 }
 
 impl Settings {
@@ -197,9 +221,27 @@ pub struct Args {
     /// Import POIs.
     #[structopt(short = "p", long = "import-poi")]
     import_poi: Option<bool>,
+    /// Search the addresses of the POIs in memory instead of querying Elasticsearch for each POI.
+    #[structopt(long = "in-memory-reverse")]
+    in_memory_reverse: Option<bool>,
+    /// Dump directory (written by an importer with a `file://` connection string) from which
+    /// the addresses and streets are loaded for the in memory search of the POIs addresses.
+    /// If not given, they are loaded from Elasticsearch.
+    #[structopt(long = "addresses-dump", parse(from_os_str))]
+    addresses_dump: Option<PathBuf>,
+    /// Import addresses.
+    #[structopt(long = "import-address")]
+    import_address: Option<bool>,
+    /// Name of the dataset of the addresses, `<dataset>_osm` if not given.
+    #[structopt(long = "address-dataset")]
+    address_dataset: Option<String>,
     /// Name of the dataset.
     #[structopt(short = "d", long = "dataset")]
     pub dataset: Option<String>,
+    /// Languages of the names and labels of the POIs and streets.
+    /// If not given, the languages of the admins are used.
+    #[structopt(long = "lang")]
+    langs: Vec<String>,
     /// Number of shards for the admin es index
     #[structopt(long = "nb-admin-shards")]
     nb_admin_shards: Option<usize>,
@@ -218,6 +260,12 @@ pub struct Args {
     /// Number of replicas for the es index
     #[structopt(long = "nb-poi-replicas")]
     nb_poi_replicas: Option<usize>,
+    /// Number of shards for the address es index
+    #[structopt(long = "nb-address-shards")]
+    nb_address_shards: Option<usize>,
+    /// Number of replicas for the address es index
+    #[structopt(long = "nb-address-replicas")]
+    nb_address_replicas: Option<usize>,
     /// If you use this option by providing a filename, then we
     /// will use a SQlite database that will be persisted. You
     /// can only do that if osm2mimir was compiled with the
@@ -235,6 +283,10 @@ pub struct Args {
     /// to handle values that are too high.
     #[structopt(short = "T", long = "nb-insert-threads")]
     nb_insert_threads: Option<usize>,
+    /// Number of threads used to search the addresses of the POIs without `addr:*` tags in
+    /// Elasticsearch.
+    #[structopt(long = "nb-reverse-threads")]
+    nb_reverse_threads: Option<usize>,
 
     /// Path to the config directory
     /// osm2mimir will read the default configuration in there, and maybe
@@ -250,6 +302,12 @@ pub struct Args {
     /// be set)
     #[structopt(short = "s", long = "settings")]
     settings: Option<String>,
+
+    #[structopt(flatten)]
+    pub rejects: RejectsArgs,
+
+    #[structopt(flatten)]
+    pub publication: crate::utils::PublicationArgs,
 }
 
 impl Source for Args {
@@ -265,6 +323,20 @@ impl Source for Args {
             m.insert(String::from("dataset"), Value::new(None, dataset));
         }
 
+        // LANGS
+        if !self.langs.is_empty() {
+            m.insert(
+                String::from("langs"),
+                Value::new(
+                    None,
+                    self.langs
+                        .iter()
+                        .map(|lang| Value::new(None, lang.clone()))
+                        .collect::<Vec<_>>(),
+                ),
+            );
+        }
+
         // ADMIN
         if let Some(import_admin) = self.import_admin {
             m.insert(String::from("admin.import"), Value::new(None, import_admin));
@@ -313,6 +385,35 @@ impl Source for Args {
             m.insert(String::from("poi.import"), Value::new(None, import_poi));
         }
 
+        if let Some(in_memory_reverse) = self.in_memory_reverse {
+            m.insert(
+                String::from("poi.in_memory_reverse"),
+                Value::new(None, in_memory_reverse),
+            );
+        }
+
+        if let Some(addresses_dump) = self.addresses_dump.clone() {
+            m.insert(
+                String::from("poi.addresses_dump"),
+                Value::new(None, addresses_dump.to_str().expect("valid utf-8 filename")),
+            );
+        }
+
+        // ADDRESS
+        if let Some(import_address) = self.import_address {
+            m.insert(
+                String::from("address.import"),
+                Value::new(None, import_address),
+            );
+        }
+
+        if let Some(address_dataset) = self.address_dataset.clone() {
+            m.insert(
+                String::from("address.dataset"),
+                Value::new(None, address_dataset),
+            );
+        }
+
         // ELASTICSEARCH SETTINGS
 
         if let Some(connection_string) = self.connection_string.clone() {
@@ -382,6 +483,36 @@ impl Source for Args {
             );
         }
 
+        if let Some(nb_address_shards) = self.nb_address_shards {
+            m.insert(
+                String::from("elasticsearch.addresses_shards"),
+                Value::new(
+                    None,
+                    i64::try_from(nb_address_shards).map_err(|e| {
+                        ConfigError::Message(format!(
+                            "Could not convert count of address shards to integer: {}",
+                            e
+                        ))
+                    })?,
+                ),
+            );
+        }
+
+        if let Some(nb_address_replicas) = self.nb_address_replicas {
+            m.insert(
+                String::from("elasticsearch.addresses_replicas"),
+                Value::new(
+                    None,
+                    i64::try_from(nb_address_replicas).map_err(|e| {
+                        ConfigError::Message(format!(
+                            "Could not convert count of address replicas to integer: {}",
+                            e
+                        ))
+                    })?,
+                ),
+            );
+        }
+
         if let Some(nb_admin_shards) = self.nb_admin_shards {
             m.insert(
                 String::from("elasticsearch.admins_shards"),
@@ -427,6 +558,21 @@ impl Source for Args {
             );
         }
 
+        if let Some(nb_reverse_threads) = self.nb_reverse_threads {
+            m.insert(
+                String::from("elasticsearch.reverse_thread_count"),
+                Value::new(
+                    None,
+                    i64::try_from(nb_reverse_threads).map_err(|e| {
+                        ConfigError::Message(format!(
+                            "Could not convert elasticsearch reverse thread count to integer: {}",
+                            e
+                        ))
+                    })?,
+                ),
+            );
+        }
+
         // DATABASE
         #[cfg(feature = "db-storage")]
         if let Some(db_file) = self.db_file.clone() {
@@ -32,12 +32,16 @@ use crate::admin_geofinder::AdminGeoFinder;
 use crate::{labels, utils};
 use failure::format_err;
 use failure::{Error, ResultExt};
-use mimir::rubber::{IndexSettings, Rubber, TypedIndex};
+use mimir::objects::MimirObject;
+use mimir::rubber::{IndexSettings, PublicationSettings, Rubber, TypedIndex};
 use slog_scope::{info, warn};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::mem::replace;
 use std::ops::Deref;
 use std::sync::Arc;
+use transit_model::objects as navitia;
+use typed_index_collection::Idx;
 
 const GLOBAL_STOP_INDEX_NAME: &str = "munin_global_stops";
 
@@ -57,38 +61,354 @@ pub fn initialize_weights<'a, It, S: ::std::hash::BuildHasher>(
     }
 }
 
+fn get_lines(idx: Idx<navitia::StopArea>, navitia: &transit_model::Model) -> Vec<mimir::Line> {
+    use mimir::FromTransitModel;
+    let mut lines: Vec<_> = navitia
+        .get_corresponding_from_idx(idx)
+        .into_iter()
+        .map(|l_idx| mimir::Line::from_transit_model(l_idx, navitia))
+        .collect();
+    sort_lines(&mut lines);
+    lines
+}
+
+/// Sort the lines of a stop by their sort order, then code and name.
+pub fn sort_lines(lines: &mut [mimir::Line]) {
+    // we want the lines to be sorted in a way where
+    // line-3 is before line-11, so be use a human_sort
+    lines.sort_by(|lhs, rhs| {
+        match (&lhs.sort_order, &rhs.sort_order) {
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(s), Some(o)) => s.cmp(o),
+            (None, None) => Ordering::Equal,
+        }
+        .then_with(|| match (&lhs.code, &rhs.code) {
+            (Some(l), Some(r)) => human_sort::compare(l, r),
+            _ => Ordering::Equal,
+        })
+        .then_with(|| human_sort::compare(&lhs.name, &rhs.name))
+    });
+}
+
+fn to_mimir(
+    idx: Idx<navitia::StopArea>,
+    stop_area: &navitia::StopArea,
+    navitia: &transit_model::Model,
+) -> mimir::Stop {
+    let commercial_modes = navitia
+        .get_corresponding_from_idx(idx)
+        .into_iter()
+        .map(|cm_idx| mimir::CommercialMode {
+            id: mimir::objects::normalize_id(
+                "commercial_mode",
+                &navitia.commercial_modes[cm_idx].id,
+            ),
+            name: navitia.commercial_modes[cm_idx].name.clone(),
+        })
+        .collect();
+    let physical_modes = navitia
+        .get_corresponding_from_idx(idx)
+        .into_iter()
+        .map(|pm_idx| mimir::PhysicalMode {
+            id: mimir::objects::normalize_id("physical_mode", &navitia.physical_modes[pm_idx].id),
+            name: navitia.physical_modes[pm_idx].name.clone(),
+        })
+        .collect();
+    let comments = stop_area
+        .comment_links
+        .iter()
+        .filter_map(|comment_id| {
+            let res = navitia.comments.get(comment_id);
+            if res.is_none() {
+                warn!("Could not retrieve comments for id {}", comment_id);
+            }
+            res
+        })
+        .map(|comment| mimir::Comment {
+            name: comment.name.clone(),
+        })
+        .collect();
+    let feed_publishers = navitia
+        .get_corresponding_from_idx(idx)
+        .into_iter()
+        .map(|contrib_idx| mimir::FeedPublisher {
+            id: navitia.contributors[contrib_idx].id.clone(),
+            name: navitia.contributors[contrib_idx].name.clone(),
+            license: navitia.contributors[contrib_idx]
+                .license
+                .clone()
+                .unwrap_or_else(|| "".into()),
+            url: navitia.contributors[contrib_idx]
+                .website
+                .clone()
+                .unwrap_or_else(|| "".into()),
+        })
+        .collect();
+    let coord = mimir::Coord::new(stop_area.coord.lon, stop_area.coord.lat);
+
+    let lines = get_lines(idx, navitia);
+
+    let aliases = utils::get_aliases(
+        &stop_area.name,
+        stop_area
+            .object_properties
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str())),
+    );
+
+    mimir::Stop {
+        id: mimir::objects::normalize_id("stop_area", &stop_area.id),
+        label: stop_area.name.clone(),
+        name: stop_area.name.clone(),
+        coord,
+        approx_coord: Some(coord.into()),
+        commercial_modes,
+        physical_modes,
+        lines,
+        comments,
+        timezone: stop_area
+            .timezone
+            .clone()
+            .map(chrono_tz::Tz::name)
+            .map(str::to_owned)
+            .unwrap_or_default(),
+        codes: stop_area
+            .codes
+            .iter()
+            .map(|&(ref t, ref v)| mimir::Code {
+                name: t.clone(),
+                value: v.clone(),
+            })
+            .collect(),
+        properties: stop_area
+            .object_properties
+            .iter()
+            .map(|(ref k, ref v)| mimir::Property {
+                key: k.to_string(),
+                value: v.to_string(),
+            })
+            .collect(),
+        feed_publishers,
+        aliases,
+        ..Default::default()
+    }
+}
+
+fn get_nb_stop_points(navitia: &transit_model::Model) -> HashMap<String, u32> {
+    navitia
+        .stop_areas
+        .iter()
+        .map(|(idx, sa)| {
+            let id = mimir::objects::normalize_id("stop_area", &sa.id);
+            let nb_stop_points = navitia
+                .get_corresponding_from_idx::<_, navitia::StopPoint>(idx)
+                .len();
+            (id, nb_stop_points as u32)
+        })
+        .collect()
+}
+
+/// Convert the stop areas of a transit model (read from a NTFS or a GTFS) into weighted stops.
+/// The weight of a stop depends on its number of stop points.
+pub fn stops_from_model(navitia: &transit_model::Model) -> Vec<mimir::Stop> {
+    let nb_stop_points = get_nb_stop_points(navitia);
+    let mut stops: Vec<mimir::Stop> = navitia
+        .stop_areas
+        .iter()
+        .map(|(idx, sa)| to_mimir(idx, sa, navitia))
+        .collect();
+    initialize_weights(stops.iter_mut(), &nb_stop_points);
+    stops
+}
+
+fn get_parent_stop_area(
+    stop_area_id: &str,
+    navitia: &transit_model::Model,
+) -> Option<mimir::ParentStopArea> {
+    let parent = navitia.stop_areas.get(stop_area_id);
+    if parent.is_none() {
+        warn!("Could not retrieve stop area {}", stop_area_id);
+    }
+    parent.map(|sa| mimir::ParentStopArea {
+        id: mimir::objects::normalize_id("stop_area", &sa.id),
+        name: sa.name.clone(),
+    })
+}
+
+fn stop_point_to_mimir(
+    idx: Idx<navitia::StopPoint>,
+    stop_point: &navitia::StopPoint,
+    navitia: &transit_model::Model,
+) -> mimir::StopPoint {
+    let commercial_modes = navitia
+        .get_corresponding_from_idx(idx)
+        .into_iter()
+        .map(|cm_idx| mimir::CommercialMode {
+            id: mimir::objects::normalize_id(
+                "commercial_mode",
+                &navitia.commercial_modes[cm_idx].id,
+            ),
+            name: navitia.commercial_modes[cm_idx].name.clone(),
+        })
+        .collect();
+    let physical_modes = navitia
+        .get_corresponding_from_idx(idx)
+        .into_iter()
+        .map(|pm_idx| mimir::PhysicalMode {
+            id: mimir::objects::normalize_id("physical_mode", &navitia.physical_modes[pm_idx].id),
+            name: navitia.physical_modes[pm_idx].name.clone(),
+        })
+        .collect();
+    let mut lines: Vec<_> = navitia
+        .get_corresponding_from_idx(idx)
+        .into_iter()
+        .map(|l_idx| {
+            use mimir::FromTransitModel;
+            mimir::Line::from_transit_model(l_idx, navitia)
+        })
+        .collect();
+    sort_lines(&mut lines);
+    let coord = mimir::Coord::new(stop_point.coord.lon, stop_point.coord.lat);
+    let aliases = utils::get_aliases(
+        &stop_point.name,
+        stop_point
+            .object_properties
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str())),
+    );
+
+    mimir::StopPoint {
+        id: mimir::objects::normalize_id("stop_point", &stop_point.id),
+        label: stop_point.name.clone(),
+        name: stop_point.name.clone(),
+        coord,
+        approx_coord: Some(coord.into()),
+        stop_type: mimir::StopPointType::StopPoint,
+        parent: get_parent_stop_area(&stop_point.stop_area_id, navitia),
+        commercial_modes,
+        physical_modes,
+        lines,
+        codes: stop_point
+            .codes
+            .iter()
+            .map(|&(ref t, ref v)| mimir::Code {
+                name: t.clone(),
+                value: v.clone(),
+            })
+            .collect(),
+        properties: stop_point
+            .object_properties
+            .iter()
+            .map(|(ref k, ref v)| mimir::Property {
+                key: k.to_string(),
+                value: v.to_string(),
+            })
+            .collect(),
+        aliases,
+        ..Default::default()
+    }
+}
+
+fn entrance_to_mimir(
+    entrance: &navitia::StopLocation,
+    navitia: &transit_model::Model,
+) -> mimir::StopPoint {
+    let coord = mimir::Coord::new(entrance.coord.lon, entrance.coord.lat);
+    mimir::StopPoint {
+        id: mimir::objects::normalize_id("stop_point", &entrance.id),
+        label: entrance.name.clone(),
+        name: entrance.name.clone(),
+        coord,
+        approx_coord: Some(coord.into()),
+        stop_type: mimir::StopPointType::Entrance,
+        parent: entrance
+            .parent_id
+            .as_ref()
+            .and_then(|id| get_parent_stop_area(id, navitia)),
+        ..Default::default()
+    }
+}
+
+/// Convert the stop points and the entrances of a transit model into weighted stop points.
+/// The weight of a stop point is the one of its stop area.
+pub fn stop_points_from_model(navitia: &transit_model::Model) -> Vec<mimir::StopPoint> {
+    let nb_stop_points = get_nb_stop_points(navitia);
+    let max = *nb_stop_points.values().max().unwrap_or(&1) as f64;
+    navitia
+        .stop_points
+        .iter()
+        .map(|(idx, sp)| stop_point_to_mimir(idx, sp, navitia))
+        .chain(
+            navitia
+                .stop_locations
+                .values()
+                .filter(|sl| matches!(sl.stop_type, navitia::StopType::StopEntrance))
+                .map(|sl| entrance_to_mimir(sl, navitia)),
+        )
+        .map(|mut stop_point| {
+            stop_point.weight = stop_point
+                .parent
+                .as_ref()
+                .and_then(|parent| nb_stop_points.get(&parent.id))
+                .map_or(0.0, |nb| *nb as f64 / max);
+            stop_point
+        })
+        .collect()
+}
+
+/// Mix the weight of a stop with the weight of its city
+fn weight_with_admins(weight: f64, admins: &[Arc<mimir::Admin>]) -> f64 {
+    let mut admin_weight = admins
+        .iter()
+        .filter(|adm| adm.is_city())
+        .map(|adm| adm.weight)
+        .next()
+        .unwrap_or(0.0);
+    // FIXME: 1024, automagic!
+    // It's a factor used to bring the stop weight and the admin weight in the same order of
+    // magnitude...
+    // We then use a log to compress the distance between low admin weight and high ones.
+    admin_weight = admin_weight * 1024.0 + 1.0;
+    admin_weight = admin_weight.log10();
+    (weight + admin_weight) / 2.0
+}
+
 pub fn import_stops(
     mut stops: Vec<mimir::Stop>,
     connection_string: &str,
     dataset: &str,
     index_settings: IndexSettings,
+    publication_settings: PublicationSettings,
 ) -> Result<(), Error> {
     info!("creation of indexes");
-    let mut rubber = Rubber::new(connection_string);
+    let mut rubber = Rubber::new(connection_string).with_publication_settings(publication_settings);
     rubber.initialize_templates()?;
 
-    attach_stops_to_admins(stops.iter_mut(), &mut rubber);
+    let admins_geofinder = load_admins(&mut rubber);
+    attach_stops_to_admins(stops.iter_mut(), &admins_geofinder);
 
     for stop in &mut stops {
         stop.coverages.push(dataset.to_string());
-        let mut admin_weight = stop
-            .administrative_regions
-            .iter()
-            .filter(|adm| adm.is_city())
-            .map(|adm| adm.weight)
-            .next()
-            .unwrap_or(0.0);
-        // FIXME: 1024, automagic!
-        // It's a factor used to bring the stop weight and the admin weight in the same order of
-        // magnitude...
-        // We then use a log to compress the distance between low admin weight and high ones.
-        admin_weight = admin_weight * 1024.0 + 1.0;
-        admin_weight = admin_weight.log10();
-        stop.weight = (stop.weight + admin_weight) / 2.0;
+        stop.weight = weight_with_admins(stop.weight, &stop.administrative_regions);
     }
 
     let global_index =
-        update_global_stop_index(&mut rubber, stops.iter(), dataset, &index_settings)?;
+        update_global_stop_index(&mut rubber, stops.iter(), Some(dataset), &index_settings)?;
+    // the global index is checked before the stops of the dataset are published,
+    // so that both are published or none of them
+    rubber
+        .check_publication(
+            mimir::Stop::doc_type(),
+            &global_index,
+            GLOBAL_STOP_INDEX_NAME,
+        )
+        .with_context(|err| {
+            format!(
+                "Publication of {} aborted, the index is kept for inspection: {}",
+                global_index, err
+            )
+        })?;
 
     info!("Importing {} stops into Mimir", stops.len());
     let nb_stops = rubber.public_index(dataset, &index_settings, stops.into_iter())?;
@@ -99,6 +419,38 @@ pub fn import_stops(
     Ok(())
 }
 
+/// Import the stop points in a private index,
+/// they are then only searched when the `public_transport:stop_point` type is asked.
+pub fn import_stop_points(
+    mut stop_points: Vec<mimir::StopPoint>,
+    connection_string: &str,
+    dataset: &str,
+    index_settings: IndexSettings,
+    publication_settings: PublicationSettings,
+) -> Result<(), Error> {
+    let mut rubber = Rubber::new(connection_string).with_publication_settings(publication_settings);
+    rubber.initialize_templates()?;
+
+    let admins_geofinder = load_admins(&mut rubber);
+    for stop_point in &mut stop_points {
+        let admins = admins_geofinder.get(&stop_point.coord);
+        let admins_iter = admins.iter().map(|a| a.deref());
+        let country_codes = utils::find_country_codes(admins_iter.clone());
+
+        stop_point.label = labels::format_stop_label(&stop_point.name, admins_iter, &country_codes);
+        stop_point.zip_codes = utils::get_zip_codes_from_admins(&admins);
+        stop_point.country_codes = country_codes;
+        stop_point.weight = weight_with_admins(stop_point.weight, &admins);
+        stop_point.administrative_regions = admins;
+        stop_point.coverages.push(dataset.to_string());
+    }
+
+    info!("Importing {} stop points into Mimir", stop_points.len());
+    let nb_stop_points = rubber.private_index(dataset, &index_settings, stop_points.into_iter())?;
+    info!("Nb of indexed stop points: {}", nb_stop_points);
+    Ok(())
+}
+
 fn attach_stop(stop: &mut mimir::Stop, admins: Vec<Arc<mimir::Admin>>) {
     let admins_iter = admins.iter().map(|a| a.deref());
     let country_codes = utils::find_country_codes(admins_iter.clone());
@@ -110,15 +462,8 @@ fn attach_stop(stop: &mut mimir::Stop, admins: Vec<Arc<mimir::Admin>>) {
     stop.administrative_regions = admins;
 }
 
-/// Attach the stops to administrative regions
-///
-/// The admins are loaded from Elasticsearch and stored in a quadtree
-/// We attach a stop with all the admins that have a boundary containing
-/// the coordinate of the stop
-fn attach_stops_to_admins<'a, It: Iterator<Item = &'a mut mimir::Stop>>(
-    stops: It,
-    rubber: &mut Rubber,
-) {
+/// Load the admins from Elasticsearch and store them in a quadtree
+fn load_admins(rubber: &mut Rubber) -> AdminGeoFinder {
     let admins = rubber.get_all_admins().unwrap_or_else(|_| {
         warn!("Administratives regions not found in elasticsearch db");
         vec![]
@@ -126,8 +471,17 @@ fn attach_stops_to_admins<'a, It: Iterator<Item = &'a mut mimir::Stop>>(
 
     info!("{} administrative regions loaded from mimir", admins.len());
 
-    let admins_geofinder = admins.into_iter().collect::<AdminGeoFinder>();
+    admins.into_iter().collect()
+}
 
+/// Attach the stops to administrative regions
+///
+/// We attach a stop with all the admins that have a boundary containing
+/// the coordinate of the stop
+fn attach_stops_to_admins<'a, It: Iterator<Item = &'a mut mimir::Stop>>(
+    stops: It,
+    admins_geofinder: &AdminGeoFinder,
+) {
     let mut nb_unmatched = 0u32;
     let mut nb_matched = 0u32;
     for mut stop in stops {
@@ -173,6 +527,7 @@ fn merge_stops<It: IntoIterator<Item = mimir::Stop>>(
         let commercial_modes = replace(&mut stop.commercial_modes, vec![]);
         let properties = replace(&mut stop.properties, vec![]);
         let feed_publishers = replace(&mut stop.feed_publishers, vec![]);
+        let aliases = replace(&mut stop.aliases, vec![]);
 
         let stop_in_map = stops_by_id.entry(stop.id.clone()).or_insert(stop);
 
@@ -182,6 +537,7 @@ fn merge_stops<It: IntoIterator<Item = mimir::Stop>>(
         merge_collection(&mut stop_in_map.coverages, cov);
         merge_collection(&mut stop_in_map.properties, properties);
         merge_collection(&mut stop_in_map.feed_publishers, feed_publishers);
+        merge_collection(&mut stop_in_map.aliases, aliases);
     }
     stops_by_id.into_iter().map(|(_, v)| v)
 }
@@ -192,17 +548,48 @@ fn get_all_stops(rubber: &mut Rubber, index: String) -> Result<Vec<mimir::Stop>,
         .map_err(|e| format_err!("Getting all stops {}", e.to_string()))
 }
 
+/// Rebuild the global stop index from the published stop indexes, after a stop dataset
+/// has been deleted or rolled back. The new index has the settings of the replaced one.
+pub fn rebuild_global_stop_index(rubber: &mut Rubber) -> Result<(), Error> {
+    let index_settings = rubber
+        .get_index_settings(GLOBAL_STOP_INDEX_NAME)?
+        .unwrap_or(IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 1,
+        });
+    let global_index = update_global_stop_index(
+        rubber,
+        std::iter::empty::<&mimir::Stop>(),
+        None,
+        &index_settings,
+    )?;
+    publish_global_index(rubber, &global_index).context("Error while publishing global index")?;
+    Ok(())
+}
+
+// The stops of the dataset, if given, are replaced by `stops`
 fn update_global_stop_index<'a, It: Iterator<Item = &'a mimir::Stop>>(
     rubber: &mut Rubber,
     stops: It,
-    dataset: &str,
+    dataset: Option<&str>,
     index_settings: &IndexSettings,
 ) -> Result<String, Error> {
-    let dataset_index = mimir::rubber::get_main_type_and_dataset_index::<mimir::Stop>(dataset);
+    let dataset_index = dataset.map(mimir::rubber::get_main_type_and_dataset_index::<mimir::Stop>);
+    let stop_index = mimir::rubber::get_main_type_index::<mimir::Stop>();
+    // the stop points indexes also start with 'munin_stop_', they must not be merged
+    let stop_points_index = mimir::rubber::get_main_type_index::<mimir::StopPoint>();
+    // only the published indexes are merged, not the previous versions
+    // or the indexes rejected by the publication checks
     let stops_indexes = rubber
-        .get_all_aliased_index(&mimir::rubber::get_main_type_index::<mimir::Stop>())?
+        .get_all_aliased_index(&stop_index)?
         .into_iter()
-        .filter(|&(_, ref aliases)| !aliases.contains(&dataset_index))
+        .filter(|&(ref index, _)| !index.starts_with(&stop_points_index))
+        .filter(|&(_, ref aliases)| aliases.contains(&stop_index))
+        .filter(|&(_, ref aliases)| {
+            dataset_index
+                .as_ref()
+                .map_or(true, |dataset_index| !aliases.contains(dataset_index))
+        })
         .map(|(index, _)| index);
 
     let all_es_stops = stops_indexes
@@ -29,7 +29,10 @@
 // www.navitia.io
 
 use crate::Error;
+use mimir::provenance::Provenance;
+use mimir::rubber::DocTypeCheck;
 use slog_scope::error;
+use std::path::Path;
 use std::process::exit;
 use std::sync::Arc;
 use structopt::StructOpt;
@@ -61,6 +64,19 @@ pub fn normalize_admin_weight(admins: &mut [mimir::Admin]) {
     }
 }
 
+/// The house number as used in the id of an address: without spaces,
+/// and with the separators of the ids replaced by dashes
+pub fn normalize_house_number_id(house_number: &str) -> String {
+    house_number
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n'))
+        .map(|c| match c {
+            '/' | '.' | ':' | ';' => '-',
+            c => c,
+        })
+        .collect()
+}
+
 /// normalize the weight for it to be in [0, 1]
 pub fn normalize_weight(weight: f64, max_weight: f64) -> f64 {
     let w = weight / max_weight;
@@ -71,6 +87,66 @@ pub fn normalize_weight(weight: f64, max_weight: f64) -> f64 {
     }
 }
 
+/// Arguments of the checks made on a new index before publishing it, and of the number
+/// of previous indexes kept, to be flattened in the arguments of the import tools.
+/// A check can be given for a doc type, as `<doc_type>=<value>`, or for all the doc types
+/// published by the tool.
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct PublicationArgs {
+    /// Minimum number of documents of a new index. If it has less documents,
+    /// it is not published and the previous index is kept.
+    /// Can be given for several doc types, e.g. `--min-docs street=1000`.
+    #[structopt(long = "min-docs")]
+    pub min_docs: Vec<DocTypeCheck<u64>>,
+    /// Maximum drop of the number of documents of a new index versus the index it replaces,
+    /// 0.2 for 20%. If the drop is bigger, the new index is not published.
+    /// Can be given for several doc types, e.g. `--max-drop poi=0.1`.
+    #[structopt(long = "max-drop")]
+    pub max_drop: Vec<DocTypeCheck<f64>>,
+    /// Query string that must match at least a document of a new index to publish it.
+    /// Can be given several times, and for a doc type, e.g. `--smoke-query street=name:rivoli`.
+    #[structopt(long = "smoke-query")]
+    pub smoke_queries: Vec<DocTypeCheck<String>>,
+    /// Number of previous versions of an index kept after publishing a new one.
+    /// The aliases can then be re-pointed to one of them with `mimir_rollback`.
+    #[structopt(long = "keep-versions", default_value = "0")]
+    pub keep_versions: usize,
+    /// Compute the sha1 of the input files, given in the provenance of the indexes.
+    /// The inputs are then read once more, which takes a while for the big files.
+    #[structopt(long = "checksum-inputs")]
+    pub checksum_inputs: bool,
+}
+
+impl From<PublicationArgs> for mimir::rubber::PublicationSettings {
+    fn from(args: PublicationArgs) -> Self {
+        mimir::rubber::PublicationSettings {
+            min_docs: args.min_docs,
+            max_drop: args.max_drop,
+            smoke_queries: args.smoke_queries,
+            keep_versions: args.keep_versions,
+            provenance: None,
+        }
+    }
+}
+
+impl PublicationArgs {
+    /// The publication settings of the indexes of an import tool, with their provenance
+    /// built from the command line and the input files
+    pub fn settings<P: AsRef<Path>>(
+        &self,
+        inputs: impl IntoIterator<Item = P>,
+    ) -> Result<mimir::rubber::PublicationSettings, Error> {
+        let mut provenance = Provenance::from_command_line(env!("CARGO_PKG_VERSION"));
+        for input in inputs {
+            provenance = provenance.with_input(input.as_ref(), self.checksum_inputs)?;
+        }
+        Ok(mimir::rubber::PublicationSettings {
+            provenance: Some(provenance),
+            ..self.clone().into()
+        })
+    }
+}
+
 pub fn wrapped_launch_run<O, F>(run: F) -> Result<(), Error>
 where
     F: FnOnce(O) -> Result<(), Error>,
@@ -109,3 +185,64 @@ pub fn get_country_code(codes: &[mimir::Code]) -> Option<String> {
 pub fn find_country_codes<'a>(admins: impl Iterator<Item = &'a mimir::Admin>) -> Vec<String> {
     admins.filter_map(|a| get_country_code(&a.codes)).collect()
 }
+
+/// Tags holding the alternative names of a place (cf https://wiki.openstreetmap.org/wiki/Key:name)
+pub const ALIAS_KEYS: [&str; 5] = [
+    "alt_name",
+    "old_name",
+    "short_name",
+    "official_name",
+    "loc_name",
+];
+
+/// Collect the alternative names of a place from its tags (or properties).
+/// Several values can be given in a tag, separated by `;`.
+/// The aliases equal to the name of the place are skipped.
+pub fn get_aliases<'a>(
+    name: &str,
+    tags: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Vec<String> {
+    let mut aliases: Vec<String> = Vec::new();
+    for (key, value) in tags {
+        if !ALIAS_KEYS.contains(&key) {
+            continue;
+        }
+        for alias in value.split(';').map(str::trim) {
+            if !alias.is_empty() && alias != name && !aliases.iter().any(|a| a == alias) {
+                aliases.push(alias.to_string());
+            }
+        }
+    }
+    aliases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_house_number_id() {
+        assert_eq!(normalize_house_number_id(" 12 bis"), "12bis");
+        assert_eq!(normalize_house_number_id("1/2.3:4;5\t\r\n"), "1-2-3-4-5");
+    }
+
+    #[test]
+    fn aliases_from_tags() {
+        let tags = vec![
+            ("name", "Gare Montparnasse"),
+            ("alt_name", "Paris Montparnasse;Montparnasse-Bienvenüe"),
+            ("official_name", "Gare Montparnasse"),
+            ("old_name", " Montparnasse-Bienvenüe "),
+            ("short_name", "Montparnasse"),
+            ("name:fr", "Gare Montparnasse"),
+        ];
+        assert_eq!(
+            get_aliases("Gare Montparnasse", tags),
+            vec![
+                "Paris Montparnasse".to_string(),
+                "Montparnasse-Bienvenüe".to_string(),
+                "Montparnasse".to_string(),
+            ]
+        );
+    }
+}
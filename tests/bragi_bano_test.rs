@@ -61,10 +61,34 @@ pub fn bragi_bano_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
 }
 
 fn status_test(bragi: &mut BragiHandler) {
-    assert_eq!(
-        bragi.get_json("/status").pointer("/status"),
-        Some(&json!("good"))
-    );
+    // the provenances are cached, and fetched in the background by the first request
+    let indexes = (0..50)
+        .find_map(|_| {
+            let status = bragi.get_json("/status");
+            assert_eq!(status.pointer("/status"), Some(&json!("good")));
+            let indexes = status
+                .pointer("/indexes")
+                .and_then(|i| i.as_object())
+                .filter(|i| !i.is_empty())
+                .cloned();
+            if indexes.is_none() {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            indexes
+        })
+        .unwrap();
+
+    // the provenance of the addresses is given by the import tool
+    let (_, provenance) = indexes
+        .iter()
+        .find(|(index, _)| index.starts_with("munin_addr_fr_"))
+        .unwrap();
+    assert_eq!(provenance.pointer("/tool"), Some(&json!("bano2mimir")));
+    assert_eq!(provenance.pointer("/dataset"), Some(&json!("fr")));
+    assert_eq!(provenance.pointer("/nb_docs"), Some(&json!(35)));
+    // the arguments and the inputs are only given by mimir_admin
+    assert_eq!(provenance.pointer("/args"), None);
+    assert_eq!(provenance.pointer("/inputs"), None);
 }
 
 fn simple_bano_autocomplete_test(bragi: &mut BragiHandler) {
@@ -43,12 +43,14 @@ pub fn bragi_ntfs_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
         &[
             "--input=./tests/fixtures/ntfs/".into(),
             "--dataset=dataset1".into(),
+            "--stop-points".into(),
             format!("--connection-string={}", es_wrapper.host()),
         ],
         &es_wrapper,
     );
 
     gare_de_lyon(&mut bragi);
+    gare_de_lyon_stop_points(&mut bragi);
 
     let ntfs2mimir = out_dir.join("../../../ntfs2mimir").display().to_string();
     crate::launch_and_assert(
@@ -172,6 +174,42 @@ fn gare_de_lyon(bragi: &mut BragiHandler) {
     );
 }
 
+fn gare_de_lyon_stop_points(bragi: &mut BragiHandler) {
+    // the stop points are only searched when their type is asked
+    let response = bragi.get("/autocomplete?q=gare de lyon&pt_dataset[]=dataset1");
+    assert!(response
+        .iter()
+        .all(|place| get_value(place, "type") != "public_transport:stop_point"));
+
+    let response = bragi.get(
+        "/autocomplete?q=gare de lyon rer&pt_dataset[]=dataset1&type[]=public_transport:stop_point",
+    );
+    let stop_point = response
+        .iter()
+        .find(|place| get_value(place, "id") == "stop_point:GDLR")
+        .unwrap();
+    assert_eq!(get_value(stop_point, "type"), "public_transport:stop_point");
+    assert_eq!(get_value(stop_point, "name"), "Gare de Lyon (RER)");
+    assert_eq!(
+        stop_point.get("parent").unwrap(),
+        &json!({"id": "stop_area:GDL", "name": "Gare de Lyon"})
+    );
+
+    // a stop point returned by the autocomplete can be looked up by its id
+    for query in &[
+        "/features/stop_point:GDLR?pt_dataset[]=dataset1",
+        "/features/stop_point:GDLR?_all_data=true",
+    ] {
+        let features = bragi.get(query);
+        assert_eq!(features.len(), 1, "{}", query);
+        assert_eq!(get_value(&features[0], "id"), "stop_point:GDLR");
+        assert_eq!(
+            features[0].get("parent").unwrap(),
+            &json!({"id": "stop_area:GDL", "name": "Gare de Lyon"})
+        );
+    }
+}
+
 fn gare_de_lyon_with_two_datasets(bragi: &mut BragiHandler) {
     // with this query we should find only one response, a stop
     let response =
@@ -30,7 +30,7 @@
 
 use cosmogony::ZoneType;
 use geo::prelude::BoundingRect;
-use mimir::rubber::{self, IndexSettings, Rubber};
+use mimir::rubber::{self, IndexSettings, PublicationSettings, Rubber};
 use mimir::{Admin, Coord, MimirObject, Street};
 use serde_json::{json, Value};
 
@@ -323,3 +323,109 @@ pub fn rubber_empty_bulk(mut es: crate::ElasticSearchWrapper<'_>) {
     assert!(result.is_ok());
     assert_eq!(result.unwrap(), 0); // we have indexed nothing, but it's ok
 }
+
+/// test that the versions kept to roll back are not searched, and that a rollback
+/// moves all the aliases to a previously published version
+pub fn rubber_keep_and_rollback(es: crate::ElasticSearchWrapper<'_>) {
+    info!("running rubber_keep_and_rollback");
+    let mut rubber =
+        Rubber::new(&es.docker_wrapper.host()).with_publication_settings(PublicationSettings {
+            keep_versions: 1,
+            ..Default::default()
+        });
+    let index_settings = IndexSettings {
+        nb_shards: 1,
+        nb_replicas: 0,
+    };
+    let street = |id: &str| {
+        let coord = Coord::new(2.35, 48.85);
+        Street {
+            id: id.to_string(),
+            name: format!("{}'s street", id),
+            label: format!("{}'s street", id),
+            coord,
+            approx_coord: Some(coord.into()),
+            ..Default::default()
+        }
+    };
+    let search_ids = |index: &str| -> Vec<String> {
+        es.refresh();
+        es.search_on_index(index, "*:*")
+            .pointer("/hits/hits")
+            .and_then(Value::as_array)
+            .unwrap()
+            .iter()
+            .map(|hit| hit["_source"]["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    for id in &["bob", "bobette"] {
+        let nb = rubber
+            .public_index("fr", &index_settings, std::iter::once(street(id)))
+            .unwrap();
+        assert_eq!(nb, 1);
+    }
+    // the previous version is kept, but only the new one is searched
+    for index in &["munin", "munin_geo_data", "munin_street", "munin_street_fr"] {
+        assert_eq!(search_ids(index), vec!["bobette"]);
+    }
+    let versions = rubber.get_index_versions("street", "fr").unwrap();
+    assert_eq!(versions.len(), 2);
+    assert!(versions[0].is_published);
+    assert!(!versions[1].is_published && versions[1].was_published);
+    assert!(versions[1].aliases.is_empty());
+
+    // a version that has never been published can not be rolled back to
+    let unpublished = rubber.make_index::<Street>("fr", &index_settings).unwrap();
+    let unpublished_name = rubber.get_index_versions("street", "fr").unwrap()[0]
+        .name
+        .clone();
+    assert!(rubber
+        .rollback_index("street", "fr", Some(&unpublished_name))
+        .is_err());
+    rubber
+        .bulk_index(&unpublished, std::iter::once(street("ghost")))
+        .unwrap();
+
+    // the rollback moves all the aliases
+    let published = rubber.rollback_index("street", "fr", None).unwrap();
+    assert_eq!(published, versions[1].name);
+    for index in &["munin", "munin_geo_data", "munin_street", "munin_street_fr"] {
+        assert_eq!(search_ids(index), vec!["bob"]);
+    }
+
+    // at the next publication, the unpublished version older than the new one is deleted,
+    // and only the most recent previously published version is kept
+    rubber
+        .public_index("fr", &index_settings, std::iter::once(street("bobby")))
+        .unwrap();
+    assert_eq!(search_ids("munin"), vec!["bobby"]);
+    let remaining: Vec<_> = rubber
+        .get_index_versions("street", "fr")
+        .unwrap()
+        .into_iter()
+        .skip(1)
+        .map(|v| v.name)
+        .collect();
+    assert_eq!(remaining, vec![versions[0].name.clone()]);
+
+    // a version published without the publication marker (by a previous release)
+    // is kept when it is replaced
+    let legacy = rubber.make_index::<Street>("be", &index_settings).unwrap();
+    rubber
+        .bulk_index(&legacy, std::iter::once(street("bart")))
+        .unwrap();
+    let legacy_name = rubber.get_index_versions("street", "be").unwrap()[0]
+        .name
+        .clone();
+    rubber
+        .alias("munin_street_be", &[legacy_name.clone()], &[])
+        .unwrap();
+    rubber
+        .public_index("be", &index_settings, std::iter::once(street("bert")))
+        .unwrap();
+    let versions = rubber.get_index_versions("street", "be").unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[1].name, legacy_name);
+    assert!(!versions[1].is_published && versions[1].was_published);
+}
@@ -150,6 +150,7 @@ fn all_tests() {
     rubber_test::rubber_custom_id(ElasticSearchWrapper::new(&docker_wrapper));
     rubber_test::rubber_ghost_index_cleanup(ElasticSearchWrapper::new(&docker_wrapper));
     rubber_test::rubber_empty_bulk(ElasticSearchWrapper::new(&docker_wrapper));
+    rubber_test::rubber_keep_and_rollback(ElasticSearchWrapper::new(&docker_wrapper));
     bragi_bano_test::bragi_bano_test(ElasticSearchWrapper::new(&docker_wrapper));
     bragi_osm_test::bragi_osm_test(ElasticSearchWrapper::new(&docker_wrapper));
     bragi_poi_test::test_i18n_poi(ElasticSearchWrapper::new(&docker_wrapper));